@@ -0,0 +1,321 @@
+//! Analysis mode: the position on the board is searched continuously and
+//! shown as an evaluation bar, the best few lines and arrows on the board.
+
+use std::sync::Arc;
+
+use chess_wasm::endgame::{self, Outcome};
+use chess_wasm::search::{is_tablebase_score, mate_distance, PvLine, SearchLimits, SearchResult, Searcher, MATE};
+use chess_wasm::syzygy::{Tablebase, Wdl};
+use chess_wasm::{ChessColor, Grid, Move};
+use macroquad::miniquad::date;
+use macroquad::prelude::*;
+
+use crate::layout::PANEL_TEXT;
+
+/// Lines shown, best first.
+pub const LINES: usize = 3;
+/// Seconds of each frame given to a search.
+const FRAME_BUDGET: f64 = 0.010;
+const MIN_NODES_PER_FRAME: u64 = 1_000;
+const MAX_NODES_PER_FRAME: u64 = 200_000;
+const MAX_DEPTH: u32 = 32;
+const ANALYSIS_TT_MB: usize = 16;
+/// Centipawns at which the bar is about three quarters filled.
+const BAR_SCALE: f32 = 250.0;
+
+const ARROW_COLORS: [Color; LINES] = [
+    Color::new(0.15, 0.65, 0.25, 0.85),
+    Color::new(0.15, 0.45, 0.75, 0.6),
+    Color::new(0.15, 0.45, 0.75, 0.4),
+];
+
+pub struct Analysis {
+    pub enabled: bool,
+    /// Lines are filled in by an external engine instead of the search.
+    external: bool,
+    searcher: Searcher,
+    /// The position analysed, whose side to move the scores belong to.
+    grid: Grid,
+    depth: u32,
+    lines: Vec<PvLine>,
+    slice: SearchSlice,
+    tablebase: Option<Arc<Tablebase>>,
+    /// What the solved endgames or the tablebases say about the position
+    /// they were last asked about, when they cover it.
+    tablebase_result: Option<String>,
+    tablebase_position: Option<u64>,
+}
+
+impl Default for Analysis {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Analysis {
+    pub fn new() -> Self {
+        Analysis {
+            enabled: false,
+            external: false,
+            searcher: Searcher::new(ANALYSIS_TT_MB),
+            grid: Grid::new(),
+            depth: 0,
+            lines: Vec::new(),
+            slice: SearchSlice::new(),
+            tablebase: None,
+            tablebase_result: None,
+            tablebase_position: None,
+        }
+    }
+
+    pub fn set_tablebase(&mut self, tablebase: Option<Arc<Tablebase>>) {
+        self.searcher.set_tablebase(tablebase.clone());
+        self.tablebase = tablebase;
+        self.tablebase_position = None;
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+        self.depth = 0;
+        self.lines.clear();
+    }
+
+    pub fn set_external(&mut self, external: bool) {
+        self.external = external;
+        self.depth = 0;
+        self.lines.clear();
+    }
+
+    /// Follows the board, reached through the positions in `history`, and,
+    /// unless an external engine does the work, searches one more slice.
+    pub fn update(&mut self, grid: &Grid, history: &[u64]) {
+        if !self.enabled {
+            return;
+        }
+        if grid.position_hash() != self.grid.position_hash() {
+            self.grid = grid.clone();
+            self.depth = 0;
+            self.lines.clear();
+        }
+        if self.tablebase_position != Some(grid.position_hash()) {
+            self.tablebase_position = Some(grid.position_hash());
+            self.tablebase_result = endgame_result(grid)
+                .or_else(|| self.tablebase.as_ref().and_then(|tablebase| tablebase_result(tablebase, grid)));
+        }
+        let mated = self.lines.first().is_some_and(|line| mate_distance(line.score).is_some());
+        if self.external || self.depth >= MAX_DEPTH || mated {
+            return;
+        }
+        let limits = SearchLimits {
+            depth: Some(self.depth + 1),
+            multipv: LINES,
+            ..Default::default()
+        };
+        self.searcher.set_history(history);
+        let result = self.slice.search(&mut self.searcher, &mut self.grid, limits);
+        if result.depth > self.depth {
+            self.depth = result.depth;
+            self.lines = result.lines;
+        }
+    }
+
+    /// Takes line `index` of an external engine's analysis of `grid`.
+    pub fn set_line(&mut self, grid: &Grid, index: usize, depth: u32, line: PvLine) {
+        if !self.enabled || index >= LINES || grid.position_hash() != self.grid.position_hash() {
+            return;
+        }
+        if depth != self.depth {
+            self.depth = depth;
+            self.lines.truncate(index);
+        }
+        if index <= self.lines.len() {
+            self.lines.truncate(index);
+            self.lines.push(line);
+        }
+    }
+
+    fn white_score(&self, score: i32) -> i32 {
+        match self.grid.side_to_move() {
+            ChessColor::White => score,
+            ChessColor::Black => -score,
+        }
+    }
+
+    pub fn draw_eval_bar(&self, rect: Rect) {
+        if !self.enabled {
+            return;
+        }
+        let Some(line) = self.lines.first() else {
+            draw_rectangle(rect.x, rect.y, rect.w, rect.h, GRAY);
+            return;
+        };
+        let score = self.white_score(line.score);
+        let white_share = match mate_distance(score) {
+            Some(moves) if moves > 0 => 1.0,
+            Some(_) => 0.0,
+            None => 1.0 / (1.0 + (-(score as f32) / BAR_SCALE).exp()),
+        };
+        let white_height = rect.h * white_share;
+        draw_rectangle(rect.x, rect.y, rect.w, rect.h - white_height, BLACK);
+        draw_rectangle(rect.x, rect.bottom() - white_height, rect.w, white_height, WHITE);
+        draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 1.0, GRAY);
+
+        let text = short_score(score);
+        let size = measure_text(&text, None, 12, 1.0);
+        let x = rect.x + (rect.w - size.width) / 2.0;
+        if score >= 0 {
+            draw_text(&text, x, rect.bottom() - 4.0, 12.0, BLACK);
+        } else {
+            draw_text(&text, x, rect.y + 12.0, 12.0, WHITE);
+        }
+    }
+
+    /// Depth and the best lines in SAN, inside an engine panel's content area.
+    pub fn draw_lines(&self, panel: Rect) {
+        let line_height = 18.0;
+        let header = if self.lines.is_empty() {
+            "Analysing...".to_string()
+        } else {
+            format!("Depth {}", self.depth)
+        };
+        draw_text(&header, panel.x, panel.y + line_height, 18.0, PANEL_TEXT);
+        for (i, line) in self.lines.iter().enumerate() {
+            let mut words: Vec<String> = vec![score_text(self.white_score(line.score))];
+            words.extend(self.grid.san_line(&line.moves).split(' ').map(str::to_string));
+            let mut text = words.join(" ");
+            while words.len() > 2 && measure_text(&text, None, 16, 1.0).width > panel.w {
+                words.pop();
+                text = words.join(" ");
+            }
+            let y = panel.y + line_height * (i + 2) as f32;
+            draw_text(&text, panel.x, y, 16.0, PANEL_TEXT);
+        }
+        if let Some(result) = &self.tablebase_result {
+            let y = panel.y + line_height * (LINES + 2) as f32;
+            draw_text(result, panel.x, y, 16.0, SKYBLUE);
+        }
+    }
+
+    /// Arrows for the first move of each line, the best one on top.
+    pub fn draw_arrows(&self, cell_size: f32, offset_x: f32, offset_y: f32) {
+        if !self.enabled {
+            return;
+        }
+        for (i, line) in self.lines.iter().enumerate().rev() {
+            if let Some(&mv) = line.moves.first() {
+                let thickness = if i == 0 { 0.18 } else { 0.12 } * cell_size;
+                draw_arrow(mv, ARROW_COLORS[i], thickness, cell_size, offset_x, offset_y);
+            }
+        }
+    }
+}
+
+/// Searches a frame's worth of nodes at a time. The search runs on the main
+/// thread, the only one there is in the browser, so it works in small slices
+/// and relies on the transposition table to pick up where it left off.
+pub struct SearchSlice {
+    /// Adjusted so a slice takes about `FRAME_BUDGET`.
+    nodes: u64,
+}
+
+impl SearchSlice {
+    pub fn new() -> Self {
+        SearchSlice {
+            nodes: MIN_NODES_PER_FRAME,
+        }
+    }
+
+    /// One slice of a search with `limits`, whose node limit it sets.
+    pub fn search(&mut self, searcher: &mut Searcher, grid: &mut Grid, limits: SearchLimits) -> SearchResult {
+        let limits = SearchLimits {
+            nodes: Some(self.nodes),
+            ..limits
+        };
+        let start = date::now();
+        let result = searcher.search_with(grid, limits, |_| {});
+        let elapsed = date::now() - start;
+        if elapsed < FRAME_BUDGET / 2.0 {
+            self.nodes = (self.nodes * 2).min(MAX_NODES_PER_FRAME);
+        } else if elapsed > FRAME_BUDGET {
+            self.nodes = (self.nodes / 2).max(MIN_NODES_PER_FRAME);
+        }
+        result
+    }
+}
+
+/// The result of a solved endgame for the side to move, in moves to mate.
+fn endgame_result(grid: &Grid) -> Option<String> {
+    let outcome = endgame::probe(grid)?;
+    let text = match (outcome, outcome.moves_to_mate()) {
+        (Outcome::Loss(0), _) => "Checkmated".to_string(),
+        (Outcome::Win(_), Some(moves)) => format!("Mate in {}", moves),
+        (Outcome::Loss(_), Some(moves)) => format!("Mated in {}", moves),
+        _ => "Draw".to_string(),
+    };
+    Some(format!("Solved: {}", text))
+}
+
+/// The tablebase result for the side to move, in moves to the next capture,
+/// pawn move or mate when winning.
+fn tablebase_result(tablebase: &Tablebase, grid: &Grid) -> Option<String> {
+    let text = match tablebase.probe_wdl(grid)? {
+        Wdl::Win => match tablebase.probe_dtz(grid) {
+            Some(dtz) if dtz > 0 => format!("Win in {}", (dtz + 1) / 2),
+            _ => "Win".to_string(),
+        },
+        Wdl::Loss => "Loss".to_string(),
+        Wdl::CursedWin | Wdl::Draw | Wdl::BlessedLoss => "Draw".to_string(),
+    };
+    Some(format!("Tablebase: {}", text))
+}
+
+/// Pawns with two decimals, or moves to mate, from White's point of view.
+pub fn score_text(score: i32) -> String {
+    match mate_distance(score) {
+        Some(moves) => format!("#{}", moves),
+        None if is_tablebase_score(score) => if score > 0 { "+TB" } else { "-TB" }.to_string(),
+        None => format!("{:+.2}", score as f32 / 100.0),
+    }
+}
+
+/// Fits the narrow evaluation bar.
+fn short_score(score: i32) -> String {
+    match mate_distance(score) {
+        Some(moves) => format!("M{}", moves.abs()),
+        None if is_tablebase_score(score) => "TB".to_string(),
+        None => format!("{:.1}", (score as f32 / 100.0).abs()),
+    }
+}
+
+fn square_center(square: [usize; 2], cell_size: f32, offset_x: f32, offset_y: f32) -> Vec2 {
+    Vec2::new(
+        offset_x + cell_size * (square[0] as f32 + 0.5),
+        offset_y + cell_size * (7.5 - square[1] as f32),
+    )
+}
+
+pub fn draw_arrow(mv: Move, color: Color, thickness: f32, cell_size: f32, offset_x: f32, offset_y: f32) {
+    let from = square_center(mv.from, cell_size, offset_x, offset_y);
+    let to = square_center(mv.to, cell_size, offset_x, offset_y);
+    let direction = (to - from).normalize_or_zero();
+    let normal = direction.perp();
+    let head_length = thickness * 2.5;
+    let head_base = to - direction * head_length;
+    draw_line(from.x, from.y, head_base.x, head_base.y, thickness, color);
+    draw_triangle(
+        to,
+        head_base + normal * thickness * 1.5,
+        head_base - normal * thickness * 1.5,
+        color,
+    );
+}
+
+/// Encodes an external engine's `mate N` like the search's own mate scores.
+pub fn mate_score(moves: i32) -> i32 {
+    let plies = moves.abs() * 2 - 1;
+    if moves > 0 {
+        MATE - plies
+    } else {
+        -MATE + plies + 1
+    }
+}
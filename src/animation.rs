@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+
+use macroquad::prelude::*;
+
+use chess_wasm::Piece;
+
+const DEFAULT_DURATION: f32 = 0.18;
+
+#[derive(Clone, Copy, Debug)]
+enum AnimationKind {
+    Slide { from: [usize; 2] },
+    FadeOut,
+    FadeIn,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct PieceAnimation {
+    piece: Piece,
+    square: [usize; 2],
+    kind: AnimationKind,
+}
+
+/// Purely visual tweens layered over the board.
+///
+/// The game state is always updated before an animation starts, so nothing
+/// here is consulted by the rules; `App::draw` only asks which squares to
+/// leave empty while the tweens are drawn on top.
+pub struct Animations {
+    items: Vec<PieceAnimation>,
+    elapsed: f32,
+    duration: f32,
+}
+
+impl Default for Animations {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Animations {
+    pub fn new() -> Self {
+        Animations {
+            items: Vec::new(),
+            elapsed: 0.0,
+            duration: DEFAULT_DURATION,
+        }
+    }
+
+    /// Drops whatever was playing and starts a fresh batch, so a new move
+    /// never waits on the previous one.
+    pub fn restart(&mut self) {
+        self.items.clear();
+        self.elapsed = 0.0;
+    }
+
+    pub fn slide(&mut self, piece: Piece, from: [usize; 2], to: [usize; 2]) {
+        self.items.push(PieceAnimation {
+            piece,
+            square: to,
+            kind: AnimationKind::Slide { from },
+        });
+    }
+
+    pub fn fade_out(&mut self, piece: Piece, square: [usize; 2]) {
+        self.items.push(PieceAnimation {
+            piece,
+            square,
+            kind: AnimationKind::FadeOut,
+        });
+    }
+
+    pub fn fade_in(&mut self, piece: Piece, square: [usize; 2]) {
+        self.items.push(PieceAnimation {
+            piece,
+            square,
+            kind: AnimationKind::FadeIn,
+        });
+    }
+
+    pub fn update(&mut self, frame_time: f32) {
+        if self.items.is_empty() {
+            return;
+        }
+        self.elapsed += frame_time;
+        if self.elapsed >= self.duration {
+            self.skip();
+        }
+    }
+
+    pub fn skip(&mut self) {
+        self.items.clear();
+        self.elapsed = 0.0;
+    }
+
+    /// Whether the static piece on `square` should be left out this frame
+    /// because an animation is drawing it instead.
+    pub fn hides(&self, square: [usize; 2]) -> bool {
+        self.items.iter().any(|item| {
+            item.square == square && !matches!(item.kind, AnimationKind::FadeOut)
+        })
+    }
+
+    pub fn draw(
+        &self,
+        textures: &HashMap<Piece, Texture2D>,
+        cell_size: f32,
+        offset_x: f32,
+        offset_y: f32,
+    ) {
+        let t = (self.elapsed / self.duration).clamp(0.0, 1.0);
+        let eased = t * t * (3.0 - 2.0 * t);
+        let to_screen = |square: [usize; 2]| {
+            vec2(
+                offset_x + cell_size * square[0] as f32,
+                offset_y + cell_size * (7 - square[1]) as f32,
+            )
+        };
+
+        // Fades first so sliding pieces pass over the piece they capture.
+        let mut ordered: Vec<&PieceAnimation> = self.items.iter().collect();
+        ordered.sort_by_key(|item| matches!(item.kind, AnimationKind::Slide { .. }));
+
+        for item in ordered {
+            let Some(texture) = textures.get(&item.piece) else {
+                continue;
+            };
+            let (position, alpha) = match item.kind {
+                AnimationKind::Slide { from } => {
+                    (to_screen(from).lerp(to_screen(item.square), eased), 1.0)
+                }
+                AnimationKind::FadeOut => (to_screen(item.square), 1.0 - eased),
+                AnimationKind::FadeIn => (to_screen(item.square), eased),
+            };
+            draw_texture_ex(
+                texture,
+                position.x,
+                position.y,
+                Color::new(1.0, 1.0, 1.0, alpha),
+                DrawTextureParams {
+                    dest_size: Some(Vec2::new(cell_size, cell_size)),
+                    ..Default::default()
+                },
+            );
+        }
+    }
+}
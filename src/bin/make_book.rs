@@ -0,0 +1,52 @@
+//! Builds a Polyglot opening book from lines of UCI moves, one line per
+//! opening. Each move of a line adds one to its weight, so moves shared by
+//! several lines are chosen more often.
+//!
+//!     make_book assets/book.txt assets/book.bin
+
+use std::process::ExitCode;
+
+use chess_wasm::book::{Book, BookEntry};
+use chess_wasm::Grid;
+
+fn build(text: &str) -> Result<Book, String> {
+    let mut entries = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut grid = Grid::new();
+        for token in line.split_whitespace() {
+            let mv = grid
+                .parse_uci_move(token)
+                .ok_or_else(|| format!("line {}: illegal move {}", number + 1, token))?;
+            entries.push(BookEntry::new(&grid, mv, 1));
+            grid.make_move(mv);
+        }
+    }
+    Ok(Book::from_entries(entries))
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [input, output] = args.as_slice() else {
+        eprintln!("usage: make_book <lines.txt> <book.bin>");
+        return ExitCode::FAILURE;
+    };
+    let result = std::fs::read_to_string(input)
+        .map_err(|error| format!("cannot read {}: {}", input, error))
+        .and_then(|text| build(&text))
+        .and_then(|book| {
+            std::fs::write(output, book.to_bytes())
+                .map_err(|error| format!("cannot write {}: {}", output, error))
+                .map(|_| book.len())
+        });
+    match result {
+        Ok(entries) => {
+            println!("{} entries written to {}", entries, output);
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("{}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
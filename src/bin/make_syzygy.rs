@@ -0,0 +1,172 @@
+//! Writes Syzygy tables for the endgames solved in memory, KQvK, KRvK and
+//! KPvK, and the drawn KBvK and KNvK a pawn can promote into, as the
+//! tablebase tests read them. DTZ is worked out outwards from the captures,
+//! pawn moves and mates, and stored in plies.
+//!
+//!     make_syzygy tests/syzygy
+
+use std::path::Path;
+use std::process::ExitCode;
+
+use chess_wasm::endgame::{self, Ending, Outcome};
+use chess_wasm::syzygy::{TableWriter, Wdl};
+use chess_wasm::{ChessColor, Grid, Piece, TypePiece};
+
+/// Side to move, White's king, Black's king and the piece.
+const POSITIONS: usize = 2 * 64 * 64 * 64;
+
+fn fen_square(board: &mut [[char; 8]; 8], square: usize, piece: char) {
+    board[7 - square / 8][square % 8] = piece;
+}
+
+/// The legal position at `index`, with White's king and `piece` against
+/// Black's king.
+fn position(index: usize, piece: char) -> Option<Grid> {
+    let black_to_move = index >= POSITIONS / 2;
+    let (white_king, black_king, square) = (index / 4096 % 64, index / 64 % 64, index % 64);
+    if white_king == black_king || white_king == square || black_king == square {
+        return None;
+    }
+    if piece == 'P' && !(8..56).contains(&square) {
+        return None;
+    }
+    let mut board = [['1'; 8]; 8];
+    fen_square(&mut board, white_king, 'K');
+    fen_square(&mut board, black_king, 'k');
+    fen_square(&mut board, square, piece);
+    let rows: Vec<String> = board.iter().map(|row| row.iter().collect()).collect();
+    let side = if black_to_move { "b" } else { "w" };
+    let grid = Grid::from_fen(&format!("{} {} - - 0 1", rows.join("/"), side)).ok()?;
+    // The side that just moved can't be left in check.
+    let waiting = grid.side_to_move().opposite();
+    (!grid.is_square_attacked(grid.find_king_position(waiting), waiting)).then_some(grid)
+}
+
+/// Index of a position with the same material as `position` gives.
+fn index(grid: &Grid) -> usize {
+    let mut index = if grid.side_to_move() == ChessColor::Black { POSITIONS / 2 } else { 0 };
+    for square in 0..64 {
+        index += match grid.find_cell([square % 8, square / 8]).piece {
+            Some(Piece { type_of_piece: TypePiece::King, color: ChessColor::White }) => square * 4096,
+            Some(Piece { type_of_piece: TypePiece::King, color: ChessColor::Black }) => square * 64,
+            Some(_) => square,
+            None => 0,
+        };
+    }
+    index
+}
+
+/// Result for the side to move; captures and minor piece promotions leave
+/// a draw.
+fn wdl(grid: &Grid) -> Wdl {
+    match endgame::probe(grid) {
+        Some(Outcome::Win(_)) => Wdl::Win,
+        Some(Outcome::Loss(_)) => Wdl::Loss,
+        _ => Wdl::Draw,
+    }
+}
+
+/// DTZ of every position: a win is one ply more than its shortest way into
+/// a loss, a loss one more than its longest way into a win, counting from
+/// the moves that capture, move a pawn or mate.
+fn distances(grids: &[Option<Grid>], results: &[Wdl]) -> Result<Vec<i32>, String> {
+    let mut dtz = vec![0; POSITIONS];
+    // Moves that keep the count going, by the position they lead to.
+    let mut next: Vec<Vec<usize>> = vec![Vec::new(); POSITIONS];
+    for (index, grid) in grids.iter().enumerate() {
+        let Some(grid) = grid else { continue };
+        let result = results[index];
+        if result == Wdl::Draw {
+            continue;
+        }
+        let mut grid = grid.clone();
+        let mut zeroing = false;
+        for mv in grid.legal_moves() {
+            let undo = grid.make_move(mv);
+            let mates = grid.is_in_check() && grid.legal_moves().is_empty();
+            let keeps = -wdl(&grid) == result;
+            if keeps && (grid.halfmove_clock() == 0 || mates) {
+                zeroing = true;
+            } else if keeps {
+                next[index].push(self::index(&grid));
+            }
+            grid.unmake_move(mv, undo);
+        }
+        if result == Wdl::Win && zeroing {
+            dtz[index] = 1;
+            next[index].clear();
+        } else if next[index].is_empty() {
+            // Mated, or every move captures.
+            dtz[index] = if result == Wdl::Win { 1 } else { -1 };
+        }
+    }
+
+    let mut quiet = 0;
+    for ply in 2.. {
+        let mut changed = false;
+        for index in 0..POSITIONS {
+            if dtz[index] != 0 || next[index].is_empty() {
+                continue;
+            }
+            let done = match results[index] {
+                Wdl::Win => next[index].iter().any(|&child| dtz[child] == 1 - ply),
+                _ => next[index].iter().all(|&child| (1..ply).contains(&dtz[child])),
+            };
+            if done {
+                dtz[index] = if results[index] == Wdl::Win { ply } else { -ply };
+                changed = true;
+            }
+        }
+        // Wins and losses take turns, so stop after two plies of neither.
+        quiet = if changed { 0 } else { quiet + 1 };
+        if quiet == 2 {
+            break;
+        }
+    }
+    match (0..POSITIONS).find(|&index| results[index] != Wdl::Draw && dtz[index] == 0) {
+        Some(index) => Err(format!("no DTZ for {}", grids[index].as_ref().map_or(String::new(), Grid::to_fen))),
+        None => Ok(dtz),
+    }
+}
+
+/// Writes the `.rtbw` and `.rtbz` files of king and `piece` against king
+/// to `directory`, and returns their sizes.
+fn write(piece: char, directory: &Path) -> Result<(String, usize, usize), String> {
+    let name = format!("K{}vK", piece);
+    let grids: Vec<Option<Grid>> = (0..POSITIONS).map(|index| position(index, piece)).collect();
+    let results: Vec<Wdl> = grids.iter().map(|grid| grid.as_ref().map_or(Wdl::Draw, wdl)).collect();
+    let dtz = distances(&grids, &results)?;
+    let mut writer = TableWriter::new(&name)?;
+    for (index, grid) in grids.iter().enumerate() {
+        if let Some(grid) = grid {
+            writer.add(grid, results[index], dtz[index])?;
+        }
+    }
+    let (wdl, dtz) = writer.finish()?;
+    for (bytes, extension) in [(&wdl, "rtbw"), (&dtz, "rtbz")] {
+        let path = directory.join(format!("{}.{}", name, extension));
+        std::fs::write(&path, bytes).map_err(|error| format!("cannot write {}: {}", path.display(), error))?;
+    }
+    Ok((name, wdl.len(), dtz.len()))
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [directory] = args.as_slice() else {
+        eprintln!("usage: make_syzygy <directory>");
+        return ExitCode::FAILURE;
+    };
+    for ending in Ending::ALL {
+        endgame::prepare(ending);
+    }
+    for piece in ['Q', 'R', 'P', 'B', 'N'] {
+        match write(piece, Path::new(directory)) {
+            Ok((name, wdl, dtz)) => println!("{}: {} + {} bytes", name, wdl, dtz),
+            Err(error) => {
+                eprintln!("{}", error);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    ExitCode::SUCCESS
+}
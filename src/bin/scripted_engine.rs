@@ -0,0 +1,72 @@
+//! Stand-in UCI engine for trying out the GUI's engine support without a
+//! real engine. It plays the moves given on the command line in order,
+//! falling back to the first legal move once the script runs out or a
+//! scripted move is not legal.
+//!
+//!     chess-wasm --engine target/debug/scripted_engine e7e5 g8f6
+
+use std::io::{self, BufRead};
+
+use chess_wasm::Grid;
+
+fn set_position<'a>(mut tokens: impl Iterator<Item = &'a str>) -> Option<Grid> {
+    let mut grid = match tokens.next()? {
+        "startpos" => {
+            tokens.next();
+            Grid::new()
+        }
+        "fen" => {
+            let fen: Vec<&str> = tokens.by_ref().take_while(|&token| token != "moves").collect();
+            Grid::from_fen(&fen.join(" ")).ok()?
+        }
+        _ => return None,
+    };
+    for text in tokens {
+        let mv = grid.parse_uci_move(text)?;
+        grid.make_move(mv);
+    }
+    Some(grid)
+}
+
+fn main() {
+    let mut script = std::env::args().skip(1);
+    let mut grid = Grid::new();
+    // Move of an infinite search, given out on `stop`.
+    let mut waiting: Option<String> = None;
+
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("uci") => {
+                println!("id name Scripted");
+                println!("uciok");
+            }
+            Some("isready") => println!("readyok"),
+            Some("position") => {
+                grid = set_position(tokens).unwrap_or_default();
+            }
+            Some("go") => {
+                let scripted = script.next().and_then(|text| grid.parse_uci_move(&text));
+                let best_move = scripted
+                    .or_else(|| grid.legal_moves().first().copied())
+                    .map_or("0000".to_string(), |mv| mv.to_uci());
+                println!("info depth 1 score cp 0 nodes 1 pv {}", best_move);
+                if tokens.any(|token| token == "infinite") {
+                    waiting = Some(best_move);
+                } else {
+                    println!("bestmove {}", best_move);
+                }
+            }
+            Some("stop") => {
+                if let Some(best_move) = waiting.take() {
+                    println!("bestmove {}", best_move);
+                }
+            }
+            Some("quit") => break,
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,473 @@
+//! Universal Chess Interface front end for the engine, so it can be used
+//! from other chess GUIs and tournament managers.
+
+use std::io::{self, BufRead};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use chess_wasm::book::Book;
+use chess_wasm::endgame::{self, Ending};
+use chess_wasm::search::{mate_distance, SearchLimits, SearchResult, Searcher};
+use chess_wasm::syzygy::Tablebase;
+use chess_wasm::tt::{DEFAULT_TT_MB, MAX_TT_MB};
+use chess_wasm::{ChessColor, Grid, Move};
+
+/// Time kept back on every move for communication delays.
+const MOVE_OVERHEAD: Duration = Duration::from_millis(30);
+/// Moves the remaining time is spread over when the GUI does not say.
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+const MAX_MULTIPV: usize = 16;
+/// `BookFile` value that stands for the book compiled into the engine.
+const BUNDLED_BOOK_NAME: &str = "<bundled>";
+/// `SyzygyPath` value for no tablebases, as GUIs send it.
+const NO_SYZYGY_PATH: &str = "<empty>";
+
+/// Xorshift generator for picking book moves, so the engine keeps no
+/// global random state and a seed gives the same choices again.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // Xorshift never leaves zero.
+        Rng(seed | 1)
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 32) as u32
+    }
+}
+
+/// Parameters of a `go` command.
+#[derive(Default)]
+struct Go {
+    depth: Option<u32>,
+    movetime: Option<Duration>,
+    time: [Option<Duration>; 2],
+    increment: [Duration; 2],
+    moves_to_go: Option<u32>,
+    nodes: Option<u64>,
+    /// Moves within which to look for a mate.
+    mate: Option<u32>,
+    /// Root moves to choose from, in UCI notation; all when empty.
+    search_moves: Vec<String>,
+    infinite: bool,
+    /// Searching on the opponent's time: the clock only starts on
+    /// `ponderhit`, and nothing is answered before it or `stop`.
+    ponder: bool,
+}
+
+/// Words that start a parameter of `go`, ending a list of `searchmoves`.
+const GO_KEYWORDS: [&str; 12] = [
+    "searchmoves",
+    "ponder",
+    "wtime",
+    "btime",
+    "winc",
+    "binc",
+    "movestogo",
+    "depth",
+    "nodes",
+    "mate",
+    "movetime",
+    "infinite",
+];
+
+impl Go {
+    /// Reads what it understands and skips the rest, as a `go` has to be
+    /// answered with a `bestmove` whatever it holds.
+    fn parse<'a>(tokens: impl Iterator<Item = &'a str>) -> Go {
+        let mut tokens = tokens.peekable();
+        let mut go = Go::default();
+        while let Some(token) = tokens.next() {
+            match token {
+                "infinite" => go.infinite = true,
+                "ponder" => go.ponder = true,
+                "searchmoves" => {
+                    while let Some(text) = tokens.next_if(|token| !GO_KEYWORDS.contains(token)) {
+                        go.search_moves.push(text.to_string());
+                    }
+                }
+                _ => {
+                    let Some(number) = tokens.peek().and_then(|value| value.parse::<u64>().ok()) else {
+                        continue;
+                    };
+                    tokens.next();
+                    let millis = Duration::from_millis(number);
+                    match token {
+                        "depth" => go.depth = Some(number as u32),
+                        "movetime" => go.movetime = Some(millis),
+                        "wtime" => go.time[0] = Some(millis),
+                        "btime" => go.time[1] = Some(millis),
+                        "winc" => go.increment[0] = millis,
+                        "binc" => go.increment[1] = millis,
+                        "movestogo" => go.moves_to_go = Some(number as u32),
+                        "nodes" => go.nodes = Some(number),
+                        "mate" => go.mate = Some(number as u32),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        go
+    }
+
+    /// Whether the search may not answer before `stop` or `ponderhit`.
+    fn waits_for_stop(&self) -> bool {
+        self.infinite || self.ponder
+    }
+
+    fn limits(&self, color: ChessColor) -> SearchLimits {
+        let mut limits = SearchLimits {
+            // A mate in n moves is at most 2n - 1 plies away.
+            depth: self.depth.or(self.mate.map(|moves| (moves * 2).saturating_sub(1).max(1))),
+            nodes: self.nodes,
+            ponder: self.ponder,
+            ..Default::default()
+        };
+        if self.infinite {
+            return limits;
+        }
+        let side = match color {
+            ChessColor::White => 0,
+            ChessColor::Black => 1,
+        };
+        if let Some(movetime) = self.movetime {
+            let budget = movetime.saturating_sub(MOVE_OVERHEAD);
+            limits.soft_time = Some(budget);
+            limits.hard_time = Some(budget);
+        } else if let Some(remaining) = self.time[side] {
+            let usable = remaining.saturating_sub(MOVE_OVERHEAD);
+            let moves_to_go = self.moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+            let budget = usable / moves_to_go + self.increment[side] * 3 / 4;
+            // Stop starting iterations halfway, as the next one would
+            // rarely finish, but let a running one overrun a little.
+            limits.soft_time = Some((budget / 2).min(usable));
+            limits.hard_time = Some((budget * 2).min(usable / 2));
+        }
+        limits
+    }
+}
+
+struct Engine {
+    grid: Grid,
+    /// Hashes of the positions played before `grid`, for repetitions.
+    history: Vec<u64>,
+    /// Handed to the search thread while it runs.
+    searcher: Option<Searcher>,
+    search: Option<JoinHandle<Searcher>>,
+    stop: Arc<AtomicBool>,
+    ponderhit: Arc<AtomicBool>,
+    multipv: usize,
+    book: Book,
+    own_book: bool,
+    rng: Rng,
+    /// Castling is sent and received as the king taking its rook.
+    chess960: bool,
+}
+
+impl Engine {
+    fn new(seed: u64) -> Self {
+        let searcher = Searcher::new(DEFAULT_TT_MB);
+        Engine {
+            grid: Grid::new(),
+            history: Vec::new(),
+            stop: searcher.stop_handle(),
+            ponderhit: searcher.ponderhit_handle(),
+            searcher: Some(searcher),
+            search: None,
+            multipv: 1,
+            book: Book::bundled(),
+            own_book: true,
+            rng: Rng::new(seed),
+            chess960: false,
+        }
+    }
+
+    /// Runs one command line; returns false on `quit`.
+    fn handle(&mut self, line: &str) -> bool {
+        let mut tokens = line.split_whitespace();
+        let result = match tokens.next() {
+            Some("uci") => {
+                println!("id name chess-wasm {}", env!("CARGO_PKG_VERSION"));
+                println!("id author the chess-wasm authors");
+                println!(
+                    "option name Hash type spin default {} min 1 max {}",
+                    DEFAULT_TT_MB, MAX_TT_MB
+                );
+                println!("option name Clear Hash type button");
+                println!("option name MultiPV type spin default 1 min 1 max {}", MAX_MULTIPV);
+                println!("option name OwnBook type check default true");
+                println!("option name BookFile type string default {}", BUNDLED_BOOK_NAME);
+                println!("option name SyzygyPath type string default {}", NO_SYZYGY_PATH);
+                println!("option name Ponder type check default false");
+                println!("option name UCI_Chess960 type check default false");
+                println!("uciok");
+                Ok(())
+            }
+            Some("isready") => {
+                // Solve the small endgames now rather than on the clock.
+                for ending in Ending::ALL {
+                    endgame::prepare(ending);
+                }
+                println!("readyok");
+                Ok(())
+            }
+            Some("ucinewgame") => {
+                let searcher = self.searcher();
+                searcher.tt.clear();
+                self.grid = Grid::new();
+                self.grid.set_chess960(self.chess960);
+                self.history.clear();
+                Ok(())
+            }
+            Some("setoption") => self.set_option(line),
+            Some("position") => self.set_position(tokens),
+            Some("go") => {
+                self.go(Go::parse(tokens));
+                Ok(())
+            }
+            Some("stop") => {
+                self.searcher();
+                Ok(())
+            }
+            // The move pondered on was played: search on, now on the clock.
+            Some("ponderhit") => {
+                self.ponderhit.store(true, Ordering::Relaxed);
+                Ok(())
+            }
+            Some("quit") => {
+                self.searcher();
+                return false;
+            }
+            _ => Ok(()),
+        };
+        if let Err(error) = result {
+            println!("info string {}", error);
+        }
+        true
+    }
+
+    /// The searcher, stopping a running search and waiting for it to hand
+    /// the searcher back.
+    fn searcher(&mut self) -> &mut Searcher {
+        if let Some(search) = self.search.take() {
+            self.stop.store(true, Ordering::Relaxed);
+            self.searcher = Some(search.join().expect("search thread panicked"));
+        }
+        self.searcher.as_mut().expect("searcher is either idle or searching")
+    }
+
+    fn set_option(&mut self, line: &str) -> Result<(), String> {
+        let rest = line.trim_start_matches("setoption").trim();
+        let rest = rest.strip_prefix("name").ok_or("expected setoption name")?.trim();
+        let (name, value) = match rest.split_once(" value ") {
+            Some((name, value)) => (name.trim(), Some(value.trim())),
+            None => (rest, None),
+        };
+        match name {
+            "Hash" => {
+                let megabytes: usize = value
+                    .and_then(|value| value.parse().ok())
+                    .ok_or("Hash needs a size in MB")?;
+                self.searcher().tt.resize(megabytes);
+            }
+            "Clear Hash" => self.searcher().tt.clear(),
+            "MultiPV" => {
+                let lines: usize = value
+                    .and_then(|value| value.parse().ok())
+                    .ok_or("MultiPV needs a number of lines")?;
+                self.multipv = lines.clamp(1, MAX_MULTIPV);
+            }
+            "OwnBook" => self.own_book = value == Some("true"),
+            // The GUI decides when to ponder; the option only says it may.
+            "Ponder" => {}
+            "UCI_Chess960" => {
+                self.chess960 = value == Some("true");
+                self.grid.set_chess960(self.chess960);
+            }
+            "BookFile" => {
+                self.book = match value {
+                    None | Some(BUNDLED_BOOK_NAME) => Book::bundled(),
+                    Some(path) => std::fs::read(path)
+                        .map_err(|error| format!("cannot read {}: {}", path, error))
+                        .and_then(|bytes| Book::from_bytes(&bytes))?,
+                };
+            }
+            "SyzygyPath" => {
+                let tablebase = match value {
+                    None | Some(NO_SYZYGY_PATH) | Some("") => None,
+                    Some(path) => Some(Arc::new(Tablebase::open(path)?)),
+                };
+                if let Some(tablebase) = &tablebase {
+                    println!(
+                        "info string found {} tablebases with up to {} pieces",
+                        tablebase.len(),
+                        tablebase.max_pieces()
+                    );
+                }
+                self.searcher().set_tablebase(tablebase);
+            }
+            _ => return Err(format!("unknown option {}", name)),
+        }
+        Ok(())
+    }
+
+    fn set_position<'a>(&mut self, mut tokens: impl Iterator<Item = &'a str>) -> Result<(), String> {
+        let mut grid = match tokens.next() {
+            Some("startpos") => {
+                if tokens.next().is_some_and(|token| token != "moves") {
+                    return Err("expected moves after startpos".to_string());
+                }
+                Grid::new()
+            }
+            Some("fen") => {
+                let fen: Vec<&str> = tokens.by_ref().take_while(|&token| token != "moves").collect();
+                Grid::from_fen(&fen.join(" "))?
+            }
+            _ => return Err("expected startpos or fen".to_string()),
+        };
+        // A FEN can only be played as Chess960, whatever the option says.
+        grid.set_chess960(self.chess960 || grid.chess960());
+        let mut history = Vec::new();
+        for text in tokens {
+            let mv = grid
+                .parse_uci_move(text)
+                .ok_or_else(|| format!("illegal move {}", text))?;
+            history.push(grid.position_hash());
+            grid.make_move(mv);
+        }
+        self.grid = grid;
+        self.history = history;
+        Ok(())
+    }
+
+    fn go(&mut self, go: Go) {
+        self.searcher();
+        let search_moves: Vec<Move> = go
+            .search_moves
+            .iter()
+            .filter_map(|text| self.grid.parse_uci_move(text))
+            .collect();
+        // Analysis should see the engine's own opinion, not the book's.
+        if self.own_book && !go.waits_for_stop() && search_moves.is_empty() {
+            if let Some(mv) = self.book.choose(&self.grid, self.rng.next_u32()) {
+                println!("info string book move");
+                println!("bestmove {}", mv.to_uci());
+                return;
+            }
+        }
+        let mut searcher = self.searcher.take().expect("searcher is idle");
+        searcher.set_history(&self.history);
+        searcher.set_search_moves(&search_moves);
+        self.stop.store(false, Ordering::Relaxed);
+        self.ponderhit.store(false, Ordering::Relaxed);
+        let mut grid = self.grid.clone();
+        let limits = SearchLimits {
+            multipv: self.multipv,
+            ..go.limits(grid.side_to_move())
+        };
+        let (stop, ponderhit) = (self.stop.clone(), self.ponderhit.clone());
+        let (infinite, ponder) = (go.infinite, go.ponder);
+        self.search = Some(thread::spawn(move || {
+            let start = Instant::now();
+            let result = searcher.search_with(&mut grid, limits, |result| print_info(result, start));
+            // An infinite search answers only when told to stop, a ponder
+            // search not before the move it pondered on is played.
+            let waiting = || infinite || ponder && !ponderhit.load(Ordering::Relaxed);
+            while waiting() && !stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(5));
+            }
+            match result.best_move {
+                Some(best_move) => match result.pv.get(1) {
+                    Some(ponder) => println!("bestmove {} ponder {}", best_move.to_uci(), ponder.to_uci()),
+                    None => println!("bestmove {}", best_move.to_uci()),
+                },
+                None => println!("bestmove 0000"),
+            }
+            searcher
+        }));
+    }
+}
+
+fn print_info(result: &SearchResult, start: Instant) {
+    let millis = start.elapsed().as_millis() as u64;
+    for (i, line) in result.lines.iter().enumerate() {
+        let score = match mate_distance(line.score) {
+            Some(moves) => format!("mate {}", moves),
+            None => format!("cp {}", line.score),
+        };
+        let pv: Vec<String> = line.moves.iter().map(|mv| mv.to_uci()).collect();
+        println!(
+            "info depth {} multipv {} score {} nodes {} nps {} hashfull {} tbhits {} time {} pv {}",
+            result.depth,
+            i + 1,
+            score,
+            result.nodes,
+            result.nodes * 1000 / millis.max(1),
+            result.hashfull,
+            result.stats.tablebase_hits,
+            millis,
+            pv.join(" ")
+        );
+    }
+}
+
+fn main() {
+    let seed = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_nanos() as u64);
+    let mut engine = Engine::new(seed);
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if !engine.handle(&line) {
+            break;
+        }
+    }
+    engine.searcher();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn go_skips_what_it_does_not_know() {
+        let go = Go::parse("wtime 1000 ponder btime 2000 frobnicate 3 searchmoves e2e4 d2d4 nodes 500".split(' '));
+        assert!(go.ponder && !go.infinite);
+        assert_eq!(go.time, [Some(Duration::from_millis(1000)), Some(Duration::from_millis(2000))]);
+        assert_eq!(go.search_moves, ["e2e4", "d2d4"]);
+        assert_eq!(go.nodes, Some(500));
+        let limits = go.limits(ChessColor::White);
+        assert_eq!(limits.nodes, Some(500));
+        // Pondering keeps the time limits for after the ponder hit.
+        assert!(limits.ponder && limits.hard_time.is_some());
+        let limits = Go::parse("wtime 1000 btime 1000 infinite".split(' ')).limits(ChessColor::White);
+        assert!(!limits.ponder && limits.hard_time.is_none());
+    }
+
+    #[test]
+    fn go_mate_limits_depth() {
+        let go = Go::parse("mate 3".split(' '));
+        assert_eq!(go.limits(ChessColor::Black).depth, Some(5));
+        let go = Go::parse("depth x movetime".split(' '));
+        assert_eq!(go.depth, None);
+        assert_eq!(go.movetime, None);
+    }
+
+    #[test]
+    fn book_choices_follow_the_seed() {
+        let book = Book::bundled();
+        let grid = Grid::new();
+        let choices = |seed| {
+            let mut rng = Rng::new(seed);
+            (0..16).map(|_| book.choose(&grid, rng.next_u32())).collect::<Vec<_>>()
+        };
+        assert_eq!(choices(7), choices(7));
+        assert!(choices(7).iter().all(Option::is_some));
+    }
+}
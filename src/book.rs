@@ -0,0 +1,239 @@
+//! Polyglot opening books: sorted 16-byte entries of position key, move,
+//! weight and learning data, all big-endian.
+
+mod keys;
+
+use crate::{ChessColor, Grid, Move, TypePiece};
+use keys::RANDOM64;
+
+/// Book built from `assets/book.txt` with the `make_book` tool, compiled in
+/// for the browser, which has no files to load one from.
+pub const BUNDLED_BOOK: &[u8] = include_bytes!("../assets/book.bin");
+
+const ENTRY_SIZE: usize = 16;
+const CASTLING_OFFSET: usize = 768;
+const EN_PASSANT_OFFSET: usize = 772;
+const TURN_OFFSET: usize = 780;
+
+/// Polyglot's key for a position. Unlike `Grid::position_hash` it only
+/// counts the en passant file when a pawn can actually take there.
+pub fn polyglot_key(grid: &Grid) -> u64 {
+    let mut key = 0;
+    for square in 0..64 {
+        let position = [square % 8, square / 8];
+        let Some(piece) = grid.find_cell(position).piece else {
+            continue;
+        };
+        let kind = match piece.type_of_piece {
+            TypePiece::Pawn => 0,
+            TypePiece::Knight => 1,
+            TypePiece::Bishop => 2,
+            TypePiece::Rook => 3,
+            TypePiece::Queen => 4,
+            TypePiece::King => 5,
+        } * 2
+            + (piece.color == ChessColor::White) as usize;
+        key ^= RANDOM64[64 * kind + square];
+    }
+    for right in 0..4 {
+        if grid.castling_rights() & (1 << right) != 0 {
+            key ^= RANDOM64[CASTLING_OFFSET + right];
+        }
+    }
+    if let Some(target) = grid.en_passant() {
+        let color = grid.side_to_move();
+        // The capturing pawn stands beside the pawn that just moved.
+        let row = match color {
+            ChessColor::White => 4,
+            ChessColor::Black => 3,
+        };
+        let can_take = [target[0].wrapping_sub(1), target[0] + 1].into_iter().any(|col| {
+            col < 8
+                && grid
+                    .find_cell([col, row])
+                    .piece
+                    .is_some_and(|piece| piece.type_of_piece == TypePiece::Pawn && piece.color == color)
+        });
+        if can_take {
+            key ^= RANDOM64[EN_PASSANT_OFFSET + target[0]];
+        }
+    }
+    if grid.side_to_move() == ChessColor::White {
+        key ^= RANDOM64[TURN_OFFSET];
+    }
+    key
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BookEntry {
+    pub key: u64,
+    /// Polyglot move: to file, to rank, from file, from rank (3 bits each,
+    /// lowest first), then the promotion piece. Castling is written as the
+    /// king taking its own rook.
+    pub raw_move: u16,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+impl BookEntry {
+    pub fn new(grid: &Grid, mv: Move, weight: u16) -> Self {
+        BookEntry {
+            key: polyglot_key(grid),
+            raw_move: encode_move(grid, mv),
+            weight,
+            learn: 0,
+        }
+    }
+}
+
+/// Polyglot's encoding of a legal move of `grid`.
+pub fn encode_move(grid: &Grid, mv: Move) -> u16 {
+    let to = match grid.castling_rook_move(mv) {
+        Some((rook_from, _)) => rook_from,
+        None => mv.to,
+    };
+    let promotion = match mv.promotion {
+        None | Some(TypePiece::Pawn) | Some(TypePiece::King) => 0,
+        Some(TypePiece::Knight) => 1,
+        Some(TypePiece::Bishop) => 2,
+        Some(TypePiece::Rook) => 3,
+        Some(TypePiece::Queen) => 4,
+    };
+    (to[0] | to[1] << 3 | mv.from[0] << 6 | mv.from[1] << 9 | promotion << 12) as u16
+}
+
+/// The legal move of `grid` a Polyglot move stands for, if any.
+pub fn decode_move(grid: &Grid, raw: u16) -> Option<Move> {
+    grid.legal_moves().into_iter().find(|&mv| encode_move(grid, mv) == raw)
+}
+
+/// An opening book, entries sorted by key as in the file.
+#[derive(Clone, Debug, Default)]
+pub struct Book {
+    entries: Vec<BookEntry>,
+}
+
+impl Book {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Book, String> {
+        if !bytes.len().is_multiple_of(ENTRY_SIZE) {
+            return Err(format!("book size {} is not a multiple of {}", bytes.len(), ENTRY_SIZE));
+        }
+        let mut entries: Vec<BookEntry> = bytes
+            .chunks_exact(ENTRY_SIZE)
+            .map(|chunk| BookEntry {
+                key: u64::from_be_bytes(chunk[0..8].try_into().unwrap()),
+                raw_move: u16::from_be_bytes([chunk[8], chunk[9]]),
+                weight: u16::from_be_bytes([chunk[10], chunk[11]]),
+                learn: u32::from_be_bytes(chunk[12..16].try_into().unwrap()),
+            })
+            .collect();
+        // Lookups rely on the order, so don't trust the file for it.
+        if !entries.is_sorted_by_key(|entry| entry.key) {
+            entries.sort_by_key(|entry| entry.key);
+        }
+        Ok(Book { entries })
+    }
+
+    pub fn bundled() -> Book {
+        Book::from_bytes(BUNDLED_BOOK).expect("bundled book is well formed")
+    }
+
+    /// Sorts `entries` and merges those for the same position and move,
+    /// adding their weights.
+    pub fn from_entries(mut entries: Vec<BookEntry>) -> Book {
+        entries.sort_by_key(|entry| (entry.key, entry.raw_move));
+        let mut merged: Vec<BookEntry> = Vec::with_capacity(entries.len());
+        for entry in entries {
+            match merged.last_mut() {
+                Some(last) if last.key == entry.key && last.raw_move == entry.raw_move => {
+                    last.weight = last.weight.saturating_add(entry.weight);
+                }
+                _ => merged.push(entry),
+            }
+        }
+        // Best moves first within a position, as Polyglot writes them.
+        merged.sort_by_key(|entry| (entry.key, std::cmp::Reverse(entry.weight)));
+        Book { entries: merged }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.entries.len() * ENTRY_SIZE);
+        for entry in &self.entries {
+            bytes.extend_from_slice(&entry.key.to_be_bytes());
+            bytes.extend_from_slice(&entry.raw_move.to_be_bytes());
+            bytes.extend_from_slice(&entry.weight.to_be_bytes());
+            bytes.extend_from_slice(&entry.learn.to_be_bytes());
+        }
+        bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Legal book moves for `grid` with their weights.
+    pub fn moves(&self, grid: &Grid) -> Vec<(Move, u16)> {
+        let key = polyglot_key(grid);
+        let first = self.entries.partition_point(|entry| entry.key < key);
+        self.entries[first..]
+            .iter()
+            .take_while(|entry| entry.key == key)
+            .filter_map(|entry| decode_move(grid, entry.raw_move).map(|mv| (mv, entry.weight)))
+            .collect()
+    }
+
+    pub fn contains(&self, grid: &Grid, mv: Move) -> bool {
+        self.moves(grid).iter().any(|&(book_move, _)| book_move == mv)
+    }
+
+    /// A book move picked with probability proportional to its weight;
+    /// `roll` is any random number.
+    pub fn choose(&self, grid: &Grid, roll: u32) -> Option<Move> {
+        let moves = self.moves(grid);
+        let total: u32 = moves.iter().map(|&(_, weight)| weight as u32).sum();
+        if total == 0 {
+            return moves.first().map(|&(mv, _)| mv);
+        }
+        let mut pick = roll % total;
+        for (mv, weight) in moves {
+            if pick < weight as u32 {
+                return Some(mv);
+            }
+            pick -= weight as u32;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The reference keys from the Polyglot book format description.
+    #[test]
+    fn keys_match_polyglot() {
+        let expected: [(&[&str], u64); 9] = [
+            (&[], 0x463b96181691fc9c),
+            (&["e2e4"], 0x823c9b50fd114196),
+            (&["e2e4", "d7d5"], 0x0756b94461c50fb0),
+            (&["e2e4", "d7d5", "e4e5"], 0x662fafb965db29d4),
+            (&["e2e4", "d7d5", "e4e5", "f7f5"], 0x22a48b5a8e47ff78),
+            (&["e2e4", "d7d5", "e4e5", "f7f5", "e1e2"], 0x652a607ca3f242c1),
+            (&["e2e4", "d7d5", "e4e5", "f7f5", "e1e2", "e8f7"], 0x00fdd303c946bdd9),
+            (&["a2a4", "b7b5", "h2h4", "b5b4", "c2c4"], 0x3c8123ea7b067637),
+            (&["a2a4", "b7b5", "h2h4", "b5b4", "c2c4", "b4c3", "a1a3"], 0x5c3f9b829b279560),
+        ];
+        for (moves, key) in expected {
+            let mut grid = Grid::new();
+            for text in moves {
+                let mv = grid.parse_uci_move(text).unwrap();
+                grid.make_move(mv);
+            }
+            assert_eq!(polyglot_key(&grid), key, "after {:?}", moves);
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use chess_wasm::ChessColor;
+
+const DEFAULT_MINUTES: f32 = 10.0;
+const LOW_TIME_SECONDS: f32 = 10.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClockEvent {
+    LowTime(ChessColor),
+    Flag(ChessColor),
+}
+
+/// Remaining thinking time for both players, in seconds.
+#[derive(Clone, Copy, Debug)]
+pub struct Clock {
+    remaining: [f32; 2],
+    increment: f32,
+    low_time_warned: [bool; 2],
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new(DEFAULT_MINUTES * 60.0, 0.0)
+    }
+}
+
+fn index(color: ChessColor) -> usize {
+    match color {
+        ChessColor::White => 0,
+        ChessColor::Black => 1,
+    }
+}
+
+impl Clock {
+    pub fn new(initial: f32, increment: f32) -> Self {
+        Clock {
+            remaining: [initial; 2],
+            increment,
+            low_time_warned: [false; 2],
+        }
+    }
+
+    pub fn remaining(&self, color: ChessColor) -> f32 {
+        self.remaining[index(color)]
+    }
+
+    /// Runs `color`'s clock down and reports the first time it gets low and
+    /// when it reaches zero.
+    pub fn tick(&mut self, color: ChessColor, frame_time: f32) -> Option<ClockEvent> {
+        let i = index(color);
+        if self.remaining[i] <= 0.0 {
+            return None;
+        }
+        self.remaining[i] = (self.remaining[i] - frame_time).max(0.0);
+        if self.remaining[i] <= 0.0 {
+            return Some(ClockEvent::Flag(color));
+        }
+        if self.remaining[i] <= LOW_TIME_SECONDS && !self.low_time_warned[i] {
+            self.low_time_warned[i] = true;
+            return Some(ClockEvent::LowTime(color));
+        }
+        None
+    }
+
+    pub fn add_increment(&mut self, color: ChessColor) {
+        self.remaining[index(color)] += self.increment;
+    }
+
+    pub fn format(&self, color: ChessColor) -> String {
+        let seconds = self.remaining(color).ceil() as u32;
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
@@ -0,0 +1,151 @@
+//! Opening names: a table of ECO codes and names for the positions of
+//! well-known lines, matched on position so transpositions are found.
+
+use std::collections::HashMap;
+
+use crate::book::polyglot_key;
+use crate::{Grid, Move};
+
+/// Table compiled in from `assets/eco.txt`.
+pub const BUNDLED_ECO: &str = include_str!("../assets/eco.txt");
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Opening {
+    /// Encyclopaedia of Chess Openings code, e.g. `C60`.
+    pub code: String,
+    pub name: String,
+    /// Length of the line naming it, in plies.
+    pub plies: usize,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct EcoTable {
+    /// Keyed by Polyglot key, which ignores en passant squares no pawn can
+    /// use and so lets transposed move orders meet.
+    openings: HashMap<u64, Opening>,
+}
+
+impl EcoTable {
+    /// Reads lines of `code | name | uci moves`; blank lines and lines
+    /// starting with `#` are skipped. Later lines win for a position.
+    pub fn parse(text: &str) -> Result<EcoTable, String> {
+        let mut openings = HashMap::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('|').map(str::trim).collect();
+            let [code, name, moves] = fields[..] else {
+                return Err(format!("line {}: expected 'code | name | moves'", number + 1));
+            };
+            let mut grid = Grid::new();
+            let mut plies = 0;
+            for text in moves.split_whitespace() {
+                let mv = grid
+                    .parse_uci_move(text)
+                    .ok_or_else(|| format!("line {}: illegal move '{}'", number + 1, text))?;
+                grid.make_move(mv);
+                plies += 1;
+            }
+            let opening = Opening {
+                code: code.to_string(),
+                name: name.to_string(),
+                plies,
+            };
+            openings.insert(polyglot_key(&grid), opening);
+        }
+        Ok(EcoTable { openings })
+    }
+
+    pub fn bundled() -> EcoTable {
+        EcoTable::parse(BUNDLED_ECO).expect("bundled ECO table is well formed")
+    }
+
+    pub fn len(&self) -> usize {
+        self.openings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.openings.is_empty()
+    }
+
+    /// The opening `grid` is a named position of.
+    pub fn lookup(&self, grid: &Grid) -> Option<&Opening> {
+        self.openings.get(&polyglot_key(grid))
+    }
+
+    /// `grid`'s opening if it has one named by a line at least as long as
+    /// that of `opening`, the game's name so far, and `opening` otherwise.
+    pub fn deepest<'a>(&'a self, opening: Option<&'a Opening>, grid: &Grid) -> Option<&'a Opening> {
+        match (self.lookup(grid), opening) {
+            (Some(found), Some(known)) if found.plies < known.plies => Some(known),
+            (found, known) => found.or(known),
+        }
+    }
+
+    /// The opening of the deepest named position reached by playing `moves`
+    /// from `start`, so a game keeps its name once it leaves the table.
+    pub fn classify(&self, start: &Grid, moves: &[Move]) -> Option<&Opening> {
+        let mut grid = start.clone();
+        let mut opening = self.lookup(&grid);
+        for &mv in moves {
+            grid.make_move(mv);
+            opening = self.deepest(opening, &grid);
+        }
+        opening
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moves(texts: &[&str]) -> Vec<Move> {
+        let mut grid = Grid::new();
+        texts
+            .iter()
+            .map(|text| {
+                let mv = grid.parse_uci_move(text).unwrap();
+                grid.make_move(mv);
+                mv
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bundled_table_parses() {
+        let table = EcoTable::parse(BUNDLED_ECO).unwrap();
+        assert!(table.len() > 200);
+        assert_eq!(EcoTable::bundled().len(), table.len());
+    }
+
+    #[test]
+    fn transpositions_are_named() {
+        let table = EcoTable::bundled();
+        let start = Grid::new();
+        let pirc = table.classify(&start, &moves(&["e2e4", "d7d6", "d2d4", "g8f6"])).unwrap();
+        assert_eq!((pirc.code.as_str(), pirc.plies), ("B07", 4));
+        let transposed = table.classify(&start, &moves(&["d2d4", "g8f6", "e2e4", "d7d6"]));
+        assert_eq!(transposed, Some(pirc));
+    }
+
+    #[test]
+    fn names_outlast_the_table() {
+        let table = EcoTable::bundled();
+        let line = moves(&["d2d4", "g8f6", "c1g5", "h7h6", "g5f6"]);
+        let opening = table.classify(&Grid::new(), &line).unwrap();
+        assert_eq!(opening.name, "Trompowsky Attack");
+        // A name from a shorter line doesn't replace one from a longer line.
+        let mut grid = Grid::new();
+        grid.make_move(line[0]);
+        assert_eq!(table.deepest(Some(opening), &grid), Some(opening));
+        assert_eq!(table.deepest(None, &grid).map(|opening| opening.plies), Some(1));
+    }
+
+    #[test]
+    fn bad_lines_are_reported() {
+        assert!(EcoTable::parse("A00 | Nonsense | e2e5").unwrap_err().contains("line 1"));
+        assert!(EcoTable::parse("# comment\n\nA00 | Missing moves").unwrap_err().contains("line 3"));
+    }
+}
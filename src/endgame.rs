@@ -0,0 +1,476 @@
+//! Small endgames solved in memory: king and queen, king and rook, and king
+//! and pawn against a lone king. Each ending is worked out backwards from
+//! its mates when `prepare` asks for it, giving the distance to mate with
+//! best play for every position.
+//!
+//! The tables use their own compact squares, with the side that has the
+//! extra piece as White; `Grid`s are turned into that form when probed.
+
+use std::sync::OnceLock;
+
+use crate::{ChessColor, Grid, Move, Piece, TypePiece};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Ending {
+    Kqk,
+    Krk,
+    Kpk,
+}
+
+impl Ending {
+    pub const ALL: [Ending; 3] = [Ending::Kqk, Ending::Krk, Ending::Kpk];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Ending::Kqk => "KQK",
+            Ending::Krk => "KRK",
+            Ending::Kpk => "KPK",
+        }
+    }
+
+    /// The piece the stronger side has besides its king.
+    pub fn piece(self) -> TypePiece {
+        match self {
+            Ending::Kqk => TypePiece::Queen,
+            Ending::Krk => TypePiece::Rook,
+            Ending::Kpk => TypePiece::Pawn,
+        }
+    }
+
+    fn of(piece: TypePiece) -> Option<Ending> {
+        Ending::ALL.into_iter().find(|ending| ending.piece() == piece)
+    }
+}
+
+/// Result for the side to move with best play, in plies to mate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Win(u32),
+    Draw,
+    /// `Loss(0)` is already mated.
+    Loss(u32),
+}
+
+impl Outcome {
+    /// Full moves to mate, counting the mating move.
+    pub fn moves_to_mate(self) -> Option<u32> {
+        match self {
+            Outcome::Win(plies) => Some(plies.div_ceil(2)),
+            Outcome::Loss(plies) => Some(plies / 2),
+            Outcome::Draw => None,
+        }
+    }
+
+    /// The outcome for the side that moved into a position with this one.
+    fn before(self) -> Outcome {
+        match self {
+            Outcome::Win(plies) => Outcome::Loss(plies + 1),
+            Outcome::Draw => Outcome::Draw,
+            Outcome::Loss(plies) => Outcome::Win(plies + 1),
+        }
+    }
+}
+
+/// Side to move, stronger king, lone king and piece squares.
+const POSITIONS: usize = 2 * 64 * 64 * 64;
+const KING_STEPS: [(i32, i32); 8] = [(1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1), (0, -1), (1, -1)];
+const ROOK_LINES: [(i32, i32); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+const QUEEN_LINES: [(i32, i32); 8] = KING_STEPS;
+
+/// A solved ending, by position index.
+struct Table {
+    /// Plies to mate plus one, as a win for White to move or a loss for
+    /// Black to move; 0 for draws and positions that cannot occur.
+    plies: Vec<u8>,
+}
+
+impl Table {
+    fn outcome(&self, index: usize) -> Outcome {
+        match self.plies[index] {
+            0 => Outcome::Draw,
+            stored if white_to_move(index) => Outcome::Win(stored as u32 - 1),
+            stored => Outcome::Loss(stored as u32 - 1),
+        }
+    }
+}
+
+fn slot(ending: Ending) -> &'static OnceLock<Table> {
+    static TABLES: [OnceLock<Table>; 3] = [OnceLock::new(), OnceLock::new(), OnceLock::new()];
+    &TABLES[Ending::ALL.iter().position(|&other| other == ending).unwrap_or(0)]
+}
+
+fn table(ending: Ending) -> &'static Table {
+    slot(ending).get_or_init(|| solve(ending))
+}
+
+/// Solves `ending`, which takes a moment, if it has not been already.
+/// Probes only see the endings solved here.
+pub fn prepare(ending: Ending) {
+    table(ending);
+}
+
+fn index(white_to_move: bool, white_king: usize, black_king: usize, piece: usize) -> usize {
+    ((white_to_move as usize * 64 + white_king) * 64 + black_king) * 64 + piece
+}
+
+fn white_to_move(index: usize) -> bool {
+    index >= POSITIONS / 2
+}
+
+fn squares(index: usize) -> (usize, usize, usize) {
+    ((index >> 12) & 63, (index >> 6) & 63, index & 63)
+}
+
+fn step(square: usize, (file_step, rank_step): (i32, i32)) -> Option<usize> {
+    let file = (square % 8) as i32 + file_step;
+    let rank = (square / 8) as i32 + rank_step;
+    ((0..8).contains(&file) && (0..8).contains(&rank)).then_some((rank * 8 + file) as usize)
+}
+
+fn king_moves(square: usize) -> impl Iterator<Item = usize> {
+    KING_STEPS.into_iter().filter_map(move |offset| step(square, offset))
+}
+
+fn adjacent(a: usize, b: usize) -> bool {
+    (a % 8).abs_diff(b % 8) <= 1 && (a / 8).abs_diff(b / 8) <= 1
+}
+
+fn lines(piece: TypePiece) -> &'static [(i32, i32)] {
+    match piece {
+        TypePiece::Queen => &QUEEN_LINES,
+        _ => &ROOK_LINES,
+    }
+}
+
+/// Whether White's piece on `from` attacks `target`, with the white king on
+/// `blocker` as the only thing that can stand in the way.
+fn attacks(piece: TypePiece, from: usize, target: usize, blocker: usize) -> bool {
+    if piece == TypePiece::Pawn {
+        return target / 8 == from / 8 + 1 && (target % 8).abs_diff(from % 8) == 1;
+    }
+    let file_step = (target % 8) as i32 - (from % 8) as i32;
+    let rank_step = (target / 8) as i32 - (from / 8) as i32;
+    let straight = file_step == 0 || rank_step == 0;
+    let diagonal = file_step.abs() == rank_step.abs();
+    if target == from || !(straight || piece == TypePiece::Queen && diagonal) {
+        return false;
+    }
+    let direction = (file_step.signum(), rank_step.signum());
+    let mut square = from;
+    while let Some(next) = step(square, direction) {
+        if next == target {
+            return true;
+        }
+        if next == blocker {
+            return false;
+        }
+        square = next;
+    }
+    false
+}
+
+/// Whether the position can occur: three squares, kings apart, a pawn off
+/// the first and last ranks and Black not in check with White to move.
+fn is_legal(ending: Ending, white_to_move: bool, white_king: usize, black_king: usize, piece: usize) -> bool {
+    if white_king == black_king || piece == white_king || piece == black_king || adjacent(white_king, black_king) {
+        return false;
+    }
+    if ending == Ending::Kpk && !(8..56).contains(&piece) {
+        return false;
+    }
+    !(white_to_move && attacks(ending.piece(), piece, black_king, white_king))
+}
+
+/// Positions waiting to be settled, by plies to mate.
+struct Levels(Vec<Vec<u32>>);
+
+impl Levels {
+    fn push(&mut self, plies: usize, index: usize) {
+        if self.0.len() <= plies {
+            self.0.resize_with(plies + 1, Vec::new);
+        }
+        self.0[plies].push(index as u32);
+    }
+}
+
+/// Retrograde analysis: starting from the mates, a White position is won
+/// as soon as one move reaches a lost Black position, and a Black position
+/// is lost once every move reaches a won White one. Settling positions in
+/// order of plies gives White the quickest mate and Black the slowest.
+fn solve(ending: Ending) -> Table {
+    let piece_type = ending.piece();
+    let mut plies = vec![0u8; POSITIONS];
+    // Black moves not yet known to lead to a White win.
+    let mut unresolved = vec![0u8; POSITIONS];
+    let mut levels = Levels(Vec::new());
+
+    for white_king in 0..64 {
+        for black_king in 0..64 {
+            for piece in 0..64 {
+                if !is_legal(ending, false, white_king, black_king, piece) {
+                    continue;
+                }
+                let mut moves = 0;
+                for target in king_moves(black_king) {
+                    // Taking an undefended piece leaves a draw, so such a move
+                    // is never resolved.
+                    if target == white_king || adjacent(target, white_king) {
+                        continue;
+                    }
+                    if target == piece || !attacks(piece_type, piece, target, white_king) {
+                        moves += 1;
+                    }
+                }
+                let position = index(false, white_king, black_king, piece);
+                unresolved[position] = moves;
+                if moves == 0 && attacks(piece_type, piece, black_king, white_king) {
+                    levels.push(0, position);
+                }
+            }
+        }
+    }
+
+    // A pawn reaching the last rank wins if the queen or rook it becomes does.
+    if ending == Ending::Kpk {
+        let promoted = [table(Ending::Kqk), table(Ending::Krk)];
+        for white_king in 0..64 {
+            for black_king in 0..64 {
+                for piece in 48..56 {
+                    let square = piece + 8;
+                    if square == white_king
+                        || square == black_king
+                        || !is_legal(ending, true, white_king, black_king, piece)
+                    {
+                        continue;
+                    }
+                    let after = index(false, white_king, black_king, square);
+                    let best = promoted
+                        .iter()
+                        .filter_map(|table| match table.outcome(after) {
+                            Outcome::Loss(plies) => Some(plies as usize + 1),
+                            _ => None,
+                        })
+                        .min();
+                    if let Some(best) = best {
+                        levels.push(best, index(true, white_king, black_king, piece));
+                    }
+                }
+            }
+        }
+    }
+
+    let mut level = 0;
+    while level < levels.0.len() {
+        let settled = std::mem::take(&mut levels.0[level]);
+        for position in settled {
+            let position = position as usize;
+            if plies[position] != 0 {
+                continue;
+            }
+            plies[position] = level as u8 + 1;
+            let (white_king, black_king, piece) = squares(position);
+            if white_to_move(position) {
+                // Black king moves back.
+                for from in king_moves(black_king) {
+                    if from == white_king || from == piece || adjacent(from, white_king) {
+                        continue;
+                    }
+                    let before = index(false, white_king, from, piece);
+                    if unresolved[before] > 0 {
+                        unresolved[before] -= 1;
+                        if unresolved[before] == 0 {
+                            levels.push(level + 1, before);
+                        }
+                    }
+                }
+                continue;
+            }
+            let mut before = Vec::new();
+            for from in king_moves(white_king) {
+                if from != black_king && from != piece {
+                    before.push((from, piece));
+                }
+            }
+            if piece_type == TypePiece::Pawn {
+                let single = piece - 8;
+                if single >= 8 && single != white_king && single != black_king {
+                    before.push((white_king, single));
+                    let double = single - 8;
+                    if piece / 8 == 3 && double != white_king && double != black_king {
+                        before.push((white_king, double));
+                    }
+                }
+            } else {
+                for &direction in lines(piece_type) {
+                    let mut square = piece;
+                    while let Some(from) = step(square, direction) {
+                        if from == white_king || from == black_king {
+                            break;
+                        }
+                        before.push((white_king, from));
+                        square = from;
+                    }
+                }
+            }
+            for (king, from) in before {
+                if is_legal(ending, true, king, black_king, from) {
+                    let before = index(true, king, black_king, from);
+                    if plies[before] == 0 {
+                        levels.push(level + 1, before);
+                    }
+                }
+            }
+        }
+        level += 1;
+    }
+    Table { plies }
+}
+
+/// The ending of `grid`, if it is one solved here, and its table index.
+fn locate(grid: &Grid) -> Option<(Ending, usize)> {
+    // Castling is left out of the tables.
+    if grid.castling_rights() != 0 {
+        return None;
+    }
+    let mut kings = [None; 2];
+    let mut extra: Option<(Piece, usize)> = None;
+    for square in 0..64 {
+        let Some(piece) = grid.find_cell([square % 8, square / 8]).piece else {
+            continue;
+        };
+        if piece.type_of_piece == TypePiece::King {
+            kings[(piece.color == ChessColor::Black) as usize] = Some(square);
+        } else if extra.replace((piece, square)).is_some() {
+            return None;
+        }
+    }
+    let (piece, square) = extra?;
+    let ending = Ending::of(piece.type_of_piece)?;
+    let [white_king, black_king] = kings.map(|king| king.unwrap_or(0));
+    // Turn the board over when Black is the stronger side.
+    let (strong_king, weak_king, piece_square) = match piece.color {
+        ChessColor::White => (white_king, black_king, square),
+        ChessColor::Black => (black_king ^ 56, white_king ^ 56, square ^ 56),
+    };
+    let strong_to_move = grid.side_to_move() == piece.color;
+    Some((ending, index(strong_to_move, strong_king, weak_king, piece_square)))
+}
+
+/// The ending `grid` is in, if it is one of those solved here.
+pub fn ending(grid: &Grid) -> Option<Ending> {
+    locate(grid).map(|(ending, _)| ending)
+}
+
+/// Result with best play for the side to move, once `prepare` has solved
+/// the ending; until then `None`, so a probe never holds up a search. The
+/// fifty-move rule is not counted, as no mate here takes long.
+pub fn probe(grid: &Grid) -> Option<Outcome> {
+    let (ending, index) = locate(grid)?;
+    Some(slot(ending).get()?.outcome(index))
+}
+
+/// The moves of `grid` that keep the best result: the quickest mate when
+/// winning and the slowest when losing.
+pub fn best_moves(grid: &Grid) -> Option<Vec<Move>> {
+    probe(grid)?;
+    let mut grid = grid.clone();
+    let mut ranked = Vec::new();
+    for mv in grid.legal_moves() {
+        let undo = grid.make_move(mv);
+        // Captures and minor piece promotions leave too little to mate with.
+        let outcome = probe(&grid).or_else(|| grid.is_insufficient_material().then_some(Outcome::Draw));
+        grid.unmake_move(mv, undo);
+        ranked.push((mv, rank(outcome?.before())));
+    }
+    let best = ranked.iter().map(|&(_, rank)| rank).max()?;
+    Some(ranked.into_iter().filter(|&(_, rank)| rank == best).map(|(mv, _)| mv).collect())
+}
+
+/// Orders outcomes from the best to have to the worst.
+fn rank(outcome: Outcome) -> i32 {
+    match outcome {
+        Outcome::Win(plies) => 1_000 - plies as i32,
+        Outcome::Draw => 0,
+        Outcome::Loss(plies) => -1_000 + plies as i32,
+    }
+}
+
+/// A position of `ending` with White to move and a win at least
+/// `min_plies` from mate; `roll` is any random number.
+pub fn random_win(ending: Ending, min_plies: u32, roll: u32) -> Option<Grid> {
+    let table = table(ending);
+    let wins: Vec<usize> = (POSITIONS / 2..POSITIONS)
+        .filter(|&position| table.plies[position] as u32 > min_plies)
+        .collect();
+    if wins.is_empty() {
+        return None;
+    }
+    let (white_king, black_king, piece) = squares(wins[roll as usize % wins.len()]);
+    let mut ranks = vec![[None; 8]; 8];
+    let extra = Piece {
+        type_of_piece: ending.piece(),
+        color: ChessColor::White,
+    };
+    for (square, symbol) in [(white_king, 'K'), (black_king, 'k'), (piece, extra.fen_char())] {
+        ranks[7 - square / 8][square % 8] = Some(symbol);
+    }
+    let placement: Vec<String> = ranks
+        .iter()
+        .map(|rank| {
+            let mut text = String::new();
+            let mut empty = 0;
+            for square in rank {
+                match square {
+                    Some(symbol) => {
+                        if empty > 0 {
+                            text.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        text.push(*symbol);
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                text.push_str(&empty.to_string());
+            }
+            text
+        })
+        .collect();
+    Grid::from_fen(&format!("{} w - - 0 1", placement.join("/"))).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solved(fen: &str) -> Option<Outcome> {
+        for ending in Ending::ALL {
+            prepare(ending);
+        }
+        probe(&Grid::from_fen(fen).unwrap())
+    }
+
+    #[test]
+    fn known_results() {
+        assert_eq!(solved("k7/8/1K6/8/8/8/8/6Q1 w - - 0 1"), Some(Outcome::Win(1)));
+        assert_eq!(solved("k6Q/8/1K6/8/8/8/8/8 b - - 0 1"), Some(Outcome::Loss(0)));
+        assert_eq!(solved("k7/8/1Q6/8/8/8/8/K7 b - - 0 1"), Some(Outcome::Draw));
+        // The defending king in front of a rook pawn holds; a king on the
+        // sixth in front of any other pawn wins.
+        assert_eq!(solved("8/8/8/8/8/k7/P7/K7 w - - 0 1"), Some(Outcome::Draw));
+        assert!(matches!(solved("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1"), Some(Outcome::Loss(_))));
+        // Black's pieces are probed from the other side, and a hanging rook is a draw.
+        assert_eq!(solved("6q1/8/8/8/8/1k6/8/K7 b - - 0 1"), Some(Outcome::Win(1)));
+        assert_eq!(solved("8/8/8/8/8/8/6k1/K6R b - - 0 1"), Some(Outcome::Draw));
+    }
+
+    /// The longest mates are 10 moves in KQK and 16 in KRK.
+    #[test]
+    fn longest_mates() {
+        for (ending, moves) in [(Ending::Kqk, 10), (Ending::Krk, 16)] {
+            let longest = table(ending).plies[POSITIONS / 2..].iter().max().unwrap();
+            assert_eq!(Outcome::Win(*longest as u32 - 1).moves_to_mate(), Some(moves), "{}", ending.name());
+        }
+    }
+}
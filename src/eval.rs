@@ -0,0 +1,491 @@
+//! Hand-crafted evaluation.
+//!
+//! Every term is scored separately for the middlegame and the endgame and
+//! blended by how much material is left, so the same breakdown the search
+//! uses can be shown to the player.
+
+use crate::{
+    ChessColor, Grid, Piece, TypePiece, BISHOP_DIRECTIONS, KING_OFFSETS, KNIGHT_OFFSETS,
+    ROOK_DIRECTIONS,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Term {
+    Material,
+    PieceSquares,
+    PawnStructure,
+    KingSafety,
+    Mobility,
+    BishopPair,
+}
+
+pub const TERMS: [Term; 6] = [
+    Term::Material,
+    Term::PieceSquares,
+    Term::PawnStructure,
+    Term::KingSafety,
+    Term::Mobility,
+    Term::BishopPair,
+];
+
+impl Term {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Term::Material => "Material",
+            Term::PieceSquares => "Piece squares",
+            Term::PawnStructure => "Pawns",
+            Term::KingSafety => "King safety",
+            Term::Mobility => "Mobility",
+            Term::BishopPair => "Bishop pair",
+        }
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+/// A middlegame and an endgame score, in centipawns.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TaperedScore {
+    pub mg: i32,
+    pub eg: i32,
+}
+
+impl TaperedScore {
+    fn add(&mut self, color: ChessColor, mg: i32, eg: i32) {
+        let sign = if color == ChessColor::White { 1 } else { -1 };
+        self.mg += sign * mg;
+        self.eg += sign * eg;
+    }
+}
+
+/// Phase of a full board: knights and bishops count 1, rooks 2, queens 4.
+pub const MAX_PHASE: i32 = 24;
+
+/// Breakdown of a position's score, from White's point of view.
+#[derive(Clone, Copy, Debug)]
+pub struct Evaluation {
+    /// `MAX_PHASE` with all pieces on, 0 with only kings and pawns.
+    pub phase: i32,
+    terms: [TaperedScore; 6],
+}
+
+impl Evaluation {
+    pub fn term(&self, term: Term) -> TaperedScore {
+        self.terms[term.index()]
+    }
+
+    /// The term blended between its middlegame and endgame values.
+    pub fn tapered(&self, term: Term) -> i32 {
+        let score = self.term(term);
+        (score.mg * self.phase + score.eg * (MAX_PHASE - self.phase)) / MAX_PHASE
+    }
+
+    pub fn total(&self) -> i32 {
+        TERMS.iter().map(|&term| self.tapered(term)).sum()
+    }
+}
+
+/// Score for the side to move, as used by the search.
+pub fn evaluate(grid: &Grid) -> i32 {
+    let total = explain(grid).total();
+    match grid.side_to_move() {
+        ChessColor::White => total,
+        ChessColor::Black => -total,
+    }
+}
+
+fn piece_value(type_of_piece: TypePiece) -> (i32, i32) {
+    match type_of_piece {
+        TypePiece::Pawn => (82, 94),
+        TypePiece::Knight => (337, 281),
+        TypePiece::Bishop => (365, 297),
+        TypePiece::Rook => (477, 512),
+        TypePiece::Queen => (1025, 936),
+        TypePiece::King => (0, 0),
+    }
+}
+
+fn phase_weight(type_of_piece: TypePiece) -> i32 {
+    match type_of_piece {
+        TypePiece::Knight | TypePiece::Bishop => 1,
+        TypePiece::Rook => 2,
+        TypePiece::Queen => 4,
+        _ => 0,
+    }
+}
+
+// Piece-square tables as seen from White, rank 8 first.
+#[rustfmt::skip]
+const PAWN_MG: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+     5,  5, 10, 25, 25, 10,  5,  5,
+     0,  0,  0, 20, 20,  0,  0,  0,
+     5, -5,-10,  0,  0,-10, -5,  5,
+     5, 10, 10,-20,-20, 10, 10,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+#[rustfmt::skip]
+const PAWN_EG: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    80, 80, 80, 80, 80, 80, 80, 80,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    30, 30, 30, 30, 30, 30, 30, 30,
+    15, 15, 15, 15, 15, 15, 15, 15,
+     5,  5,  5,  5,  5,  5,  5,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+#[rustfmt::skip]
+const KNIGHT: [i32; 64] = [
+    -50,-40,-30,-30,-30,-30,-40,-50,
+    -40,-20,  0,  0,  0,  0,-20,-40,
+    -30,  0, 10, 15, 15, 10,  0,-30,
+    -30,  5, 15, 20, 20, 15,  5,-30,
+    -30,  0, 15, 20, 20, 15,  0,-30,
+    -30,  5, 10, 15, 15, 10,  5,-30,
+    -40,-20,  0,  5,  5,  0,-20,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50,
+];
+#[rustfmt::skip]
+const BISHOP: [i32; 64] = [
+    -20,-10,-10,-10,-10,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  5,  5, 10, 10,  5,  5,-10,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+    -10, 10, 10, 10, 10, 10, 10,-10,
+    -10,  5,  0,  0,  0,  0,  5,-10,
+    -20,-10,-10,-10,-10,-10,-10,-20,
+];
+#[rustfmt::skip]
+const ROOK: [i32; 64] = [
+      0,  0,  0,  0,  0,  0,  0,  0,
+      5, 10, 10, 10, 10, 10, 10,  5,
+     -5,  0,  0,  0,  0,  0,  0, -5,
+     -5,  0,  0,  0,  0,  0,  0, -5,
+     -5,  0,  0,  0,  0,  0,  0, -5,
+     -5,  0,  0,  0,  0,  0,  0, -5,
+     -5,  0,  0,  0,  0,  0,  0, -5,
+      0,  0,  0,  5,  5,  0,  0,  0,
+];
+#[rustfmt::skip]
+const QUEEN: [i32; 64] = [
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+     -5,  0,  5,  5,  5,  5,  0, -5,
+      0,  0,  5,  5,  5,  5,  0, -5,
+    -10,  5,  5,  5,  5,  5,  0,-10,
+    -10,  0,  5,  0,  0,  0,  0,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20,
+];
+#[rustfmt::skip]
+const KING_MG: [i32; 64] = [
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -20,-30,-30,-40,-40,-30,-30,-20,
+    -10,-20,-20,-20,-20,-20,-20,-10,
+     20, 20,  0,  0,  0,  0, 20, 20,
+     20, 30, 10,  0,  0, 10, 30, 20,
+];
+#[rustfmt::skip]
+const KING_EG: [i32; 64] = [
+    -50,-40,-30,-20,-20,-30,-40,-50,
+    -30,-20,-10,  0,  0,-10,-20,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-30,  0,  0,  0,  0,-30,-30,
+    -50,-30,-30,-30,-30,-30,-30,-50,
+];
+
+fn piece_square(piece: Piece, position: [usize; 2]) -> (i32, i32) {
+    let row = match piece.color {
+        ChessColor::White => 7 - position[1],
+        ChessColor::Black => position[1],
+    };
+    let index = row * 8 + position[0];
+    match piece.type_of_piece {
+        TypePiece::Pawn => (PAWN_MG[index], PAWN_EG[index]),
+        TypePiece::Knight => (KNIGHT[index], KNIGHT[index]),
+        TypePiece::Bishop => (BISHOP[index], BISHOP[index]),
+        TypePiece::Rook => (ROOK[index], ROOK[index]),
+        TypePiece::Queen => (QUEEN[index], QUEEN[index]),
+        TypePiece::King => (KING_MG[index], KING_EG[index]),
+    }
+}
+
+const DOUBLED: (i32, i32) = (-10, -20);
+const ISOLATED: (i32, i32) = (-10, -15);
+/// Passed pawn bonus by how many ranks the pawn has advanced.
+const PASSED_MG: [i32; 8] = [0, 5, 10, 15, 25, 40, 60, 0];
+const PASSED_EG: [i32; 8] = [0, 10, 20, 35, 60, 90, 130, 0];
+const BISHOP_PAIR: (i32, i32) = (30, 50);
+
+fn color_index(color: ChessColor) -> usize {
+    match color {
+        ChessColor::White => 0,
+        ChessColor::Black => 1,
+    }
+}
+
+/// Full breakdown of the position.
+pub fn explain(grid: &Grid) -> Evaluation {
+    let mut terms = [TaperedScore::default(); 6];
+    let mut phase = 0;
+    let mut bishops = [0; 2];
+    // Pawn ranks per file and colour, as a bitmask of rows.
+    let mut pawn_rows = [[0u8; 8]; 2];
+
+    for square in 0..64 {
+        let (col, row) = (square % 8, square / 8);
+        let Some(piece) = grid.find_cell([col, row]).piece else {
+            continue;
+        };
+        let (mg, eg) = piece_value(piece.type_of_piece);
+        terms[Term::Material.index()].add(piece.color, mg, eg);
+        let (mg, eg) = piece_square(piece, [col, row]);
+        terms[Term::PieceSquares.index()].add(piece.color, mg, eg);
+        phase += phase_weight(piece.type_of_piece);
+
+        match piece.type_of_piece {
+            TypePiece::Pawn => pawn_rows[color_index(piece.color)][col] |= 1 << row,
+            TypePiece::Bishop => bishops[color_index(piece.color)] += 1,
+            _ => {}
+        }
+        let (mg, eg) = mobility(grid, piece, [col, row]);
+        terms[Term::Mobility.index()].add(piece.color, mg, eg);
+    }
+
+    for color in [ChessColor::White, ChessColor::Black] {
+        let (mg, eg) = pawn_structure(&pawn_rows, color);
+        terms[Term::PawnStructure.index()].add(color, mg, eg);
+        let mg = king_safety(grid, &pawn_rows, color);
+        terms[Term::KingSafety.index()].add(color, mg, 0);
+        if bishops[color_index(color)] >= 2 {
+            terms[Term::BishopPair.index()].add(color, BISHOP_PAIR.0, BISHOP_PAIR.1);
+        }
+    }
+
+    Evaluation {
+        phase: phase.min(MAX_PHASE),
+        terms,
+    }
+}
+
+fn pawn_structure(pawn_rows: &[[u8; 8]; 2], color: ChessColor) -> (i32, i32) {
+    let own = &pawn_rows[color_index(color)];
+    let enemy = &pawn_rows[color_index(color.opposite())];
+    let (mut mg, mut eg) = (0, 0);
+    for file in 0..8 {
+        let count = own[file].count_ones() as i32;
+        if count == 0 {
+            continue;
+        }
+        if count > 1 {
+            mg += DOUBLED.0 * (count - 1);
+            eg += DOUBLED.1 * (count - 1);
+        }
+        let left = if file > 0 { own[file - 1] } else { 0 };
+        let right = if file < 7 { own[file + 1] } else { 0 };
+        if left == 0 && right == 0 {
+            mg += ISOLATED.0 * count;
+            eg += ISOLATED.1 * count;
+        }
+
+        for row in 0..8 {
+            if own[file] & (1 << row) == 0 {
+                continue;
+            }
+            // Rows in front of the pawn, from its own point of view.
+            let ahead: u8 = match color {
+                ChessColor::White => !((2u16 << row) - 1) as u8,
+                ChessColor::Black => ((1u16 << row) - 1) as u8,
+            };
+            let blockers = (file.saturating_sub(1)..=(file + 1).min(7))
+                .any(|f| enemy[f] & ahead != 0);
+            if !blockers {
+                let advanced = match color {
+                    ChessColor::White => row,
+                    ChessColor::Black => 7 - row,
+                };
+                mg += PASSED_MG[advanced];
+                eg += PASSED_EG[advanced];
+            }
+        }
+    }
+    (mg, eg)
+}
+
+/// Middlegame only: pawn shield, open files next to the king and enemy
+/// pieces close to it.
+fn king_safety(grid: &Grid, pawn_rows: &[[u8; 8]; 2], color: ChessColor) -> i32 {
+    let king = grid.find_king_position(color);
+    let own = &pawn_rows[color_index(color)];
+    let enemy = &pawn_rows[color_index(color.opposite())];
+    let forward: isize = if color == ChessColor::White { 1 } else { -1 };
+    let mut score = 0;
+
+    for file in king[0].saturating_sub(1)..=(king[0] + 1).min(7) {
+        for (distance, bonus) in [(1, 12), (2, 6)] {
+            let row = king[1] as isize + forward * distance;
+            if (0..8).contains(&row) && own[file] & (1 << row) != 0 {
+                score += bonus;
+            }
+        }
+        if own[file] == 0 {
+            score -= 20;
+            if enemy[file] == 0 {
+                score -= 10;
+            }
+        }
+    }
+
+    for col in 0..8 {
+        for row in 0..8 {
+            let Some(piece) = grid.find_cell([col, row]).piece else {
+                continue;
+            };
+            if piece.color == color {
+                continue;
+            }
+            let distance = col.abs_diff(king[0]).max(row.abs_diff(king[1]));
+            if distance <= 2 {
+                score -= match piece.type_of_piece {
+                    TypePiece::Queen => 30,
+                    TypePiece::Rook | TypePiece::Knight => 15,
+                    TypePiece::Bishop => 10,
+                    _ => 0,
+                };
+            }
+        }
+    }
+    score
+}
+
+/// Squares a piece reaches, above or below what it typically reaches.
+fn mobility(grid: &Grid, piece: Piece, position: [usize; 2]) -> (i32, i32) {
+    let (directions, sliding, baseline, weight) = match piece.type_of_piece {
+        TypePiece::Knight => (&KNIGHT_OFFSETS[..], false, 4, (4, 4)),
+        TypePiece::Bishop => (&BISHOP_DIRECTIONS[..], true, 7, (5, 5)),
+        TypePiece::Rook => (&ROOK_DIRECTIONS[..], true, 7, (2, 4)),
+        TypePiece::Queen => (&KING_OFFSETS[..], true, 14, (1, 2)),
+        _ => return (0, 0),
+    };
+    let mut count = 0;
+    for &(dc, dr) in directions {
+        let (mut col, mut row) = (position[0] as isize + dc, position[1] as isize + dr);
+        while (0..8).contains(&col) && (0..8).contains(&row) {
+            match grid.find_cell([col as usize, row as usize]).piece {
+                Some(other) => {
+                    if other.color != piece.color {
+                        count += 1;
+                    }
+                    break;
+                }
+                None => count += 1,
+            }
+            if !sliding {
+                break;
+            }
+            col += dc;
+            row += dr;
+        }
+    }
+    ((count - baseline) * weight.0, (count - baseline) * weight.1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The same position with the colours swapped and the board turned over.
+    fn mirror(fen: &str) -> String {
+        let fields: Vec<&str> = fen.split(' ').collect();
+        let swap_case = |text: &str| -> String {
+            text.chars()
+                .map(|c| if c.is_ascii_uppercase() { c.to_ascii_lowercase() } else { c.to_ascii_uppercase() })
+                .collect()
+        };
+        let board: Vec<String> = fields[0].split('/').rev().map(swap_case).collect();
+        let side = if fields[1] == "w" { "b" } else { "w" };
+        let mut castling: Vec<char> = swap_case(fields[2]).chars().collect();
+        castling.sort_by_key(|c| (c.is_ascii_lowercase(), !c.eq_ignore_ascii_case(&'k')));
+        let en_passant = match fields[3].as_bytes() {
+            [file, rank] => format!("{}{}", *file as char, (b'1' + b'8' - rank) as char),
+            _ => "-".to_string(),
+        };
+        let castling: String = castling.into_iter().collect();
+        format!("{} {} {} {} {} {}", board.join("/"), side, castling, en_passant, fields[4], fields[5])
+    }
+
+    fn eval(fen: &str) -> Evaluation {
+        explain(&Grid::from_fen(fen).unwrap())
+    }
+
+    #[test]
+    fn colour_symmetry() {
+        for fen in [
+            crate::START_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "r1bqkbnr/pp1ppppp/2n5/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq c6 0 3",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "6k1/5ppp/8/8/8/8/1Q3PPP/6K1 b - - 0 1",
+        ] {
+            let mirrored = mirror(fen);
+            assert_eq!(mirror(&mirrored), fen);
+            let (original, flipped) = (eval(fen), eval(&mirrored));
+            for term in TERMS {
+                assert_eq!(original.tapered(term), -flipped.tapered(term), "{} in {}", term.name(), fen);
+            }
+            let grid = Grid::from_fen(fen).unwrap();
+            assert_eq!(evaluate(&grid), evaluate(&Grid::from_fen(&mirrored).unwrap()), "{}", fen);
+        }
+    }
+
+    fn pawns(fen: &str) -> i32 {
+        eval(fen).tapered(Term::PawnStructure)
+    }
+
+    #[test]
+    fn pawn_structure_terms() {
+        // The e-pawn is passed unless the d-pawn can still take it.
+        assert!(pawns("4k3/p7/8/4P3/8/8/8/4K3 w - - 0 1") > pawns("4k3/3p4/8/4P3/8/8/8/4K3 w - - 0 1"));
+        // And worth more the further it has gone.
+        assert!(pawns("4k3/8/4P3/8/8/8/8/4K3 w - - 0 1") > pawns("4k3/8/8/8/4P3/8/8/4K3 w - - 0 1"));
+        // Isolated pawns are weaker than connected ones.
+        assert!(pawns("4k3/8/8/8/3PP3/8/8/4K3 w - - 0 1") > pawns("4k3/8/8/8/2P1P3/8/8/4K3 w - - 0 1"));
+        // Doubled pawns too.
+        assert!(pawns("4k3/8/8/8/8/5P2/3PP3/4K3 w - - 0 1") > pawns("4k3/8/8/8/8/4P3/3PP3/4K3 w - - 0 1"));
+    }
+
+    #[test]
+    fn bishop_pair() {
+        let pair = eval("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1");
+        assert_eq!(pair.term(Term::BishopPair), TaperedScore { mg: BISHOP_PAIR.0, eg: BISHOP_PAIR.1 });
+        let single = eval("4k3/8/8/8/8/8/8/1NB1K3 w - - 0 1");
+        assert_eq!(single.term(Term::BishopPair), TaperedScore::default());
+        let both = eval("2b1kb2/8/8/8/8/8/8/2B1KB2 w - - 0 1");
+        assert_eq!(both.term(Term::BishopPair), TaperedScore::default());
+    }
+
+    #[test]
+    fn king_safety_terms() {
+        let safety = |fen: &str| eval(fen).term(Term::KingSafety).mg;
+        // A pawn shield in front of the castled king.
+        let sheltered = safety("r3k3/pp6/8/8/8/8/5PPP/6K1 w - - 0 1");
+        assert!(sheltered > safety("r3k3/pp6/8/8/8/8/PPP5/6K1 w - - 0 1"));
+        // Pushed shield pawns protect less than ones at home.
+        assert!(sheltered > safety("r3k3/pp6/8/8/8/5PPP/8/6K1 w - - 0 1"));
+        // An enemy queen next to the king.
+        assert!(sheltered > safety("4k3/pp6/8/8/8/6q1/5PPP/6K1 w - - 0 1") + 20);
+    }
+}
@@ -0,0 +1,85 @@
+//! Hints for the player to move: the search's best move is given away in
+//! two steps, first the piece to move and then where it goes.
+
+use chess_wasm::search::{mate_distance, SearchLimits, Searcher};
+use chess_wasm::{Grid, Move};
+use macroquad::prelude::*;
+
+use crate::analysis::SearchSlice;
+
+const HINT_DEPTH: u32 = 5;
+const HINT_TT_MB: usize = 8;
+const HINT_COLOR: Color = Color::new(0.2, 0.6, 1.0, 0.45);
+
+pub struct Hint {
+    /// 0 when no hint was asked for, 1 for the piece, 2 for the destination.
+    stage: u8,
+    /// Hash of the position the hint is for.
+    position: u64,
+    best: Option<Move>,
+    searcher: Searcher,
+    slice: SearchSlice,
+}
+
+impl Default for Hint {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hint {
+    pub fn new() -> Self {
+        Hint {
+            stage: 0,
+            position: 0,
+            best: None,
+            searcher: Searcher::new(HINT_TT_MB),
+            slice: SearchSlice::new(),
+        }
+    }
+
+    /// Gives away one more step of the hint for `grid`.
+    pub fn request(&mut self, grid: &Grid) {
+        self.follow(grid);
+        self.stage = (self.stage + 1).min(2);
+    }
+
+    /// Forgets the hint once the position has changed.
+    fn follow(&mut self, grid: &Grid) {
+        if grid.position_hash() != self.position {
+            self.position = grid.position_hash();
+            self.stage = 0;
+            self.best = None;
+        }
+    }
+
+    /// Searches for the hint, a slice per frame, once one is asked for.
+    pub fn update(&mut self, grid: &Grid, history: &[u64]) {
+        self.follow(grid);
+        if self.stage == 0 || self.best.is_some() {
+            return;
+        }
+        let mut grid = grid.clone();
+        self.searcher.set_history(history);
+        let result = self.slice.search(&mut self.searcher, &mut grid, SearchLimits::depth(HINT_DEPTH));
+        if result.depth >= HINT_DEPTH || mate_distance(result.score).is_some() {
+            self.best = result.best_move;
+        }
+    }
+
+    /// Shades the piece to move and, at the second step, its destination.
+    pub fn draw(&self, cell_size: f32, offset_x: f32, offset_y: f32) {
+        let Some(best) = self.best else {
+            return;
+        };
+        let mut squares = vec![best.from];
+        if self.stage >= 2 {
+            squares.push(best.to);
+        }
+        for [col, row] in squares {
+            let x = offset_x + cell_size * col as f32;
+            let y = offset_y + cell_size * (7 - row) as f32;
+            draw_rectangle(x, y, cell_size, cell_size, HINT_COLOR);
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use macroquad::prelude::*;
+
+const MARGIN: f32 = 10.0;
+const PANEL_MIN_WIDTH: f32 = 220.0;
+const PANEL_MIN_HEIGHT: f32 = 240.0;
+const CLOCKS_HEIGHT: f32 = 60.0;
+const CAPTURED_HEIGHT: f32 = 80.0;
+const ENGINE_HEIGHT: f32 = 120.0;
+const TITLE_HEIGHT: f32 = 22.0;
+const EVAL_BAR_WIDTH: f32 = 16.0;
+
+pub const PANEL_BACKGROUND: Color = Color::new(0.16, 0.16, 0.18, 1.0);
+pub const PANEL_TEXT: Color = Color::new(0.9, 0.9, 0.9, 1.0);
+
+/// Where everything goes on screen for the current window size.
+///
+/// Recomputed every frame so the board and panels reflow when the window
+/// or the browser canvas is resized.
+#[derive(Clone, Copy, Debug)]
+pub struct Layout {
+    pub board: Rect,
+    /// Strip left of the board for the analysis evaluation bar.
+    pub eval_bar: Rect,
+    pub cell_size: f32,
+    pub clocks: Rect,
+    pub captured: Rect,
+    pub move_list: Rect,
+    pub engine_output: Rect,
+    pub portrait: bool,
+}
+
+impl Layout {
+    pub fn compute(width: f32, height: f32) -> Self {
+        if width >= height {
+            Self::landscape(width, height)
+        } else {
+            Self::portrait(width, height)
+        }
+    }
+
+    /// Board on the left, one column of panels on the right.
+    fn landscape(width: f32, height: f32) -> Self {
+        let panel_width = (width * 0.28).max(PANEL_MIN_WIDTH);
+        let board_size = (height - 2.0 * MARGIN)
+            .min(width - panel_width - EVAL_BAR_WIDTH - 4.0 * MARGIN)
+            .max(8.0);
+        let cell_size = (board_size / 8.0).floor();
+        let board_size = cell_size * 8.0;
+
+        let total_width = EVAL_BAR_WIDTH + MARGIN + board_size + MARGIN + panel_width;
+        let left = ((width - total_width) / 2.0).max(MARGIN);
+        let top = ((height - board_size) / 2.0).max(MARGIN);
+        let eval_bar = Rect::new(left, top, EVAL_BAR_WIDTH, board_size);
+        let board = Rect::new(eval_bar.right() + MARGIN, top, board_size, board_size);
+
+        let panel_x = board.right() + MARGIN;
+        let clocks = Rect::new(panel_x, top, panel_width, CLOCKS_HEIGHT);
+        let captured = Rect::new(panel_x, clocks.bottom() + MARGIN, panel_width, CAPTURED_HEIGHT);
+        let engine_output = Rect::new(
+            panel_x,
+            board.bottom() - ENGINE_HEIGHT,
+            panel_width,
+            ENGINE_HEIGHT,
+        );
+        let move_list_top = captured.bottom() + MARGIN;
+        let move_list = Rect::new(
+            panel_x,
+            move_list_top,
+            panel_width,
+            (engine_output.y - MARGIN - move_list_top).max(TITLE_HEIGHT),
+        );
+
+        Layout {
+            board,
+            eval_bar,
+            cell_size,
+            clocks,
+            captured,
+            move_list,
+            engine_output,
+            portrait: false,
+        }
+    }
+
+    /// Board on top, panels below in two columns. Used on phones.
+    fn portrait(width: f32, height: f32) -> Self {
+        let board_size = (width - EVAL_BAR_WIDTH - 3.0 * MARGIN)
+            .min(height - PANEL_MIN_HEIGHT - 3.0 * MARGIN)
+            .max(8.0);
+        let cell_size = (board_size / 8.0).floor();
+        let board_size = cell_size * 8.0;
+
+        let left = (width - board_size + EVAL_BAR_WIDTH + MARGIN) / 2.0;
+        let board = Rect::new(left, MARGIN, board_size, board_size);
+        let eval_bar = Rect::new(left - MARGIN - EVAL_BAR_WIDTH, MARGIN, EVAL_BAR_WIDTH, board_size);
+
+        let panels_top = board.bottom() + MARGIN;
+        let panels_height = (height - panels_top - MARGIN).max(PANEL_MIN_HEIGHT);
+        let column_width = (board_size - MARGIN) / 2.0;
+        let right_x = left + column_width + MARGIN;
+
+        let clocks = Rect::new(left, panels_top, column_width, CLOCKS_HEIGHT);
+        let captured = Rect::new(
+            left,
+            clocks.bottom() + MARGIN,
+            column_width,
+            panels_height - CLOCKS_HEIGHT - MARGIN,
+        );
+        let engine_height = ENGINE_HEIGHT.min(panels_height / 2.0);
+        let move_list = Rect::new(
+            right_x,
+            panels_top,
+            column_width,
+            panels_height - engine_height - MARGIN,
+        );
+        let engine_output = Rect::new(
+            right_x,
+            move_list.bottom() + MARGIN,
+            column_width,
+            engine_height,
+        );
+
+        Layout {
+            board,
+            eval_bar,
+            cell_size,
+            clocks,
+            captured,
+            move_list,
+            engine_output,
+            portrait: true,
+        }
+    }
+}
+
+/// Draws the background and title of a panel and returns the area left for
+/// its contents.
+pub fn draw_panel(rect: Rect, title: &str) -> Rect {
+    draw_rectangle(rect.x, rect.y, rect.w, rect.h, PANEL_BACKGROUND);
+    draw_text(title, rect.x + 6.0, rect.y + 16.0, 18.0, PANEL_TEXT);
+    panel_content(rect)
+}
+
+/// Area of a panel left for its contents, below the title.
+pub fn panel_content(rect: Rect) -> Rect {
+    Rect::new(
+        rect.x + 6.0,
+        rect.y + TITLE_HEIGHT,
+        rect.w - 12.0,
+        (rect.h - TITLE_HEIGHT).max(0.0),
+    )
+}
@@ -0,0 +1,1264 @@
+pub mod book;
+pub mod eco;
+pub mod endgame;
+pub mod eval;
+mod movegen;
+mod san;
+pub mod search;
+mod see;
+pub mod syzygy;
+pub mod tt;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod uci_engine;
+pub mod zobrist;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TypePiece {
+    King,
+    Queen,
+    Rook,
+    Bishop,
+    Knight,
+    Pawn,
+}
+impl TypePiece {
+    /// Standard material value in pawns.
+    pub fn value(&self) -> i32 {
+        match self {
+            TypePiece::King => 0,
+            TypePiece::Queen => 9,
+            TypePiece::Rook => 5,
+            TypePiece::Bishop => 3,
+            TypePiece::Knight => 3,
+            TypePiece::Pawn => 1,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ChessColor {
+    White,
+    Black,
+}
+impl ChessColor {
+    pub fn opposite(&self) -> ChessColor {
+        match self {
+            ChessColor::White => ChessColor::Black,
+            ChessColor::Black => ChessColor::White,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Piece {
+    pub type_of_piece: TypePiece,
+    pub color: ChessColor,
+}
+impl Piece {
+    pub fn from_fen_char(symbol: char) -> Option<Piece> {
+        let type_of_piece = match symbol.to_ascii_lowercase() {
+            'k' => TypePiece::King,
+            'q' => TypePiece::Queen,
+            'r' => TypePiece::Rook,
+            'b' => TypePiece::Bishop,
+            'n' => TypePiece::Knight,
+            'p' => TypePiece::Pawn,
+            _ => return None,
+        };
+        let color = if symbol.is_ascii_uppercase() {
+            ChessColor::White
+        } else {
+            ChessColor::Black
+        };
+        Some(Piece {
+            type_of_piece,
+            color,
+        })
+    }
+
+    pub fn fen_char(&self) -> char {
+        let symbol = match self.type_of_piece {
+            TypePiece::King => 'k',
+            TypePiece::Queen => 'q',
+            TypePiece::Rook => 'r',
+            TypePiece::Bishop => 'b',
+            TypePiece::Knight => 'n',
+            TypePiece::Pawn => 'p',
+        };
+        match self.color {
+            ChessColor::White => symbol.to_ascii_uppercase(),
+            ChessColor::Black => symbol,
+        }
+    }
+}
+/// A move from one square to another; castling is the king's two-square
+/// move, or in Chess960 the king taking its own rook, and en passant the
+/// pawn's diagonal step onto the en passant square.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Move {
+    pub from: [usize; 2],
+    pub to: [usize; 2],
+    pub promotion: Option<TypePiece>,
+}
+
+impl Move {
+    pub fn new(from: [usize; 2], to: [usize; 2]) -> Self {
+        Move {
+            from,
+            to,
+            promotion: None,
+        }
+    }
+
+    /// Long algebraic notation as used by UCI, e.g. `e2e4` or `e7e8q`.
+    pub fn to_uci(&self) -> String {
+        let mut text = Grid::position_to_chess_notation(self.from) + &Grid::position_to_chess_notation(self.to);
+        if let Some(promotion) = self.promotion {
+            let piece = Piece {
+                type_of_piece: promotion,
+                color: ChessColor::Black,
+            };
+            text.push(piece.fen_char());
+        }
+        text
+    }
+}
+
+/// Everything `Grid::make_move` overwrote, so the move can be taken back.
+#[derive(Clone, Copy, Debug)]
+pub struct Undo {
+    pub captured: Option<Piece>,
+    pub captured_position: [usize; 2],
+    pub rook_move: Option<([usize; 2], [usize; 2])>,
+    castling_rights: u8,
+    en_passant: Option<[usize; 2]>,
+    halfmove_clock: u32,
+    fullmove_number: u32,
+    hash: u64,
+}
+
+const KNIGHT_OFFSETS: [(isize, isize); 8] = [(1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1), (-2, 1), (-1, 2)];
+const KING_OFFSETS: [(isize, isize); 8] = [(1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1), (0, -1), (1, -1)];
+const ROOK_DIRECTIONS: [(isize, isize); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+const BISHOP_DIRECTIONS: [(isize, isize); 4] = [(1, 1), (-1, 1), (-1, -1), (1, -1)];
+
+#[derive(Clone, Copy, Debug)]
+pub struct Cell {
+    pub position: [usize; 2],
+    pub piece: Option<Piece>,
+}
+
+pub const WHITE_KINGSIDE: u8 = 1;
+pub const WHITE_QUEENSIDE: u8 = 2;
+pub const BLACK_KINGSIDE: u8 = 4;
+pub const BLACK_QUEENSIDE: u8 = 8;
+pub const ALL_CASTLING: u8 = WHITE_KINGSIDE | WHITE_QUEENSIDE | BLACK_KINGSIDE | BLACK_QUEENSIDE;
+
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+/// Number of Chess960 starting positions; number 518 is the usual one.
+pub const CHESS960_POSITIONS: usize = 960;
+/// Knight placements among the five squares left, by Scharnagl number.
+const CHESS960_KNIGHTS: [(usize, usize); 10] =
+    [(0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4)];
+
+#[derive(Debug, Clone)]
+pub struct Grid {
+    cells: [[Cell; 8]; 8],
+    side_to_move: ChessColor,
+    castling_rights: u8,
+    en_passant: Option<[usize; 2]>,
+    halfmove_clock: u32,
+    fullmove_number: u32,
+    hash: u64,
+    /// Castling is written as the king taking its own rook.
+    chess960: bool,
+    /// Starting column of the rook for each castling right, in bit order.
+    castling_rooks: [usize; 4],
+}
+
+impl Default for Grid {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Grid {
+    pub fn is_move_legal(&mut self, from_position: [usize; 2], to_position: [usize; 2]) -> bool {
+        if {
+            match self.find_cell(from_position).piece.unwrap().type_of_piece {
+                TypePiece::Pawn => self.is_pawn_move_legal(from_position, to_position),
+                TypePiece::Bishop => self.is_bishop_move_legal(from_position, to_position),
+                TypePiece::Rook => self.is_rook_move_legal(from_position, to_position),
+                TypePiece::Queen => {
+                    self.is_bishop_move_legal(from_position, to_position)
+                        || self.is_rook_move_legal(from_position, to_position)
+                }
+                TypePiece::King => self.is_king_move_legal(from_position, to_position),
+                TypePiece::Knight => self.is_knight_move_legal(from_position, to_position),
+            }
+        } && !self.does_move_put_king_in_check(from_position, to_position)
+        {
+            return true;
+        }
+        false
+    }
+    pub fn does_move_put_king_in_check(&mut self, from_position: [usize; 2], to_position: [usize; 2],) -> bool {
+        let king_color = self.find_cell(from_position).piece.unwrap().color;
+        let mv = Move::new(from_position, to_position);
+        let undo = self.make_move(mv);
+        let king_position = self.find_king_position(king_color);
+        let in_check = self.is_square_attacked(king_position, king_color);
+        self.unmake_move(mv, undo);
+        in_check
+    }
+    /// Whether any piece of the other colour attacks `position`, whether or
+    /// not that piece is pinned.
+    pub fn is_square_attacked(&self, position: [usize; 2], defending_color: ChessColor) -> bool {
+        Self::is_attacked_on(position, defending_color.opposite(), |square| {
+            self.cells[square[0]][square[1]].piece
+        })
+    }
+    /// Attack test that reads the board through `piece_at`, so a caller can
+    /// ask about a slightly different board without building it.
+    fn is_attacked_on(
+        position: [usize; 2],
+        attacking_color: ChessColor,
+        piece_at: impl Fn([usize; 2]) -> Option<Piece>,
+    ) -> bool {
+        let attacker_at = |col: isize, row: isize, types: &[TypePiece]| {
+            (0..8).contains(&col)
+                && (0..8).contains(&row)
+                && piece_at([col as usize, row as usize]).is_some_and(|piece| {
+                    piece.color == attacking_color && types.contains(&piece.type_of_piece)
+                })
+        };
+        let (col, row) = (position[0] as isize, position[1] as isize);
+
+        let pawn_row = match attacking_color {
+            ChessColor::White => row - 1,
+            ChessColor::Black => row + 1,
+        };
+        if attacker_at(col - 1, pawn_row, &[TypePiece::Pawn])
+            || attacker_at(col + 1, pawn_row, &[TypePiece::Pawn])
+        {
+            return true;
+        }
+        if KNIGHT_OFFSETS
+            .iter()
+            .any(|&(dc, dr)| attacker_at(col + dc, row + dr, &[TypePiece::Knight]))
+        {
+            return true;
+        }
+        if KING_OFFSETS
+            .iter()
+            .any(|&(dc, dr)| attacker_at(col + dc, row + dr, &[TypePiece::King]))
+        {
+            return true;
+        }
+
+        for (directions, sliders) in [
+            (&ROOK_DIRECTIONS, [TypePiece::Rook, TypePiece::Queen]),
+            (&BISHOP_DIRECTIONS, [TypePiece::Bishop, TypePiece::Queen]),
+        ] {
+            for &(dc, dr) in directions.iter() {
+                let (mut c, mut r) = (col + dc, row + dr);
+                while (0..8).contains(&c) && (0..8).contains(&r) {
+                    if piece_at([c as usize, r as usize]).is_some() {
+                        if attacker_at(c, r, &sliders) {
+                            return true;
+                        }
+                        break;
+                    }
+                    c += dc;
+                    r += dr;
+                }
+            }
+        }
+        false
+    }
+
+    /// Neither side can ever mate: bare kings, a single minor piece, or
+    /// only bishops that all stand on squares of one colour.
+    pub fn is_insufficient_material(&self) -> bool {
+        let mut minors = Vec::new();
+        for column in self.cells.iter() {
+            for cell in column.iter() {
+                if let Some(piece) = cell.piece {
+                    match piece.type_of_piece {
+                        TypePiece::King => {}
+                        TypePiece::Knight | TypePiece::Bishop => minors.push((piece, cell.position)),
+                        _ => return false,
+                    }
+                }
+            }
+        }
+        if minors.len() <= 1 {
+            return true;
+        }
+        let square_colour = |position: [usize; 2]| (position[0] + position[1]) % 2;
+        let first_colour = square_colour(minors[0].1);
+        minors.iter().all(|(piece, position)| {
+            piece.type_of_piece == TypePiece::Bishop && square_colour(*position) == first_colour
+        })
+    }
+
+    /// Material of White minus material of Black, in pawns.
+    pub fn material_balance(&self) -> i32 {
+        self.cells
+            .iter()
+            .flatten()
+            .filter_map(|cell| cell.piece)
+            .map(|piece| match piece.color {
+                ChessColor::White => piece.type_of_piece.value(),
+                ChessColor::Black => -piece.type_of_piece.value(),
+            })
+            .sum()
+    }
+
+    /// Whether `color` can never deliver mate, not even with the other side's
+    /// help, used to score a flag-fall as a draw. A lone minor piece can
+    /// still mate a king hemmed in by its own men, so only a lone king or a
+    /// position neither side can win counts.
+    pub fn has_insufficient_material(&self, color: ChessColor) -> bool {
+        if self.is_insufficient_material() {
+            return true;
+        }
+        self.cells
+            .iter()
+            .flatten()
+            .filter_map(|cell| cell.piece)
+            .all(|piece| piece.color != color || piece.type_of_piece == TypePiece::King)
+    }
+
+    pub fn find_king_position(&self, color: ChessColor) -> [usize; 2] {
+        for col in 0..8 {
+            for row in 0..8 {
+                if let Some(piece) = &self.cells[col][row].piece {
+                    if piece.type_of_piece == TypePiece::King && piece.color == color {
+                        return [col, row];
+                    }
+                }
+            }
+        }
+        panic!("King not found on the board!");
+    }
+    pub fn is_knight_move_legal(&self, from_position: [usize; 2], to_position: [usize; 2]) -> bool {
+        let row_diff = (to_position[1] as isize - from_position[1] as isize).abs();
+        let col_diff = (to_position[0] as isize - from_position[0] as isize).abs();
+        if (col_diff == 2 || row_diff == 2) && (col_diff == 1 || row_diff == 1) {
+            if self.find_cell(to_position).piece.is_none() {
+                return true;
+            }
+            if self.find_cell(from_position).piece.unwrap().color
+                != self.find_cell(to_position).piece.unwrap().color
+            {
+                return true;
+            }
+        }
+        false
+    }
+    pub fn is_king_move_legal(&self, from_position: [usize; 2], to_position: [usize; 2]) -> bool {
+        let row_diff = to_position[1] as isize - from_position[1] as isize;
+        let col_diff = to_position[0] as isize - from_position[0] as isize;
+        if col_diff.abs() <= 1 && row_diff.abs() <= 1 {
+            if self.find_cell(to_position).piece.is_none() {
+                return true;
+            } else {
+                return self.find_cell(from_position).piece.unwrap().color
+                    != self.find_cell(to_position).piece.unwrap().color;
+            }
+        }
+        false
+    }
+    pub fn is_path_clear(&self, from_position: [usize; 2], to_position: [usize; 2]) -> bool {
+        let mut row = from_position[1] as isize;
+        let mut col = from_position[0] as isize;
+        let row_diff = to_position[1] as isize - row;
+        let col_diff = to_position[0] as isize - col;
+
+        let row_step = if row_diff == 0 {
+            0
+        } else {
+            row_diff / row_diff.abs()
+        };
+        let col_step = if col_diff == 0 {
+            0
+        } else {
+            col_diff / col_diff.abs()
+        };
+
+        row += row_step;
+        col += col_step;
+
+        while row != to_position[1] as isize || col != to_position[0] as isize {
+            if self.find_cell([col as usize, row as usize]).piece.is_some() {
+                return false;
+            }
+            row += row_step;
+            col += col_step;
+        }
+
+        true
+    }
+    pub fn is_rook_move_legal(&self, from_position: [usize; 2], to_position: [usize; 2]) -> bool {
+        if (from_position[0] == to_position[0] || from_position[1] == to_position[1])
+            && self.is_path_clear(from_position, to_position)
+        {
+            if self.find_cell(to_position).piece.is_none() {
+                return true;
+            } else {
+                if self.find_cell(from_position).piece.unwrap().color
+                    != self.find_cell(to_position).piece.unwrap().color
+                {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+    pub fn is_bishop_move_legal(&self, from_position: [usize; 2], to_position: [usize; 2]) -> bool {
+        let row_diff = to_position[1] as isize - from_position[1] as isize;
+        let col_diff = to_position[0] as isize - from_position[0] as isize;
+        if (row_diff.abs() == col_diff.abs()) && self.is_path_clear(from_position, to_position) {
+            if self.find_cell(to_position).piece.is_none() {
+                true
+            } else {
+                self.find_cell(from_position).piece.unwrap().color
+                    != self.find_cell(to_position).piece.unwrap().color
+            }
+        } else {
+            false
+        }
+    }
+    pub fn is_pawn_move_legal(&self, from_position: [usize; 2], to_position: [usize; 2]) -> bool {
+        let direction = if self.find_cell(from_position).piece.unwrap().color == ChessColor::White {
+            1
+        } else {
+            -1
+        };
+
+        let row_diff = (to_position[1] as isize - from_position[1] as isize) * direction;
+        let col_diff = (to_position[0] as isize - from_position[0] as isize).abs();
+
+        match (row_diff, col_diff) {
+            (1, 0) => {
+                self.find_cell(to_position).piece.is_none()
+                    && self.is_path_clear(from_position, to_position)
+            } // Simple movement
+            (2, 0) if from_position[1] == 1 || from_position[1] == 6 => {
+                self.find_cell(to_position).piece.is_none()
+                    && self.is_path_clear(from_position, to_position)
+            } // Double initial movement
+            (1, 1) => match self.find_cell(to_position).piece {
+                Some(target) => target.color != self.find_cell(from_position).piece.unwrap().color,
+                None => self.en_passant == Some(to_position),
+            } // Diagonal capture, en passant included
+            _ => false,
+        }
+    }
+
+    pub fn full_positions(&mut self) {
+        for i in 0..8 {
+            for j in 0..8 {
+                self.cells[i][j].position = [i, j];
+            }
+        }
+    }
+    pub fn find_cell(&self, position: [usize; 2]) -> &Cell {
+        let [column, row] = position;
+
+        if column < 8 && row < 8 {
+            &self.cells[column][row]
+        } else {
+            panic!("Cell dont exist!");
+        }
+    }
+    fn find_cell_mut(&mut self, position: [usize; 2]) -> &mut Cell {
+        let [column, row] = position;
+
+        if column < 8 && row < 8 {
+            &mut self.cells[column][row]
+        } else {
+            panic!("Cell dont exist!");
+        }
+    }
+
+    pub fn initialize_pieces(&mut self) {
+        let white_pieces = [
+            (TypePiece::Rook, [0, 0]),
+            (TypePiece::Knight, [1, 0]),
+            (TypePiece::Bishop, [2, 0]),
+            (TypePiece::Queen, [3, 0]),
+            (TypePiece::King, [4, 0]),
+            (TypePiece::Bishop, [5, 0]),
+            (TypePiece::Knight, [6, 0]),
+            (TypePiece::Rook, [7, 0]),
+        ];
+
+        for &(type_of_piece, position) in white_pieces.iter() {
+            let cell = self.find_cell_mut(position);
+            cell.piece = Some(Piece {
+                type_of_piece,
+                color: ChessColor::White,
+            });
+        }
+
+        for column in 0..8 {
+            let cell = self.find_cell_mut([column, 1]);
+            cell.piece = Some(Piece {
+                type_of_piece: TypePiece::Pawn,
+                color: ChessColor::White,
+            });
+        }
+
+        let black_pieces = [
+            (TypePiece::Rook, [0, 7]),
+            (TypePiece::Knight, [1, 7]),
+            (TypePiece::Bishop, [2, 7]),
+            (TypePiece::Queen, [3, 7]),
+            (TypePiece::King, [4, 7]),
+            (TypePiece::Bishop, [5, 7]),
+            (TypePiece::Knight, [6, 7]),
+            (TypePiece::Rook, [7, 7]),
+        ];
+
+        for &(type_of_piece, position) in black_pieces.iter() {
+            let cell = self.find_cell_mut(position);
+            cell.piece = Some(Piece {
+                type_of_piece,
+                color: ChessColor::Black,
+            });
+        }
+
+        for column in 0..8 {
+            let cell = self.find_cell_mut([column, 6]);
+            cell.piece = Some(Piece {
+                type_of_piece: TypePiece::Pawn,
+                color: ChessColor::Black,
+            });
+        }
+    }
+
+    pub fn new() -> Self {
+        let cells = [[Cell {
+            position: [0, 0],
+            piece: None,
+        }; 8]; 8];
+        let mut grid = Grid {
+            cells,
+            side_to_move: ChessColor::White,
+            castling_rights: ALL_CASTLING,
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            hash: 0,
+            chess960: false,
+            castling_rooks: [7, 0, 7, 0],
+        };
+        grid.full_positions();
+        grid.initialize_pieces();
+        grid.hash = grid.compute_hash();
+        grid
+    }
+
+    /// Chess960 starting position number `index`, counted as Scharnagl
+    /// does: the bishops, the queen and the knights are placed in turn and
+    /// the king goes between the rooks.
+    pub fn new_chess960(index: usize) -> Result<Grid, String> {
+        if index >= CHESS960_POSITIONS {
+            return Err(format!("Chess960 positions go from 0 to {}", CHESS960_POSITIONS - 1));
+        }
+        let mut rank = [None; 8];
+        let mut rest = index;
+        rank[(rest % 4) * 2 + 1] = Some('b');
+        rest /= 4;
+        rank[(rest % 4) * 2] = Some('b');
+        rest /= 4;
+        let empty = |rank: &[Option<char>; 8]| (0..8).filter(|&column| rank[column].is_none()).collect::<Vec<_>>();
+        rank[empty(&rank)[rest % 6]] = Some('q');
+        rest /= 6;
+        let (first, second) = CHESS960_KNIGHTS[rest];
+        let free = empty(&rank);
+        rank[free[first]] = Some('n');
+        rank[free[second]] = Some('n');
+        let free = empty(&rank);
+        for (column, symbol) in free.into_iter().zip(['r', 'k', 'r']) {
+            rank[column] = Some(symbol);
+        }
+        let black: String = rank.iter().flatten().collect();
+        let rooks: String = (0..8).filter(|&column| rank[column] == Some('r')).map(|column| (b'a' + column as u8) as char).collect();
+        let rights = format!("{}{}", rooks.to_ascii_uppercase(), rooks);
+        let fen = format!("{}/pppppppp/8/8/8/8/PPPPPPPP/{} w {} - 0 1", black, black.to_ascii_uppercase(), rights);
+        let mut grid = Grid::from_fen(&fen)?;
+        grid.chess960 = true;
+        Ok(grid)
+    }
+
+    /// Whether castling is written as the king taking its own rook, as
+    /// Chess960 needs.
+    pub fn chess960(&self) -> bool {
+        self.chess960
+    }
+
+    /// Switches how castling is written, e.g. for the UCI `UCI_Chess960`
+    /// option. Standard games are played the same either way.
+    pub fn set_chess960(&mut self, chess960: bool) {
+        self.chess960 = chess960;
+    }
+
+    pub fn side_to_move(&self) -> ChessColor {
+        self.side_to_move
+    }
+
+    pub fn castling_rights(&self) -> u8 {
+        self.castling_rights
+    }
+
+    /// Square a pawn can capture onto en passant, only set when an enemy
+    /// pawn is actually next to the pawn that just made a double step.
+    pub fn en_passant(&self) -> Option<[usize; 2]> {
+        self.en_passant
+    }
+
+    /// Plies since the last capture or pawn move.
+    pub fn halfmove_clock(&self) -> u32 {
+        self.halfmove_clock
+    }
+
+    /// Zobrist key of the position, kept up to date as moves are applied.
+    pub fn position_hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// Hashes the position from scratch.
+    pub fn compute_hash(&self) -> u64 {
+        let mut hash = 0;
+        for column in self.cells.iter() {
+            for cell in column.iter() {
+                if let Some(piece) = cell.piece {
+                    hash ^= zobrist::piece_key(piece, cell.position);
+                }
+            }
+        }
+        if self.side_to_move == ChessColor::Black {
+            hash ^= zobrist::side_key();
+        }
+        hash ^= zobrist::castling_key(self.castling_rights);
+        if let Some([file, _]) = self.en_passant {
+            hash ^= zobrist::en_passant_key(file);
+        }
+        hash
+    }
+
+    fn set_piece(&mut self, position: [usize; 2], piece: Option<Piece>) {
+        if let Some(old) = self.find_cell(position).piece {
+            self.hash ^= zobrist::piece_key(old, position);
+        }
+        if let Some(new) = piece {
+            self.hash ^= zobrist::piece_key(new, position);
+        }
+        self.find_cell_mut(position).piece = piece;
+    }
+
+    fn set_castling_rights(&mut self, rights: u8) {
+        self.hash ^= zobrist::castling_key(self.castling_rights) ^ zobrist::castling_key(rights);
+        self.castling_rights = rights;
+    }
+
+    fn set_en_passant(&mut self, en_passant: Option<[usize; 2]>) {
+        if let Some([file, _]) = self.en_passant {
+            self.hash ^= zobrist::en_passant_key(file);
+        }
+        if let Some([file, _]) = en_passant {
+            self.hash ^= zobrist::en_passant_key(file);
+        }
+        self.en_passant = en_passant;
+    }
+
+    fn switch_side(&mut self) {
+        if self.side_to_move == ChessColor::Black {
+            self.fullmove_number += 1;
+        }
+        self.side_to_move = self.side_to_move.opposite();
+        self.hash ^= zobrist::side_key();
+    }
+
+    /// Whether a pawn of `color` could take en passant on `target`, i.e.
+    /// one of its pawns stands next to the enemy pawn that just passed it.
+    fn can_capture_en_passant(&self, target: [usize; 2], color: ChessColor) -> bool {
+        let (target_row, pawn_row) = match color {
+            ChessColor::White => (5, 4),
+            ChessColor::Black => (2, 3),
+        };
+        if target[1] != target_row {
+            return false;
+        }
+        [-1isize, 1].iter().any(|&side| {
+            let column = target[0] as isize + side;
+            (0..8).contains(&column)
+                && self.find_cell([column as usize, pawn_row]).piece
+                    == Some(Piece {
+                        type_of_piece: TypePiece::Pawn,
+                        color,
+                    })
+        })
+    }
+
+    /// Castling rights bit for `color` castling on the king's side or the
+    /// queen's.
+    fn castling_right(color: ChessColor, kingside: bool) -> u8 {
+        match (color, kingside) {
+            (ChessColor::White, true) => WHITE_KINGSIDE,
+            (ChessColor::White, false) => WHITE_QUEENSIDE,
+            (ChessColor::Black, true) => BLACK_KINGSIDE,
+            (ChessColor::Black, false) => BLACK_QUEENSIDE,
+        }
+    }
+
+    /// Starting square of the rook castling with `right`.
+    fn castling_rook(&self, right: u8) -> [usize; 2] {
+        let row = if right & (WHITE_KINGSIDE | WHITE_QUEENSIDE) != 0 { 0 } else { 7 };
+        [self.castling_rooks[right.trailing_zeros() as usize], row]
+    }
+
+    /// Where the king goes when castling puts the rook on `rook_to`.
+    fn castling_king_square(rook_to: [usize; 2]) -> [usize; 2] {
+        [if rook_to[0] == 5 { 6 } else { 2 }, rook_to[1]]
+    }
+
+    /// The rook's move if `mv` is shaped like castling: the king two files
+    /// over, or in Chess960 the king onto its own rook. Rights and attacks
+    /// are not looked at.
+    pub fn castling_rook_move(&self, mv: Move) -> Option<([usize; 2], [usize; 2])> {
+        let king = self.find_cell(mv.from).piece?;
+        if king.type_of_piece != TypePiece::King || mv.to[1] != mv.from[1] {
+            return None;
+        }
+        let kingside = mv.to[0] > mv.from[0];
+        let rook_from = if self.chess960 {
+            let rook = Piece {
+                type_of_piece: TypePiece::Rook,
+                color: king.color,
+            };
+            if self.find_cell(mv.to).piece != Some(rook) {
+                return None;
+            }
+            mv.to
+        } else {
+            if mv.from[0].abs_diff(mv.to[0]) != 2 {
+                return None;
+            }
+            self.castling_rook(Self::castling_right(king.color, kingside))
+        };
+        Some((rook_from, [if kingside { 5 } else { 3 }, mv.from[1]]))
+    }
+
+    /// The square the moving piece ends on, which for castling in Chess960
+    /// is not the one the move names.
+    pub fn landing_square(&self, mv: Move) -> [usize; 2] {
+        match self.castling_rook_move(mv) {
+            Some((_, rook_to)) => Self::castling_king_square(rook_to),
+            None => mv.to,
+        }
+    }
+
+    /// The castling move for `right`, written the way this game writes it.
+    fn castling_move(&self, right: u8) -> Move {
+        let rook = self.castling_rook(right);
+        let color = if rook[1] == 0 { ChessColor::White } else { ChessColor::Black };
+        let king = self.find_king_position(color);
+        if self.chess960 {
+            Move::new(king, rook)
+        } else {
+            Move::new(king, [if rook[0] > king[0] { 6 } else { 2 }, king[1]])
+        }
+    }
+
+    /// Castling moves for the side to move that the rules allow.
+    pub(crate) fn castling_moves(&self) -> impl Iterator<Item = Move> + '_ {
+        let color = self.side_to_move;
+        [true, false]
+            .into_iter()
+            .map(move |kingside| Self::castling_right(color, kingside))
+            .filter(|&right| self.castling_rights & right != 0)
+            .map(|right| self.castling_move(right))
+            .filter(|mv| self.is_move_castling(mv.from, mv.to))
+    }
+
+    /// Whether the king may castle with this move: the right is still
+    /// there, the rook is in place, nothing but the two of them stands
+    /// between where they are and where they go, and the king is not in
+    /// check and crosses no attacked square.
+    pub fn is_move_castling(&self, from_position: [usize; 2], to_position: [usize; 2]) -> bool {
+        let Some((rook_from, rook_to)) = self.castling_rook_move(Move::new(from_position, to_position)) else {
+            return false;
+        };
+        let Some(king) = self.find_cell(from_position).piece else {
+            return false;
+        };
+        let right = Self::castling_right(king.color, rook_to[0] == 5);
+        let king_row = if king.color == ChessColor::White { 0 } else { 7 };
+        if self.castling_rights & right == 0 || from_position[1] != king_row || self.castling_rook(right) != rook_from {
+            return false;
+        }
+        let rook = Piece {
+            type_of_piece: TypePiece::Rook,
+            color: king.color,
+        };
+        if self.find_cell(rook_from).piece != Some(rook) {
+            return false;
+        }
+
+        let king_to = Self::castling_king_square(rook_to);
+        let columns = [from_position[0], king_to[0], rook_from[0], rook_to[0]];
+        let (first, last) = (*columns.iter().min().unwrap(), *columns.iter().max().unwrap());
+        let in_the_way = (first..=last).any(|col| {
+            let square = [col, king_row];
+            square != from_position && square != rook_from && self.find_cell(square).piece.is_some()
+        });
+        if in_the_way {
+            return false;
+        }
+        // Both pieces are lifted, so a rook shielding the king's path from
+        // along the rank does not count.
+        let lifted = |square: [usize; 2]| {
+            if square == from_position || square == rook_from {
+                None
+            } else {
+                self.cells[square[0]][square[1]].piece
+            }
+        };
+        let (first, last) = (from_position[0].min(king_to[0]), from_position[0].max(king_to[0]));
+        (first..=last).all(|col| !Self::is_attacked_on([col, king_row], king.color.opposite(), lifted))
+    }
+
+    /// Parses a position in Forsyth-Edwards Notation.
+    pub fn from_fen(fen: &str) -> Result<Grid, String> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.len() < 4 {
+            return Err(format!("FEN needs at least 4 fields: {}", fen));
+        }
+
+        let mut grid = Grid::new();
+        for column in grid.cells.iter_mut() {
+            for cell in column.iter_mut() {
+                cell.piece = None;
+            }
+        }
+
+        let ranks: Vec<&str> = fields[0].split('/').collect();
+        if ranks.len() != 8 {
+            return Err(format!("FEN board needs 8 ranks: {}", fields[0]));
+        }
+        for (i, rank) in ranks.iter().enumerate() {
+            let row = 7 - i;
+            let mut column = 0;
+            for symbol in rank.chars() {
+                if let Some(empty) = symbol.to_digit(10) {
+                    column += empty as usize;
+                    continue;
+                }
+                let piece = Piece::from_fen_char(symbol)
+                    .ok_or_else(|| format!("Unknown piece '{}' in FEN", symbol))?;
+                if column >= 8 {
+                    return Err(format!("Too many squares in FEN rank {}", rank));
+                }
+                grid.cells[column][row].piece = Some(piece);
+                column += 1;
+            }
+            if column != 8 {
+                return Err(format!("FEN rank {} does not have 8 squares", rank));
+            }
+        }
+
+        grid.side_to_move = match fields[1] {
+            "w" => ChessColor::White,
+            "b" => ChessColor::Black,
+            other => return Err(format!("Unknown side to move '{}'", other)),
+        };
+
+        grid.castling_rights = 0;
+        if fields[2] != "-" {
+            for symbol in fields[2].chars() {
+                grid.add_castling_right(symbol)?;
+            }
+        }
+
+        grid.en_passant = None;
+        if fields[3] != "-" {
+            let target = Grid::chess_notation_to_position(fields[3])
+                .ok_or_else(|| format!("Bad en passant square '{}'", fields[3]))?;
+            if grid.can_capture_en_passant(target, grid.side_to_move) {
+                grid.en_passant = Some(target);
+            }
+        }
+
+        grid.halfmove_clock = match fields.get(4) {
+            Some(field) => field.parse().map_err(|_| format!("Bad halfmove clock '{}'", field))?,
+            None => 0,
+        };
+        grid.fullmove_number = match fields.get(5) {
+            Some(field) => field.parse().map_err(|_| format!("Bad fullmove number '{}'", field))?,
+            None => 1,
+        };
+
+        for color in [ChessColor::White, ChessColor::Black] {
+            let kings = grid
+                .cells
+                .iter()
+                .flatten()
+                .filter(|cell| {
+                    cell.piece
+                        == Some(Piece {
+                            type_of_piece: TypePiece::King,
+                            color,
+                        })
+                })
+                .count();
+            if kings != 1 {
+                return Err(format!("FEN must have exactly one {:?} king", color));
+            }
+        }
+
+        grid.hash = grid.compute_hash();
+        Ok(grid)
+    }
+
+    pub fn to_fen(&self) -> String {
+        let mut board = String::new();
+        for row in (0..8).rev() {
+            let mut empty = 0;
+            for column in 0..8 {
+                match self.cells[column][row].piece {
+                    Some(piece) => {
+                        if empty > 0 {
+                            board.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        board.push(piece.fen_char());
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                board.push_str(&empty.to_string());
+            }
+            if row > 0 {
+                board.push('/');
+            }
+        }
+
+        let side = match self.side_to_move {
+            ChessColor::White => "w",
+            ChessColor::Black => "b",
+        };
+        let mut castling: String = [
+            (WHITE_KINGSIDE, 'K'),
+            (WHITE_QUEENSIDE, 'Q'),
+            (BLACK_KINGSIDE, 'k'),
+            (BLACK_QUEENSIDE, 'q'),
+        ]
+        .iter()
+        .filter(|(right, _)| self.castling_rights & right != 0)
+        .map(|&(right, symbol)| self.castling_symbol(right, symbol))
+        .collect();
+        if castling.is_empty() {
+            castling.push('-');
+        }
+        let en_passant = match self.en_passant {
+            Some(target) => Grid::position_to_chess_notation(target),
+            None => "-".to_string(),
+        };
+        format!(
+            "{} {} {} {} {} {}",
+            board, side, castling, en_passant, self.halfmove_clock, self.fullmove_number
+        )
+    }
+
+    /// Reads one castling right in FEN: `KQkq`, taking the outermost rook
+    /// on that side as X-FEN does, or the rook's file as in Shredder-FEN.
+    /// Rooks anywhere but the corners, or a king off the e-file, make the
+    /// game Chess960.
+    fn add_castling_right(&mut self, symbol: char) -> Result<(), String> {
+        let color = if symbol.is_ascii_uppercase() { ChessColor::White } else { ChessColor::Black };
+        let row = if color == ChessColor::White { 0 } else { 7 };
+        let rook = Some(Piece {
+            type_of_piece: TypePiece::Rook,
+            color,
+        });
+        let king = (0..8).find(|&col| {
+            self.cells[col][row].piece
+                == Some(Piece {
+                    type_of_piece: TypePiece::King,
+                    color,
+                })
+        });
+        let (kingside, column) = match symbol.to_ascii_lowercase() {
+            'k' => {
+                let first = king.map_or(5, |king| king + 1);
+                (true, (first..8).rev().find(|&col| self.cells[col][row].piece == rook).unwrap_or(7))
+            }
+            'q' => (false, (0..king.unwrap_or(4)).find(|&col| self.cells[col][row].piece == rook).unwrap_or(0)),
+            file @ 'a'..='h' => {
+                let column = file as usize - 'a' as usize;
+                let king = king.ok_or_else(|| format!("Castling right '{}' without a king on its rank", symbol))?;
+                (column > king, column)
+            }
+            _ => return Err(format!("Unknown castling right '{}'", symbol)),
+        };
+        let right = Self::castling_right(color, kingside);
+        self.castling_rights |= right;
+        self.castling_rooks[right.trailing_zeros() as usize] = column;
+        if king != Some(4) || column != if kingside { 7 } else { 0 } {
+            self.chess960 = true;
+        }
+        Ok(())
+    }
+
+    /// X-FEN letter for `right`: `symbol` when its rook is the outermost
+    /// one on that side, which is always so in standard chess, or else the
+    /// rook's file.
+    fn castling_symbol(&self, right: u8, symbol: char) -> char {
+        let [column, row] = self.castling_rook(right);
+        let rook = self.find_cell([column, row]).piece;
+        let outer = if right & (WHITE_KINGSIDE | BLACK_KINGSIDE) != 0 { column + 1..8 } else { 0..column };
+        if outer.into_iter().any(|col| rook.is_some() && self.cells[col][row].piece == rook) {
+            let file = (b'a' + column as u8) as char;
+            if symbol.is_ascii_uppercase() { file.to_ascii_uppercase() } else { file }
+        } else {
+            symbol
+        }
+    }
+
+    /// Castling rights lost when a piece leaves or lands on `position`.
+    fn castling_rights_touched(&self, position: [usize; 2]) -> u8 {
+        [WHITE_KINGSIDE, WHITE_QUEENSIDE, BLACK_KINGSIDE, BLACK_QUEENSIDE]
+            .into_iter()
+            .filter(|&right| self.castling_rook(right) == position)
+            .fold(0, |lost, right| lost | right)
+    }
+
+    /// Plays `mv` on the board and hands the turn over. Castling and en
+    /// passant are recognised from the king and pawn moves themselves.
+    /// The returned record is what `unmake_move` needs to take it back.
+    pub fn make_move(&mut self, mv: Move) -> Undo {
+        let piece = self
+            .find_cell(mv.from)
+            .piece
+            .expect("make_move called without a piece on the from square");
+        let castling = self.castling_rook_move(mv);
+        let mut undo = Undo {
+            captured: if castling.is_some() { None } else { self.find_cell(mv.to).piece },
+            captured_position: mv.to,
+            rook_move: None,
+            castling_rights: self.castling_rights,
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+            fullmove_number: self.fullmove_number,
+            hash: self.hash,
+        };
+
+        if piece.type_of_piece == TypePiece::Pawn
+            && undo.captured.is_none()
+            && mv.from[0] != mv.to[0]
+            && Some(mv.to) == self.en_passant
+        {
+            undo.captured_position = [mv.to[0], mv.from[1]];
+            undo.captured = self.find_cell(undo.captured_position).piece;
+            self.set_piece(undo.captured_position, None);
+        }
+
+        // In Chess960 the king and rook may land on each other's squares,
+        // so both are lifted before either is put down.
+        let mut to = mv.to;
+        let mut rook = None;
+        if let Some((rook_from, rook_to)) = castling {
+            rook = self.find_cell(rook_from).piece;
+            self.set_piece(rook_from, None);
+            to = Self::castling_king_square(rook_to);
+            undo.rook_move = Some((rook_from, rook_to));
+        }
+
+        let placed = match mv.promotion {
+            Some(type_of_piece) => Piece {
+                type_of_piece,
+                color: piece.color,
+            },
+            None => piece,
+        };
+        self.set_piece(mv.from, None);
+        if let Some((_, rook_to)) = undo.rook_move {
+            self.set_piece(rook_to, rook);
+        }
+        self.set_piece(to, Some(placed));
+
+        if undo.captured.is_some() || piece.type_of_piece == TypePiece::Pawn {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+
+        let mut lost = self.castling_rights_touched(mv.from) | self.castling_rights_touched(mv.to);
+        if piece.type_of_piece == TypePiece::King {
+            lost |= Self::castling_right(piece.color, true) | Self::castling_right(piece.color, false);
+        }
+        self.set_castling_rights(self.castling_rights & !lost);
+
+        let mut en_passant = None;
+        if piece.type_of_piece == TypePiece::Pawn && mv.from[1].abs_diff(mv.to[1]) == 2 {
+            let target = [mv.from[0], (mv.from[1] + mv.to[1]) / 2];
+            if self.can_capture_en_passant(target, piece.color.opposite()) {
+                en_passant = Some(target);
+            }
+        }
+        self.set_en_passant(en_passant);
+        self.switch_side();
+        undo
+    }
+
+    /// Takes back `mv`, which must be the last move made with `make_move`.
+    pub fn unmake_move(&mut self, mv: Move, undo: Undo) {
+        if let Some((rook_from, rook_to)) = undo.rook_move {
+            let king = self.find_cell_mut(Self::castling_king_square(rook_to)).piece.take();
+            let rook = self.find_cell_mut(rook_to).piece.take();
+            self.find_cell_mut(rook_from).piece = rook;
+            self.find_cell_mut(mv.from).piece = king;
+        } else {
+            let placed = self.find_cell(mv.to).piece;
+            let piece = match (placed, mv.promotion) {
+                (Some(piece), Some(_)) => Some(Piece {
+                    type_of_piece: TypePiece::Pawn,
+                    color: piece.color,
+                }),
+                (placed, None) => placed,
+                (None, Some(_)) => None,
+            };
+            self.find_cell_mut(mv.to).piece = None;
+            self.find_cell_mut(mv.from).piece = piece;
+            self.find_cell_mut(undo.captured_position).piece = undo.captured;
+        }
+
+        self.side_to_move = self.side_to_move.opposite();
+        self.castling_rights = undo.castling_rights;
+        self.en_passant = undo.en_passant;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.fullmove_number = undo.fullmove_number;
+        self.hash = undo.hash;
+    }
+
+    pub fn position_to_chess_notation(position: [usize; 2]) -> String {
+        let column = (position[0] + 'a' as usize) as u8 as char;
+        let row = (position[1] + 1).to_string();
+        format!("{}{}", column, row)
+    }
+
+    /// The legal move written as `text` in UCI notation.
+    pub fn parse_uci_move(&self, text: &str) -> Option<Move> {
+        self.legal_moves().into_iter().find(|mv| mv.to_uci() == text)
+    }
+
+    pub fn chess_notation_to_position(notation: &str) -> Option<[usize; 2]> {
+        let bytes = notation.as_bytes();
+        if bytes.len() != 2 || !(b'a'..=b'h').contains(&bytes[0]) || !(b'1'..=b'8').contains(&bytes[1]) {
+            return None;
+        }
+        Some([(bytes[0] - b'a') as usize, (bytes[1] - b'1') as usize])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(grid: &mut Grid, moves: &[&str]) {
+        for text in moves {
+            let mv = grid.parse_uci_move(text).unwrap_or_else(|| panic!("{} is not legal", text));
+            grid.make_move(mv);
+        }
+    }
+
+    #[test]
+    fn chess960_castling_rights_in_fen() {
+        // Rooks in the corners are written KQkq whichever way they came in.
+        for rights in ["HAha", "KQkq"] {
+            let fen = format!("rk5r/8/8/8/8/8/8/RK5R w {} - 0 1", rights);
+            let grid = Grid::from_fen(&fen).unwrap();
+            assert!(grid.chess960());
+            assert_eq!(grid.castling_rights(), ALL_CASTLING);
+            assert_eq!(grid.to_fen(), "rk5r/8/8/8/8/8/8/RK5R w KQkq - 0 1");
+        }
+        // The standard position reads the same either way.
+        let grid = Grid::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1").unwrap();
+        assert!(!grid.chess960());
+        assert_eq!(grid.to_fen(), START_FEN);
+        // A right to the inner of two rooks on one side needs its file.
+        let fen = "1rr1k3/8/8/8/8/8/8/1RR1K3 w Cc - 0 1";
+        assert_eq!(Grid::from_fen(fen).unwrap().to_fen(), fen);
+        for index in [0, 518, 959] {
+            let grid = Grid::new_chess960(index).unwrap();
+            let again = Grid::from_fen(&grid.to_fen()).unwrap();
+            assert_eq!(again.to_fen(), grid.to_fen());
+            assert_eq!(again.position_hash(), grid.position_hash());
+        }
+    }
+
+    #[test]
+    fn chess960_castling() {
+        // The king takes its own rook and they end up on g1 and f1.
+        let mut grid = Grid::from_fen("rk5r/8/8/8/8/8/8/RK5R w KQkq - 0 1").unwrap();
+        play(&mut grid, &["b1h1"]);
+        assert_eq!(grid.to_fen(), "rk5r/8/8/8/8/8/8/R4RK1 b kq - 1 1");
+        // Queenside the king goes to c8 and the rook to d8, past the king's own square.
+        play(&mut grid, &["b8a8"]);
+        assert_eq!(grid.to_fen(), "2kr3r/8/8/8/8/8/8/R4RK1 w - - 2 2");
+
+        // Castling where the king does not move, only the rook.
+        let mut grid = Grid::from_fen("6kr/8/8/8/8/8/8/6KR w Kk - 0 1").unwrap();
+        play(&mut grid, &["g1h1"]);
+        assert_eq!(grid.to_fen(), "6kr/8/8/8/8/8/8/5RK1 b k - 1 1");
+        // And where the rook stays put while the king moves onto its square.
+        let mut grid = Grid::from_fen("2rk4/8/8/8/8/8/8/R2K4 b Qq - 0 1").unwrap();
+        play(&mut grid, &["d8c8"]);
+        assert_eq!(grid.to_fen(), "2kr4/8/8/8/8/8/8/R2K4 w Q - 1 2");
+
+        // Not through an attacked square, nor with a piece in the way.
+        let grid = Grid::from_fen("r3k3/8/8/8/8/8/8/1RK1r3 w B - 0 1").unwrap();
+        assert!(grid.parse_uci_move("c1b1").is_none());
+        let grid = Grid::from_fen("4k3/8/8/8/8/8/8/RNK5 w A - 0 1").unwrap();
+        assert!(grid.parse_uci_move("c1a1").is_none());
+
+        // Taken back, everything is where it was.
+        let mut grid = Grid::from_fen("rk5r/8/8/8/8/8/8/RK5R w KQkq - 0 1").unwrap();
+        let (fen, hash) = (grid.to_fen(), grid.position_hash());
+        for mv in grid.legal_moves() {
+            let undo = grid.make_move(mv);
+            assert_eq!(grid.position_hash(), grid.compute_hash());
+            grid.unmake_move(mv, undo);
+            assert_eq!((grid.to_fen(), grid.position_hash()), (fen.clone(), hash));
+        }
+    }
+
+    #[test]
+    fn material_balance_counts_promoted_pieces() {
+        assert_eq!(Grid::new().material_balance(), 0);
+        // A pawn queening is worth eight pawns more, though nothing was taken.
+        let mut grid = Grid::from_fen("k7/4P3/8/8/8/8/8/K7 w - - 0 1").unwrap();
+        assert_eq!(grid.material_balance(), 1);
+        play(&mut grid, &["e7e8q"]);
+        assert_eq!(grid.material_balance(), 9);
+    }
+
+    #[test]
+    fn flag_fall_material() {
+        // A knight can still mate a king boxed in by its own pawn.
+        let grid = Grid::from_fen("7k/7p/8/8/8/8/8/K5N1 w - - 0 1").unwrap();
+        assert!(!grid.has_insufficient_material(ChessColor::White));
+        assert!(!grid.has_insufficient_material(ChessColor::Black));
+        // A lone king never mates, and a lone minor not against a lone king.
+        let grid = Grid::from_fen("7k/8/8/8/8/8/8/K5N1 w - - 0 1").unwrap();
+        assert!(grid.has_insufficient_material(ChessColor::White));
+        assert!(grid.has_insufficient_material(ChessColor::Black));
+        let grid = Grid::from_fen("7k/8/8/8/8/8/8/KQ6 w - - 0 1").unwrap();
+        assert!(!grid.has_insufficient_material(ChessColor::White));
+        assert!(grid.has_insufficient_material(ChessColor::Black));
+    }
+}
@@ -1,28 +1,142 @@
-use std::panic;
+use std::sync::Arc;
 
 use macroquad::prelude::*;
 use macroquad::window::Conf;
 
+use chess_wasm::book::Book;
+use chess_wasm::endgame::{self, Ending};
+use chess_wasm::eco::{EcoTable, Opening};
+use chess_wasm::eval::{self, TERMS};
+use chess_wasm::search;
+use chess_wasm::syzygy::Tablebase;
+use chess_wasm::{ChessColor, Grid, Move, Piece, TypePiece, CHESS960_POSITIONS, START_FEN};
+
+mod analysis;
+mod animation;
+mod clock;
+mod hint;
+mod layout;
+mod record;
+mod review;
+mod sound;
+mod storage;
+mod theme;
+mod trainer;
+#[cfg(not(target_arch = "wasm32"))]
+mod uci_client;
+use analysis::Analysis;
+use animation::Animations;
+use clock::{Clock, ClockEvent};
+use hint::Hint;
+use layout::{draw_panel, panel_content, Layout, PANEL_TEXT};
+use record::{GameRecord, GameResult};
+use review::Review;
+use sound::{SoundEffect, Sounds};
+use theme::{load_piece_set, BoardPalette, Theme};
+use trainer::Trainer;
+
+#[cfg(not(target_arch = "wasm32"))]
+const BOOK_KEY: &str = "book";
+#[cfg(not(target_arch = "wasm32"))]
+const SYZYGY_KEY: &str = "syzygy";
+#[cfg(not(target_arch = "wasm32"))]
+const PGN_FILE: &str = "game.pgn";
+
 pub struct App {
-    turn: ChessColor,
     grid: Grid,
     selected_piece: Option<(usize, usize)>,
-    log: Vec<([usize;2], [usize;2])>
+    log: Vec<([usize;2], [usize;2])>,
+    captured: Vec<Piece>,
+    history: Vec<u64>,
+    snapshots: Vec<Snapshot>,
+    animations: Animations,
+    clock: Clock,
+    pending_sounds: Vec<SoundEffect>,
+    last_result: Option<GameResult>,
+    /// The game that ended last, for reviewing it.
+    last_game: Option<GameRecord>,
+    /// Plies at which a hint was taken. Kept through undo, so taking one
+    /// back does not hide that a hint was used.
+    hints: Vec<usize>,
+    book: Book,
+    eco: EcoTable,
+    /// Deepest named opening position the game has reached.
+    opening: Option<Opening>,
+    /// Short message shown under the move list until the next move.
+    notice: Option<String>,
+    /// Starting position number of a Chess960 game.
+    chess960: Option<usize>,
+    hovered: Option<[usize; 2]>,
+}
+
+/// Board as it was before a move, kept so the move can be undone.
+struct Snapshot {
+    grid: Grid,
+    mv: Move,
+    log_len: usize,
+    capture: Option<(Piece, [usize; 2])>,
+    /// The move is in the opening book.
+    book: bool,
+}
+impl Default for App {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 impl App {
     pub fn new() -> Self {
+        let grid = Grid::new();
         App {
-            turn: ChessColor::White,
-            grid: Grid::new(),
+            history: vec![grid.position_hash()],
+            grid,
             selected_piece: None,
-            log: Vec::new()
+            log: Vec::new(),
+            captured: Vec::new(),
+            snapshots: Vec::new(),
+            animations: Animations::new(),
+            clock: Clock::default(),
+            pending_sounds: Vec::new(),
+            last_result: None,
+            last_game: None,
+            hints: Vec::new(),
+            book: Book::default(),
+            eco: EcoTable::bundled(),
+            opening: None,
+            notice: None,
+            chess960: None,
+            hovered: None,
         }
     }
-    pub fn default(&mut self){
+    pub fn reset(&mut self){
         self.grid = Grid::new();
         self.log = Vec::new();
-        self.turn = ChessColor::White;
         self.selected_piece = None;
+        self.captured = Vec::new();
+        self.history = vec![self.grid.position_hash()];
+        self.snapshots = Vec::new();
+        self.hints = Vec::new();
+        self.opening = None;
+        self.chess960 = None;
+        self.animations.skip();
+        self.clock = Clock::default();
+    }
+    /// Starts a new game from `grid` instead of the usual position.
+    pub fn start_from(&mut self, grid: Grid) {
+        self.reset();
+        self.history = vec![grid.position_hash()];
+        self.grid = grid;
+        self.last_result = None;
+        self.notice = None;
+    }
+    /// Starts a Chess960 game from starting position number `index`.
+    pub fn start_chess960(&mut self, index: usize) {
+        match Grid::new_chess960(index) {
+            Ok(grid) => {
+                self.start_from(grid);
+                self.chess960 = Some(index);
+            }
+            Err(error) => self.notice = Some(error),
+        }
     }
     
     pub fn move_piece(
@@ -33,680 +147,761 @@ impl App {
         offset_x: f32,
         offset_y: f32,
     ) {
-        let col = ((mouse_x - offset_x) / cell_size) as usize;
-        let row = 7 - ((mouse_y - offset_y) / cell_size) as usize;
-
-        if col < 8 && row < 8 {
+        if let Some([col, row]) = square_at(mouse_x, mouse_y, cell_size, offset_x, offset_y) {
             match self.selected_piece {
                 None => {
-                    if self.grid.cells[col][row].piece.is_some() {
-                        if self.grid.cells[col][row].piece.unwrap().color == self.turn{
+                    if let Some(piece) = self.grid.find_cell([col, row]).piece {
+                        if piece.color == self.grid.side_to_move() {
                             self.selected_piece = Some((col, row));
                         }
                     }
                 }
                 Some((from_col, from_row)) => {
-                    if self.is_move_castling([from_col, from_row], [col, row]) {
-                        let king = self.grid.cells[from_col][from_row].piece.take();
-                        self.grid.cells[col][row].piece = king;
-    
-                        let (rook_from_col, rook_to_col) = if col == 6 {
-                            (7, 5) 
-                        } else {
-                            (0, 2) 
-                        };
-                        let rook = self.grid.cells[rook_from_col][row].piece.take();
-                        self.grid.cells[rook_to_col][row].piece = rook;
-    
-                        self.add_log(([from_col, from_row], [col, row]));
-                        self.add_log(([rook_from_col, row], [rook_to_col, row]));
-                        self.turn = self.turn.opposite();
-                        
-                    } 
-                        else if self.grid.is_move_legal([from_col, from_row], [col, row]) {
-                        let piece = self.grid.cells[from_col][from_row].piece.take();
-                        self.grid.cells[col][row].piece = piece;
-                        self.selected_piece = None;
-                        self.add_log(([from_col, from_row], [col, row]));
-                        self.turn = self.turn.opposite();
-                    }
-                    
+                    let from = [from_col, from_row];
+                    let to = [col, row];
+                    let promotion = self.promotion_for(from, to);
+                    // Castling is played by moving the king onto its square
+                    // or, as Chess960 writes it, onto the rook.
+                    let moves = self.grid.legal_moves();
+                    let legal = moves
+                        .iter()
+                        .find(|mv| mv.from == from && mv.to == to && mv.promotion == promotion)
+                        .or_else(|| moves.iter().find(|mv| mv.from == from && self.grid.landing_square(**mv) == to))
+                        .copied();
                     self.selected_piece = None;
+                    if let Some(mv) = legal {
+                        self.play(mv);
+                    }
                 }
             }
         }
-        if self.is_checkmate(ChessColor::White) || self.is_checkmate(ChessColor::Black){
-            self.default();
+    }
+    /// Plays a legal move and ends the game if it was the last one.
+    pub fn play(&mut self, mv: Move) {
+        let before = self.grid.clone();
+        let log_len = self.log.len();
+        let undo = self.grid.make_move(mv);
+        self.add_log((mv.from, before.landing_square(mv)));
+        if let Some(rook_move) = undo.rook_move {
+            self.add_log(rook_move);
+        }
+        let capture = undo.captured.map(|piece| (piece, undo.captured_position));
+        if let Some((piece, _)) = capture {
+            self.captured.push(piece);
+        }
+
+        self.animate_moves(&before, log_len, capture);
+        self.snapshots.push(Snapshot {
+            book: self.book.contains(&before, mv),
+            grid: before,
+            mv,
+            log_len,
+            capture,
+        });
+        self.history.push(self.grid.position_hash());
+        self.opening = self.eco.deepest(self.opening.as_ref(), &self.grid).cloned();
+        self.notice = None;
+        self.clock.add_increment(self.grid.side_to_move().opposite());
+        let effect = self.move_sound(
+            undo.rook_move.is_some(),
+            capture.is_some(),
+            mv.promotion.is_some(),
+        );
+        self.pending_sounds.push(effect);
+
+        if self.grid.is_checkmate() {
+            self.game_over(GameResult::Checkmate(self.grid.side_to_move().opposite()));
+        } else if let Some(reason) = search::automatic_draw(&self.grid, self.earlier_positions()) {
+            self.game_over(GameResult::Draw(reason));
+        }
+    }
+    /// Plays a move given in UCI notation, if it is legal.
+    pub fn play_uci_move(&mut self, text: &str) -> bool {
+        match self.grid.parse_uci_move(text) {
+            Some(mv) => {
+                self.selected_piece = None;
+                self.play(mv);
+                true
+            }
+            None => false,
+        }
+    }
+    /// The game being played.
+    pub fn record(&self) -> GameRecord {
+        GameRecord {
+            start: self.snapshots.first().map_or(&self.grid, |snapshot| &snapshot.grid).clone(),
+            moves: self.snapshots.iter().map(|snapshot| snapshot.mv).collect(),
+            hints: self.hints.iter().copied().filter(|&ply| ply < self.snapshots.len()).collect(),
+            result: None,
+        }
+    }
+    /// Notes that the player to move took a hint.
+    pub fn use_hint(&mut self) {
+        let ply = self.snapshots.len();
+        if !self.hints.contains(&ply) {
+            self.hints.push(ply);
+        }
+    }
+    /// The game in progress if it has started, otherwise the last one played.
+    pub fn game_to_review(&self) -> Option<GameRecord> {
+        if self.snapshots.is_empty() {
+            self.last_game.clone()
+        } else {
+            Some(self.record())
         }
     }
+    /// The game `game_to_review` picks, in PGN.
+    pub fn pgn(&self) -> Option<String> {
+        let record = self.game_to_review()?;
+        let opening = self.eco.classify(&record.start, &record.moves);
+        Some(record.to_pgn(opening))
+    }
+    /// Exports the game to review as PGN and says where it went.
+    pub fn export_pgn(&mut self) {
+        self.notice = Some(match self.pgn() {
+            Some(pgn) => export_pgn(&pgn),
+            None => "No game to export".to_string(),
+        });
+    }
+    /// The game so far as a UCI `position` command.
+    pub fn uci_position(&self) -> String {
+        let record = self.record();
+        let start = record.start.to_fen();
+        let mut command = if start == START_FEN {
+            "position startpos".to_string()
+        } else {
+            format!("position fen {}", start)
+        };
+        if !record.moves.is_empty() {
+            command.push_str(" moves");
+            for mv in &record.moves {
+                command.push(' ');
+                command.push_str(&mv.to_uci());
+            }
+        }
+        command
+    }
+    pub fn grid(&self) -> &Grid {
+        &self.grid
+    }
+    pub fn side_to_move(&self) -> ChessColor {
+        self.grid.side_to_move()
+    }
+    fn game_over(&mut self, result: GameResult) {
+        self.pending_sounds.clear();
+        self.pending_sounds.push(SoundEffect::GameOver);
+        self.last_game = Some(GameRecord {
+            result: Some(result),
+            ..self.record()
+        });
+        self.reset();
+        self.last_result = Some(result);
+    }
+    /// Hashes of the positions before the current one, oldest first.
+    pub fn earlier_positions(&self) -> &[u64] {
+        &self.history[..self.history.len() - 1]
+    }
+    pub fn claim_draw(&mut self) {
+        if let Some(reason) = search::claimable_draw(&self.grid, self.earlier_positions()) {
+            self.game_over(GameResult::Draw(reason));
+        }
+    }
+    fn move_sound(&self, castled: bool, captured: bool, promoted: bool) -> SoundEffect {
+        let king_position = self.grid.find_king_position(self.grid.side_to_move());
+        if self.grid.is_square_attacked(king_position, self.grid.side_to_move()) {
+            SoundEffect::Check
+        } else if promoted {
+            SoundEffect::Promotion
+        } else if castled {
+            SoundEffect::Castle
+        } else if captured {
+            SoundEffect::Capture
+        } else {
+            SoundEffect::Move
+        }
+    }
+    /// Sounds triggered since the last call, for the caller to play.
+    pub fn take_sounds(&mut self) -> Vec<SoundEffect> {
+        std::mem::take(&mut self.pending_sounds)
+    }
     pub fn add_log(&mut self, position: ([usize;2],[usize;2])){
         self.log.push(position);
     }
+    /// Takes back the last move, castling included.
+    pub fn undo(&mut self) {
+        let Some(snapshot) = self.snapshots.pop() else {
+            return;
+        };
+        self.animations.restart();
+        for &(from, to) in &self.log[snapshot.log_len..] {
+            if let Some(piece) = snapshot.grid.find_cell(from).piece {
+                self.animations.slide(piece, to, from);
+            }
+        }
+        if let Some((piece, position)) = snapshot.capture {
+            self.animations.fade_in(piece, position);
+            self.captured.pop();
+        }
+        self.grid = snapshot.grid;
+        self.log.truncate(snapshot.log_len);
+        self.history.pop();
+        self.selected_piece = None;
+        let record = self.record();
+        self.opening = self.eco.classify(&record.start, &record.moves).cloned();
+        self.notice = None;
+    }
+    fn animate_moves(&mut self, before: &Grid, log_len: usize, capture: Option<(Piece, [usize; 2])>) {
+        self.animations.restart();
+        for &(from, to) in &self.log[log_len..] {
+            if let Some(piece) = before.find_cell(from).piece {
+                self.animations.slide(piece, from, to);
+            }
+        }
+        if let Some((piece, position)) = capture {
+            self.animations.fade_out(piece, position);
+        }
+    }
+    /// Remembers the square under the mouse for the capture warning.
+    pub fn hover(&mut self, mouse_x: f32, mouse_y: f32, cell_size: f32, offset_x: f32, offset_y: f32) {
+        self.hovered = square_at(mouse_x, mouse_y, cell_size, offset_x, offset_y);
+    }
+
+    /// Whether the selected piece taking on the hovered square loses
+    /// material once the exchange there is played out.
+    fn is_losing_capture_hovered(&self) -> bool {
+        let (Some((col, row)), Some(to)) = (self.selected_piece, self.hovered) else {
+            return false;
+        };
+        let from = [col, row];
+        let promotion = self.promotion_for(from, to);
+        self.grid
+            .legal_moves()
+            .into_iter()
+            .find(|mv| mv.from == from && mv.to == to && mv.promotion == promotion)
+            .is_some_and(|mv| {
+                let target = self.grid.find_cell(to).piece;
+                target.is_some_and(|piece| piece.color != self.grid.side_to_move()) && self.grid.see(mv) < 0
+            })
+    }
+    /// Pawns reaching the last rank always become queens.
+    fn promotion_for(&self, from: [usize; 2], to: [usize; 2]) -> Option<TypePiece> {
+        let piece = self.grid.find_cell(from).piece?;
+        if piece.type_of_piece == TypePiece::Pawn && (to[1] == 0 || to[1] == 7) {
+            Some(TypePiece::Queen)
+        } else {
+            None
+        }
+    }
+    /// Pieces taken by `color`, most valuable first.
+    pub fn captured_by(&self, color: ChessColor) -> Vec<Piece> {
+        let mut pieces: Vec<Piece> = self
+            .captured
+            .iter()
+            .copied()
+            .filter(|piece| piece.color != color)
+            .collect();
+        pieces.sort_by_key(|piece| std::cmp::Reverse(piece.type_of_piece.value()));
+        pieces
+    }
+    /// Advances animations and, while `clock_running`, the clock.
+    pub fn update(&mut self, frame_time: f32, clock_running: bool) {
+        self.animations.update(frame_time);
+        if self.log.is_empty() || !clock_running {
+            return;
+        }
+        match self.clock.tick(self.grid.side_to_move(), frame_time) {
+            Some(ClockEvent::LowTime(_)) => self.pending_sounds.push(SoundEffect::LowTime),
+            Some(ClockEvent::Flag(color)) => {
+                let result = if self.grid.has_insufficient_material(color.opposite()) {
+                    GameResult::TimeoutDraw
+                } else {
+                    GameResult::Timeout(color.opposite())
+                };
+                self.game_over(result);
+            }
+            None => {}
+        }
+    }
+    pub fn skip_animations(&mut self) {
+        self.animations.skip();
+    }
     
     
 
     pub fn draw(
         &self,
         textures: &std::collections::HashMap<Piece, Texture2D>,
+        palette: &BoardPalette,
         cell_size: f32,
         offset_x: f32,
         offset_y: f32,
     ) {
-        for i in 0..=7 {
-            for j in 0..=7 {
-                let x = offset_x + cell_size * i as f32;
-                let y = offset_y + cell_size * j as f32;
+        draw_board(&self.grid, textures, palette, cell_size, offset_x, offset_y, |square| {
+            self.animations.hides(square)
+        });
 
-                if (i + j) % 2 == 0 {
-                    draw_rectangle(x, y, cell_size, cell_size, WHITE);
-                } else {
-                    draw_rectangle(x, y, cell_size, cell_size, GRAY);
-                }
-            }
-        }
-
-        for i in 0..=7 {
-            for j in 0..=7 {
-                let cell = &self.grid.cells[i][j];
-                if let Some(piece) = cell.piece {
-                    if let Some(texture) = textures.get(&piece) {
-                        let x = offset_x + cell_size * i as f32;
-                        let y = offset_y + cell_size * (7 - j) as f32;
-                        draw_texture_ex(
-                            texture,
-                            x,
-                            y,
-                            WHITE,
-                            DrawTextureParams {
-                                dest_size: Some(Vec2::new(cell_size, cell_size)),
-                                ..Default::default()
-                            },
-                        );
-                    }
-                }
-            }
-        }
+        self.animations.draw(textures, cell_size, offset_x, offset_y);
 
         if let Some((col, row)) = self.selected_piece {
             let x = offset_x + cell_size * col as f32;
             let y = offset_y + cell_size * (7 - row) as f32;
-            draw_rectangle_lines(x, y, cell_size, cell_size, 3.0, YELLOW);
+            draw_rectangle_lines(x, y, cell_size, cell_size, 3.0, palette.highlight);
+        }
+        if let (true, Some([col, row])) = (self.is_losing_capture_hovered(), self.hovered) {
+            let x = offset_x + cell_size * col as f32;
+            let y = offset_y + cell_size * (7 - row) as f32;
+            draw_rectangle_lines(x, y, cell_size, cell_size, 3.0, RED);
         }
     }
-    pub fn is_checkmate(&self, color: ChessColor) -> bool {
-        let king_position = self.grid.find_king_position(color);
-        if !self.grid.is_square_attacked(king_position, color) {
-            return false; 
+    pub fn draw_panels(
+        &self,
+        layout: &Layout,
+        textures: &std::collections::HashMap<Piece, Texture2D>,
+        analysis: &Analysis,
+        review: &Review,
+        trainer: &Trainer,
+    ) {
+        let clocks = draw_panel(layout.clocks, "Clocks");
+        for (i, (color, name)) in [(ChessColor::White, "White"), (ChessColor::Black, "Black")]
+            .into_iter()
+            .enumerate()
+        {
+            let text = format!("{} {}", name, self.clock.format(color));
+            let x = clocks.x + clocks.w / 2.0 * i as f32;
+            let text_color = if color == self.grid.side_to_move() { YELLOW } else { PANEL_TEXT };
+            draw_text(&text, x, clocks.y + 22.0, 24.0, text_color);
         }
 
-        for from_col in 0..8 {
-            for from_row in 0..8 {
-                if let Some(piece) = self.grid.cells[from_col][from_row].piece {
-                    if piece.color == color {
-                        for to_col in 0..8 {
-                            for to_row in 0..8 {
-                                if self.grid.is_move_legal([from_col, from_row], [to_col, to_row]) {
-                                    return false;
-                                }
-                            }
-                        }
-                    }
+        let captured = draw_panel(layout.captured, "Captured");
+        let icon_size = (captured.h / 2.0).min(24.0);
+        let balance = self.grid.material_balance();
+        for (row, color) in [ChessColor::White, ChessColor::Black].into_iter().enumerate() {
+            let y = captured.y + icon_size * row as f32;
+            let mut x = captured.x;
+            for piece in self.captured_by(color) {
+                if let Some(texture) = textures.get(&piece) {
+                    draw_texture_ex(
+                        texture,
+                        x,
+                        y,
+                        WHITE,
+                        DrawTextureParams {
+                            dest_size: Some(Vec2::new(icon_size, icon_size)),
+                            ..Default::default()
+                        },
+                    );
                 }
+                x += icon_size * 0.6;
+            }
+            let advantage = match color {
+                ChessColor::White => balance,
+                ChessColor::Black => -balance,
+            };
+            if advantage > 0 {
+                let text = format!("+{}", advantage);
+                draw_text(&text, x + icon_size * 0.5, y + icon_size * 0.75, 20.0, PANEL_TEXT);
             }
         }
-        true
-    }
-    pub fn has_king_moved(&self, color: ChessColor) -> bool {
-        let king_start_row = if color == ChessColor::White { 0 } else { 7 };
-        for &(from, _) in &self.log {
-            if from == [4, king_start_row] {
-                return true;
+
+        let moves = draw_panel(layout.move_list, "Moves");
+        let line_height = 18.0;
+        let engine = panel_content(layout.engine_output);
+        if review.active {
+            review.draw_moves(moves);
+            draw_panel(layout.engine_output, &review.title());
+            review.draw_summary(engine);
+            return;
+        }
+        let mut top = moves.y;
+        if let Some(index) = self.chess960 {
+            let text = format!("Chess960 #{}", index);
+            draw_text(&text, moves.x, top + line_height, 16.0, SKYBLUE);
+            top += line_height;
+        } else if let Some(opening) = &self.opening {
+            let mut words: Vec<&str> = opening.name.split(' ').collect();
+            let mut text = format!("{} {}", opening.code, words.join(" "));
+            while words.len() > 1 && measure_text(&text, None, 16, 1.0).width > moves.w {
+                words.pop();
+                text = format!("{} {}...", opening.code, words.join(" "));
             }
+            draw_text(&text, moves.x, top + line_height, 16.0, SKYBLUE);
+            top += line_height;
         }
-        false
-    }
-    pub fn has_rook_moved(&self, color: ChessColor, is_kingside: bool) -> bool {
-        let rook_start_col = if is_kingside { 7 } else { 0 };
-        let rook_start_row = if color == ChessColor::White { 0 } else { 7 };
-    
-        for &(from, _) in &self.log {
-            if from == [rook_start_col, rook_start_row] {
-                return true;
+        let visible = ((moves.y + moves.h - top) / line_height) as usize;
+        let first = self.snapshots.len().saturating_sub(visible);
+        for (ply, snapshot) in self.snapshots.iter().enumerate().skip(first) {
+            let mut text = snapshot.grid.san_line(&[snapshot.mv]);
+            if snapshot.book {
+                text.push_str(" (book)");
+            }
+            if self.hints.contains(&ply) {
+                text.push_str(" (hint)");
             }
+            let y = top + line_height * (ply - first + 1) as f32;
+            draw_text(&text, moves.x, y, 18.0, PANEL_TEXT);
         }
-        false
-    }
-    pub fn is_move_castling(&self, from_position: [usize; 2], to_position: [usize; 2]) -> bool {
-        if let Some(piece) = self.grid.find_cell(from_position).piece {
-            if piece.type_of_piece == TypePiece::King {
-                let (king_start_col, king_end_col, rook_start_col) = match piece.color {
-                    ChessColor::White => (4, if to_position[0] == 6 { 6 } else { 2 }, if to_position[0] == 6 { 7 } else { 0 }),
-                    ChessColor::Black => (4, if to_position[0] == 6 { 6 } else { 2 }, if to_position[0] == 6 { 7 } else { 0 }),
-                };
-                let king_row = if piece.color == ChessColor::White { 0 } else { 7 };
-    
-                if self.has_king_moved(piece.color) {
-                    return false;
-                }
-    
-                if self.has_rook_moved(piece.color, to_position[0] == 6) {
-                    return false;
-                }
-    
-                if !self.grid.is_path_clear([king_start_col, king_row], [rook_start_col, king_row]) {
-                    return false;
-                }
-    
-                let step: i32 = if king_end_col > king_start_col { 1 } else { -1 };
-                for col in (std::cmp::min(king_start_col, king_end_col)..=std::cmp::max(king_start_col, king_end_col)).step_by(step.abs() as usize) {
-                    if self.grid.is_square_attacked([col, king_row], piece.color) {
-                        return false;
-                    }
-                }
-    
-                if self.grid.is_square_attacked([king_end_col, king_row], piece.color) {
-                    return false;
-                }
-    
-                return true;
+        if self.snapshots.is_empty() {
+            if let Some(result) = &self.last_result {
+                draw_text(&result.message(), moves.x, top + line_height, 18.0, PANEL_TEXT);
+                draw_text("R: review the game", moves.x, top + line_height * 2.0, 18.0, PANEL_TEXT);
+                draw_text("S: save as PGN", moves.x, top + line_height * 3.0, 18.0, PANEL_TEXT);
             }
         }
-        false
-    }
-}
+        let warning_y = moves.y + moves.h - 4.0 - line_height;
+        if self.is_losing_capture_hovered() {
+            draw_text("This capture loses material", moves.x, warning_y, 18.0, RED);
+        } else if let Some(notice) = &self.notice {
+            draw_text(notice, moves.x, warning_y, 18.0, YELLOW);
+        }
+        if let Some(reason) = search::claimable_draw(&self.grid, self.earlier_positions()) {
+            let text = format!("D: claim draw ({})", reason);
+            draw_text(&text, moves.x, moves.y + moves.h - 4.0, 18.0, YELLOW);
+        }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-pub enum TypePiece {
-    King,
-    Queen,
-    Rook,
-    Bishop,
-    Knight,
-    Pawn,
-}
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-pub enum ChessColor {
-    White,
-    Black,
-}
-impl ChessColor {
-    pub fn opposite(&self) -> ChessColor {
-        match self {
-            ChessColor::White => ChessColor::Black,
-            ChessColor::Black => ChessColor::White,
+        if trainer.active && !analysis.enabled {
+            draw_panel(layout.engine_output, "Endgame trainer");
+            trainer.draw(engine);
+            return;
+        }
+        draw_panel(layout.engine_output, "Engine");
+        if analysis.enabled {
+            analysis.draw_lines(engine);
+            return;
+        }
+        let evaluation = eval::explain(&self.grid);
+        let text = format!("Eval {:+.2}", evaluation.total() as f32 / 100.0);
+        draw_text(&text, engine.x, engine.y + 18.0, 18.0, PANEL_TEXT);
+        let column_width = engine.w / 2.0;
+        for (i, term) in TERMS.iter().enumerate() {
+            let x = engine.x + column_width * (i % 2) as f32;
+            let y = engine.y + 18.0 + line_height * (i / 2 + 1) as f32;
+            let text = format!("{} {:+}", term.name(), evaluation.tapered(*term));
+            draw_text(&text, x, y, 16.0, PANEL_TEXT);
         }
     }
 }
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-pub struct Piece {
-    type_of_piece: TypePiece,
-    color: ChessColor,
-}
-#[derive(Clone, Copy, Debug)]
-pub struct Cell {
-    position: [usize; 2],
-    piece: Option<Piece>,
-}
 
-#[derive(Debug, Clone)]
-pub struct Grid {
-    cells: [[Cell; 8]; 8],
-}
-
-impl Grid {
-    pub fn is_move_legal(&self, from_position: [usize; 2], to_position: [usize; 2]) -> bool {
-        if {
-            match self.find_cell(from_position).piece.unwrap().type_of_piece {
-                TypePiece::Pawn => self.is_pawn_move_legal(from_position, to_position),
-                TypePiece::Bishop => self.is_bishop_move_legal(from_position, to_position),
-                TypePiece::Rook => self.is_rook_move_legal(from_position, to_position),
-                TypePiece::Queen => {
-                    self.is_bishop_move_legal(from_position, to_position)
-                        || self.is_rook_move_legal(from_position, to_position)
-                }
-                TypePiece::King => self.is_king_move_legal(from_position, to_position),
-                TypePiece::Knight => self.is_knight_move_legal(from_position, to_position),
-            }
-        } && !self.does_move_put_king_in_check(from_position, to_position)
-        {
-            return true;
-        }
-        false
+#[macroquad::main(conf())]
+async fn main() {
+    let mut app = App::new();
+    app.book = load_book();
+    if let Some(index) = chess960_from_args() {
+        app.start_chess960(index);
     }
-    pub fn does_move_put_king_in_check(&self, from_position: [usize; 2], to_position: [usize; 2],) -> bool {
-        let mut temp_board = self.clone();
-        
-        let piece = temp_board.cells[from_position[0]][from_position[1]]
-            .piece
-            .take();
-        temp_board.cells[to_position[0]][to_position[1]].piece = piece;
-    
-        let king_color = temp_board.cells[to_position[0]][to_position[1]]
-            .piece
-            .unwrap()
-            .color;
-        let king_position = temp_board.find_king_position(king_color);
-        
-        temp_board.is_square_attacked(king_position, king_color)
-    }
-    pub fn is_square_attacked(&self, position: [usize; 2], defending_color: ChessColor) -> bool {
-        let attacking_color = defending_color.opposite();
-        for col in 0..8 {
-            for row in 0..8 {
-                if let Some(piece) = self.cells[col][row].piece {
-                    if piece.color == attacking_color && self.is_move_legal([col, row], position) {
-                        return true;
-                    }
-                }
+    let mut theme = Theme::load();
+    let mut textures = load_piece_set(theme.piece_set_dir()).await;
+    let mut sounds = Sounds::load().await;
+    let mut analysis = Analysis::new();
+    analysis.set_tablebase(load_tablebase());
+    let mut review = Review::new();
+    let mut hint = Hint::new();
+    let mut trainer = Trainer::new();
+    // Solved now, while the window opens, rather than in the middle of a game.
+    for ending in Ending::ALL {
+        endgame::prepare(ending);
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    let mut engine = uci_client::EngineSession::from_args();
+    loop {
+        if is_key_pressed(KeyCode::T) {
+            theme.next_palette();
+        }
+        if is_key_pressed(KeyCode::P) {
+            let previous_set = theme.piece_set_dir();
+            theme.next_piece_set();
+            if theme.piece_set_dir() != previous_set {
+                textures = load_piece_set(theme.piece_set_dir()).await;
             }
         }
-        false
-    }
 
-    pub fn find_king_position(&self, color: ChessColor) -> [usize; 2] {
-        for col in 0..8 {
-            for row in 0..8 {
-                if let Some(piece) = &self.cells[col][row].piece {
-                    if piece.type_of_piece == TypePiece::King && piece.color == color {
-                        return [col, row];
-                    }
-                }
-            }
+        clear_background(WHITE);
+
+        let layout = Layout::compute(screen_width(), screen_height());
+        let (cell_size, offset_x, offset_y) = (layout.cell_size, layout.board.x, layout.board.y);
+
+        if is_key_pressed(KeyCode::U) || is_key_pressed(KeyCode::Backspace) {
+            app.undo();
         }
-        panic!("King not found on the board!");
-    }
-    pub fn is_knight_move_legal(&self, from_position: [usize; 2], to_position: [usize; 2]) -> bool {
-        let row_diff = (to_position[1] as isize - from_position[1] as isize).abs();
-        let col_diff = (to_position[0] as isize - from_position[0] as isize).abs();
-        if col_diff == 2 || row_diff == 2 {
-            if col_diff == 1 || row_diff == 1 {
-                if self.find_cell(to_position).piece.is_none() {
-                    return true;
-                }
-                if self.find_cell(from_position).piece.unwrap().color
-                    != self.find_cell(to_position).piece.unwrap().color
-                {
-                    return true;
+        if is_key_pressed(KeyCode::Space) {
+            app.skip_animations();
+        }
+        if is_key_pressed(KeyCode::D) {
+            app.claim_draw();
+        }
+        if is_key_pressed(KeyCode::A) {
+            analysis.toggle();
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                if engine.has_engine() {
+                    engine.set_analysing(analysis.enabled);
                 }
             }
         }
-        false
-    }
-    pub fn is_king_move_legal(&self, from_position: [usize; 2], to_position: [usize; 2]) -> bool {
-        let row_diff = to_position[1] as isize - from_position[1] as isize;
-        let col_diff = to_position[0] as isize - from_position[0] as isize;
-        if col_diff.abs() <= 1 && row_diff.abs() <= 1 {
-            if self.find_cell(to_position).piece.is_none() {
-                return true;
-            } else {
-                if self.find_cell(from_position).piece.unwrap().color
-                    != self.find_cell(to_position).piece.unwrap().color
-                {
-                    return true;
-                } else {
-                    return false;
-                }
+        if is_key_pressed(KeyCode::R) {
+            if review.active {
+                review.stop();
+            } else if let Some(record) = app.game_to_review() {
+                review.start(record);
             }
         }
-        false
-    }
-    pub fn is_path_clear(&self, from_position: [usize; 2], to_position: [usize; 2]) -> bool {
-        let mut row = from_position[1] as isize;
-        let mut col = from_position[0] as isize;
-        let row_diff = to_position[1] as isize - row;
-        let col_diff = to_position[0] as isize - col;
-
-        let row_step = if row_diff == 0 {
-            0
-        } else {
-            row_diff / row_diff.abs()
-        };
-        let col_step = if col_diff == 0 {
-            0
-        } else {
-            col_diff / col_diff.abs()
-        };
-
-        row += row_step;
-        col += col_step;
-
-        while row != to_position[1] as isize || col != to_position[0] as isize {
-            if self.find_cell([col as usize, row as usize]).piece.is_some() {
-                return false;
+        if review.active {
+            if is_key_pressed(KeyCode::Left) {
+                review.step(-1);
+            }
+            if is_key_pressed(KeyCode::Right) {
+                review.step(1);
+            }
+            if is_key_pressed(KeyCode::Home) {
+                review.step(isize::MIN);
+            }
+            if is_key_pressed(KeyCode::End) {
+                review.step(isize::MAX);
+            }
+            if is_key_pressed(KeyCode::B) {
+                review.toggle_best();
             }
-            row += row_step;
-            col += col_step;
         }
-
-        true
-    }
-    pub fn is_rook_move_legal(&self, from_position: [usize; 2], to_position: [usize; 2]) -> bool {
-        if (from_position[0] == to_position[0] || from_position[1] == to_position[1])
-            && self.is_path_clear(from_position, to_position)
+        review.update();
+        if is_key_pressed(KeyCode::S) {
+            app.export_pgn();
+        }
+        if is_key_pressed(KeyCode::H) && !review.active {
+            hint.request(app.grid());
+            app.use_hint();
+        }
+        if is_key_pressed(KeyCode::F) && !review.active {
+            app.start_chess960(rand::gen_range(0, CHESS960_POSITIONS));
+        }
+        if is_key_pressed(KeyCode::G) && !review.active {
+            trainer.start(&mut app);
+        }
+        trainer.update(&mut app);
+        hint.update(app.grid(), app.earlier_positions());
+        match review.position() {
+            Some(position) => analysis.update(position, &review.earlier_positions()),
+            None => analysis.update(app.grid(), app.earlier_positions()),
+        }
+        #[cfg(not(target_arch = "wasm32"))]
         {
-            if self.find_cell(to_position).piece.is_none() {
-                return true;
-            } else {
-                if self.find_cell(from_position).piece.unwrap().color
-                    != self.find_cell(to_position).piece.unwrap().color
-                {
-                    return true;
-                }
+            if is_key_pressed(KeyCode::E) {
+                engine.toggle_opponent(&app);
+            }
+            if is_key_pressed(KeyCode::A) || is_key_pressed(KeyCode::E) {
+                analysis.set_external(engine.is_analysing());
             }
+            engine.update(&mut app, &mut analysis);
         }
-
-        false
-    }
-    pub fn is_bishop_move_legal(&self, from_position: [usize; 2], to_position: [usize; 2]) -> bool {
-        let row_diff = to_position[1] as isize - from_position[1] as isize;
-        let col_diff = to_position[0] as isize - from_position[0] as isize;
-        if (row_diff.abs() == col_diff.abs()) && self.is_path_clear(from_position, to_position) {
-            if self.find_cell(to_position).piece.is_none() {
-                return true;
-            } else {
-                if self.find_cell(from_position).piece.unwrap().color
-                    != self.find_cell(to_position).piece.unwrap().color
-                {
-                    return true;
-                } else {
-                    return false;
-                }
+        if is_key_pressed(KeyCode::M) {
+            sounds.toggle_mute();
+        }
+        if is_key_pressed(KeyCode::Equal) || is_key_pressed(KeyCode::KpAdd) {
+            sounds.change_volume(0.1);
+        }
+        if is_key_pressed(KeyCode::Minus) || is_key_pressed(KeyCode::KpSubtract) {
+            sounds.change_volume(-0.1);
+        }
+        app.update(get_frame_time(), !analysis.enabled && !review.active && !trainer.active);
+        let (mouse_x, mouse_y) = mouse_position();
+        app.hover(mouse_x, mouse_y, cell_size, offset_x, offset_y);
+
+        match review.position() {
+            Some(grid) => {
+                draw_board(grid, &textures, theme.palette(), cell_size, offset_x, offset_y, |_| false);
+                review.draw_arrows(cell_size, offset_x, offset_y);
+            }
+            None => {
+                app.draw(&textures, theme.palette(), cell_size, offset_x, offset_y);
+                hint.draw(cell_size, offset_x, offset_y);
             }
-        } else {
-            false
         }
-    }
-    pub fn is_pawn_move_legal(&self, from_position: [usize; 2], to_position: [usize; 2]) -> bool {
-        let direction = if self.find_cell(from_position).piece.unwrap().color == ChessColor::White {
-            1
+        analysis.draw_arrows(cell_size, offset_x, offset_y);
+        analysis.draw_eval_bar(layout.eval_bar);
+        app.draw_panels(&layout, &textures, &analysis, &review, &trainer);
+        #[cfg(not(target_arch = "wasm32"))]
+        engine.draw(layout.engine_output);
+        let sound_status = if sounds.is_muted() {
+            "Muted".to_string()
         } else {
-            -1
+            format!("Vol {:.0}%", sounds.volume() * 100.0)
         };
+        draw_text(
+            &sound_status,
+            layout.clocks.right() - 80.0,
+            layout.clocks.y + 16.0,
+            16.0,
+            PANEL_TEXT,
+        );
 
-        let row_diff = (to_position[1] as isize - from_position[1] as isize) * direction;
-        let col_diff = (to_position[0] as isize - from_position[0] as isize).abs();
-
-        match (row_diff, col_diff) {
-            (1, 0) => {
-                self.find_cell(to_position).piece.is_none()
-                    && self.is_path_clear(from_position, to_position)
-            } // Simple movement
-            (2, 0) if from_position[1] == 1 || from_position[1] == 6 => {
-                self.find_cell(to_position).piece.is_none()
-                    && self.is_path_clear(from_position, to_position)
-            } // Double initial movement
-            (1, 1) => {
-                self.find_cell(to_position).piece.is_some()
-                    && self.find_cell(to_position).piece.unwrap().color
-                        != self.find_cell(from_position).piece.unwrap().color
-            } // Diagonal capture
-            _ => false,
-        }
-    }
-
-    pub fn full_positions(&mut self) {
-        for i in 0..8 {
-            for j in 0..8 {
-                self.cells[i][j].position = [i, j];
+        if is_mouse_button_pressed(MouseButton::Left) {
+            if review.active {
+                review.click(mouse_x, mouse_y, panel_content(layout.move_list));
+            } else {
+                app.skip_animations();
+                app.move_piece(mouse_x, mouse_y, cell_size, offset_x, offset_y);
             }
         }
-    }
-    pub fn find_cell(&self, position: [usize; 2]) -> &Cell {
-        let [column, row] = position;
-
-        if column < 8 && row < 8 {
-            &self.cells[column][row]
-        } else {
-            panic!("Cell dont exist!");
+        for effect in app.take_sounds() {
+            sounds.play(effect);
         }
+
+        next_frame().await
     }
-    pub fn find_cell_mut(&mut self, position: [usize; 2]) -> &mut Cell {
-        let [column, row] = position;
+}
 
-        if column < 8 && row < 8 {
-            &mut self.cells[column][row]
-        } else {
-            panic!("Cell dont exist!");
-        }
-    }
-
-    pub fn initialize_pieces(&mut self) {
-        let white_pieces = [
-            (TypePiece::Rook, [0, 0]),
-            (TypePiece::Knight, [1, 0]),
-            (TypePiece::Bishop, [2, 0]),
-            (TypePiece::Queen, [3, 0]),
-            (TypePiece::King, [4, 0]),
-            (TypePiece::Bishop, [5, 0]),
-            (TypePiece::Knight, [6, 0]),
-            (TypePiece::Rook, [7, 0]),
-        ];
-
-        for &(type_of_piece, position) in white_pieces.iter() {
-            let cell = self.find_cell_mut(position);
-            cell.piece = Some(Piece {
-                type_of_piece,
-                color: ChessColor::White,
-            });
-        }
-
-        for column in 0..8 {
-            let cell = self.find_cell_mut([column, 1]);
-            cell.piece = Some(Piece {
-                type_of_piece: TypePiece::Pawn,
-                color: ChessColor::White,
-            });
-        }
-
-        let black_pieces = [
-            (TypePiece::Rook, [0, 7]),
-            (TypePiece::Knight, [1, 7]),
-            (TypePiece::Bishop, [2, 7]),
-            (TypePiece::Queen, [3, 7]),
-            (TypePiece::King, [4, 7]),
-            (TypePiece::Bishop, [5, 7]),
-            (TypePiece::Knight, [6, 7]),
-            (TypePiece::Rook, [7, 7]),
-        ];
-
-        for &(type_of_piece, position) in black_pieces.iter() {
-            let cell = self.find_cell_mut(position);
-            cell.piece = Some(Piece {
-                type_of_piece,
-                color: ChessColor::Black,
-            });
-        }
-
-        for column in 0..8 {
-            let cell = self.find_cell_mut([column, 6]);
-            cell.piece = Some(Piece {
-                type_of_piece: TypePiece::Pawn,
-                color: ChessColor::Black,
-            });
+/// The book given as `--book <path>` or used last time, or the bundled one.
+#[cfg(not(target_arch = "wasm32"))]
+fn load_book() -> Book {
+    let path = std::env::args().skip_while(|arg| arg != "--book").nth(1);
+    if let Some(path) = &path {
+        storage::save(BOOK_KEY, path);
+    }
+    let Some(path) = path.or_else(|| storage::load(BOOK_KEY)) else {
+        return Book::bundled();
+    };
+    match std::fs::read(&path).map_err(|error| error.to_string()).and_then(|bytes| Book::from_bytes(&bytes)) {
+        Ok(book) => book,
+        Err(error) => {
+            eprintln!("Could not load book {}: {}", path, error);
+            Book::bundled()
         }
     }
+}
 
-    pub fn new() -> Self {
-        let cells = [[Cell {
-            position: [0, 0],
-            piece: None,
-        }; 8]; 8];
-        let mut grid = Grid { cells };
-        grid.full_positions();
-        grid.initialize_pieces();
-        grid
-    }
-
-    pub fn print_board(&self) {
-        for i in 0..8 {
-            for j in 0..8 {
-                if let Some(piece) = self.cells[i][j].piece {
-                    print!("{:?} ", piece.type_of_piece);
-                } else {
-                    print!("None ");
-                }
-            }
-            println!();
+#[cfg(target_arch = "wasm32")]
+fn load_book() -> Book {
+    Book::bundled()
+}
+
+/// Starting position number given as `--chess960 <n>`.
+#[cfg(not(target_arch = "wasm32"))]
+fn chess960_from_args() -> Option<usize> {
+    let index = std::env::args().skip_while(|arg| arg != "--chess960").nth(1)?;
+    match index.parse() {
+        Ok(index) => Some(index),
+        Err(_) => {
+            eprintln!("Not a Chess960 position number: {}", index);
+            None
         }
     }
+}
 
-    pub fn position_to_chess_notation(position: [usize; 2]) -> String {
-        let column = (position[0] + 'a' as usize) as u8 as char;
-        let row = (position[1] + 1).to_string();
-        format!("{}{}", column, row)
+/// The browser has no command line.
+#[cfg(target_arch = "wasm32")]
+fn chess960_from_args() -> Option<usize> {
+    None
+}
+
+/// Syzygy tables in the directory given as `--syzygy <dir>` or used last
+/// time.
+#[cfg(not(target_arch = "wasm32"))]
+fn load_tablebase() -> Option<Arc<Tablebase>> {
+    let directory = std::env::args().skip_while(|arg| arg != "--syzygy").nth(1);
+    if let Some(directory) = &directory {
+        storage::save(SYZYGY_KEY, directory);
+    }
+    let directory = directory.or_else(|| storage::load(SYZYGY_KEY))?;
+    match Tablebase::open(&directory) {
+        Ok(tablebase) => Some(Arc::new(tablebase)),
+        Err(error) => {
+            eprintln!("Could not load tablebases: {}", error);
+            None
+        }
     }
 }
 
-async fn load_textures() -> std::collections::HashMap<Piece, Texture2D> {
-    let mut textures = std::collections::HashMap::new();
-
-    let white_pawn = load_texture("newimg/white-pawn.png").await.unwrap();
-    let black_pawn = load_texture("newimg/black-pawn.png").await.unwrap();
-    let white_rook = load_texture("newimg/white-rook.png").await.unwrap();
-    let black_rook = load_texture("newimg/black-rook.png").await.unwrap();
-    let white_bishop = load_texture("newimg/white-bishop.png").await.unwrap();
-    let black_bishop = load_texture("newimg/black-bishop.png").await.unwrap();
-    let white_queen = load_texture("newimg/white-queen.png").await.unwrap();
-    let black_queen = load_texture("newimg/black-queen.png").await.unwrap();
-    let white_knight = load_texture("newimg/white-knight.png").await.unwrap();
-    let black_knight = load_texture("newimg/black-knight.png").await.unwrap();
-    let white_king = load_texture("newimg/white-king.png").await.unwrap();
-    let black_king = load_texture("newimg/black-king.png").await.unwrap();
-
-    textures.insert(
-        Piece {
-            type_of_piece: TypePiece::Pawn,
-            color: ChessColor::White,
-        },
-        white_pawn,
-    );
-    textures.insert(
-        Piece {
-            type_of_piece: TypePiece::Pawn,
-            color: ChessColor::Black,
-        },
-        black_pawn,
-    );
-    textures.insert(
-        Piece {
-            type_of_piece: TypePiece::Rook,
-            color: ChessColor::White,
-        },
-        white_rook,
-    );
-    textures.insert(
-        Piece {
-            type_of_piece: TypePiece::Rook,
-            color: ChessColor::Black,
-        },
-        black_rook,
-    );
-    textures.insert(
-        Piece {
-            type_of_piece: TypePiece::Bishop,
-            color: ChessColor::White,
-        },
-        white_bishop,
-    );
-    textures.insert(
-        Piece {
-            type_of_piece: TypePiece::Bishop,
-            color: ChessColor::Black,
-        },
-        black_bishop,
-    );
-    textures.insert(
-        Piece {
-            type_of_piece: TypePiece::Queen,
-            color: ChessColor::White,
-        },
-        white_queen,
-    );
-    textures.insert(
-        Piece {
-            type_of_piece: TypePiece::Queen,
-            color: ChessColor::Black,
-        },
-        black_queen,
-    );
-    textures.insert(
-        Piece {
-            type_of_piece: TypePiece::Knight,
-            color: ChessColor::White,
-        },
-        white_knight,
-    );
-    textures.insert(
-        Piece {
-            type_of_piece: TypePiece::Knight,
-            color: ChessColor::Black,
-        },
-        black_knight,
-    );
-    textures.insert(
-        Piece {
-            type_of_piece: TypePiece::King,
-            color: ChessColor::White,
-        },
-        white_king,
-    );
-    textures.insert(
-        Piece {
-            type_of_piece: TypePiece::King,
-            color: ChessColor::Black,
-        },
-        black_king,
-    );
-    textures
+/// The browser has no files to read tables from.
+#[cfg(target_arch = "wasm32")]
+fn load_tablebase() -> Option<Arc<Tablebase>> {
+    None
 }
 
-#[macroquad::main(conf())]
-async fn main() {
-    let mut app = App::new();
-    let textures = load_textures().await;
-    loop {
-        clear_background(WHITE);
+/// Writes `pgn` to a file and the clipboard and says so.
+#[cfg(not(target_arch = "wasm32"))]
+fn export_pgn(pgn: &str) -> String {
+    miniquad::window::clipboard_set(pgn);
+    match std::fs::write(PGN_FILE, pgn) {
+        Ok(()) => format!("Saved to {} and copied", PGN_FILE),
+        Err(error) => {
+            eprintln!("Could not write {}: {}", PGN_FILE, error);
+            "PGN copied to clipboard".to_string()
+        }
+    }
+}
 
-        let (screen_width, screen_height) = (screen_width(), screen_height());
-        let cell_size = (screen_width.min(screen_height)) / 8.0;
-        let offset_x = (screen_width - cell_size * 8.0) / 2.0;
-        let offset_y = (screen_height - cell_size * 8.0) / 2.0;
+/// Copies `pgn` to the clipboard, the browser having no files to write.
+#[cfg(target_arch = "wasm32")]
+fn export_pgn(pgn: &str) -> String {
+    miniquad::window::clipboard_set(pgn);
+    "PGN copied to clipboard".to_string()
+}
 
-        app.draw(&textures, cell_size, offset_x, offset_y);
+/// Squares and pieces of `grid`, leaving out the squares `hidden` says an
+/// animation is drawing.
+fn draw_board(
+    grid: &Grid,
+    textures: &std::collections::HashMap<Piece, Texture2D>,
+    palette: &BoardPalette,
+    cell_size: f32,
+    offset_x: f32,
+    offset_y: f32,
+    hidden: impl Fn([usize; 2]) -> bool,
+) {
+    for i in 0..=7 {
+        for j in 0..=7 {
+            let x = offset_x + cell_size * i as f32;
+            let y = offset_y + cell_size * j as f32;
+
+            if (i + j) % 2 == 0 {
+                draw_rectangle(x, y, cell_size, cell_size, palette.light);
+            } else {
+                draw_rectangle(x, y, cell_size, cell_size, palette.dark);
+            }
+        }
+    }
 
-        if is_mouse_button_pressed(MouseButton::Left) {
-            let (mouse_x, mouse_y) = mouse_position();
-            app.move_piece(mouse_x, mouse_y, cell_size, offset_x, offset_y);
+    for i in 0..=7 {
+        for j in 0..=7 {
+            let cell = grid.find_cell([i, j]);
+            if hidden([i, j]) {
+                continue;
+            }
+            if let Some(piece) = cell.piece {
+                if let Some(texture) = textures.get(&piece) {
+                    let x = offset_x + cell_size * i as f32;
+                    let y = offset_y + cell_size * (7 - j) as f32;
+                    draw_texture_ex(
+                        texture,
+                        x,
+                        y,
+                        WHITE,
+                        DrawTextureParams {
+                            dest_size: Some(Vec2::new(cell_size, cell_size)),
+                            ..Default::default()
+                        },
+                    );
+                }
+            }
         }
+    }
+}
 
-        next_frame().await
+/// Board square under a screen position, if any.
+fn square_at(x: f32, y: f32, cell_size: f32, offset_x: f32, offset_y: f32) -> Option<[usize; 2]> {
+    let board_size = cell_size * 8.0;
+    if x < offset_x || y < offset_y || x >= offset_x + board_size || y >= offset_y + board_size {
+        return None;
     }
+    let col = ((x - offset_x) / cell_size) as usize;
+    let row = 7 - ((y - offset_y) / cell_size) as usize;
+    (col < 8 && row < 8).then_some([col, row])
 }
 
 fn conf() -> Conf {
     Conf {
-        window_width: 900,
+        window_width: 1200,
         window_height: 900,
-        window_resizable: false,
+        window_resizable: true,
         window_title: "Chess".to_string(),
         ..Default::default()
     }
 }
-
-// #[cfg(test)]
-// mod tests{
-//     use super::*;
-
-//     #[test]
-//     fn it_works(){
-//         let piece = Piece{type_of_piece:TypePiece::King, color:ChessColor::Black};
-//         let mut cell = Cell{name:['a','a'], piece:Some(piece)};
-//         let mut grid = Grid{cells: [[cell.clone();8];8]};
-//         grid.full_names();
-//         for cell in grid.cells {
-//             for i in cell{
-//                 println!("{:?}",i.name);
-//             }
-//         }
-//     }
-// }
@@ -0,0 +1,422 @@
+//! Legal move generation.
+//!
+//! Checkers and pinned pieces are worked out once per position, so every
+//! move emitted here is legal without having to be played and tested.
+
+use crate::{
+    ChessColor, Grid, Move, Piece, TypePiece, BISHOP_DIRECTIONS, KING_OFFSETS, KNIGHT_OFFSETS,
+    ROOK_DIRECTIONS,
+};
+
+const PROMOTIONS: [TypePiece; 4] = [
+    TypePiece::Queen,
+    TypePiece::Rook,
+    TypePiece::Bishop,
+    TypePiece::Knight,
+];
+
+fn bit(square: [usize; 2]) -> u64 {
+    1 << (square[1] * 8 + square[0])
+}
+
+fn on_board(col: isize, row: isize) -> bool {
+    (0..8).contains(&col) && (0..8).contains(&row)
+}
+
+fn is_slider_for(piece: Piece, diagonal: bool) -> bool {
+    match piece.type_of_piece {
+        TypePiece::Queen => true,
+        TypePiece::Rook => !diagonal,
+        TypePiece::Bishop => diagonal,
+        _ => false,
+    }
+}
+
+/// What the side to move has to respect before moving anything.
+struct Constraints {
+    king: [usize; 2],
+    checkers: usize,
+    /// Squares a non-king move has to land on while in single check: the
+    /// checking piece and, for sliders, the squares between it and the king.
+    evasions: u64,
+    /// Direction from the king towards each pinned piece.
+    pins: [[Option<(isize, isize)>; 8]; 8],
+}
+
+impl Grid {
+    /// All legal moves for the side to move. Promotions come as four moves,
+    /// one per piece.
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let mut moves = Vec::with_capacity(64);
+        self.generate_legal_moves(&mut moves);
+        moves
+    }
+
+    /// Fills `moves` with the legal moves, reusing its allocation.
+    pub fn generate_legal_moves(&self, moves: &mut Vec<Move>) {
+        moves.clear();
+        let color = self.side_to_move;
+        let constraints = self.constraints(color);
+
+        self.king_moves(&constraints, moves);
+        if constraints.checkers > 1 {
+            return;
+        }
+
+        for col in 0..8 {
+            for row in 0..8 {
+                let Some(piece) = self.cells[col][row].piece else {
+                    continue;
+                };
+                if piece.color != color || piece.type_of_piece == TypePiece::King {
+                    continue;
+                }
+                let from = [col, row];
+                let start = moves.len();
+                match piece.type_of_piece {
+                    TypePiece::Pawn => self.pawn_moves(from, color, moves),
+                    TypePiece::Knight => self.step_moves(from, color, &KNIGHT_OFFSETS, moves),
+                    TypePiece::Bishop => self.slide_moves(from, color, &BISHOP_DIRECTIONS, moves),
+                    TypePiece::Rook => self.slide_moves(from, color, &ROOK_DIRECTIONS, moves),
+                    TypePiece::Queen => {
+                        self.slide_moves(from, color, &BISHOP_DIRECTIONS, moves);
+                        self.slide_moves(from, color, &ROOK_DIRECTIONS, moves);
+                    }
+                    TypePiece::King => unreachable!(),
+                }
+                self.retain_legal(&constraints, start, moves);
+            }
+        }
+    }
+
+    pub fn is_in_check(&self) -> bool {
+        let king = self.find_king_position(self.side_to_move);
+        self.is_square_attacked(king, self.side_to_move)
+    }
+
+    pub fn is_checkmate(&self) -> bool {
+        self.is_in_check() && self.legal_moves().is_empty()
+    }
+
+    pub fn is_stalemate(&self) -> bool {
+        !self.is_in_check() && self.legal_moves().is_empty()
+    }
+
+    fn constraints(&self, color: ChessColor) -> Constraints {
+        let king = self.find_king_position(color);
+        let enemy = color.opposite();
+        let (king_col, king_row) = (king[0] as isize, king[1] as isize);
+        let mut constraints = Constraints {
+            king,
+            checkers: 0,
+            evasions: 0,
+            pins: [[None; 8]; 8],
+        };
+
+        let pawn_row = match color {
+            ChessColor::White => king_row + 1,
+            ChessColor::Black => king_row - 1,
+        };
+        let enemy_pawn = Piece {
+            type_of_piece: TypePiece::Pawn,
+            color: enemy,
+        };
+        let enemy_knight = Piece {
+            type_of_piece: TypePiece::Knight,
+            color: enemy,
+        };
+        for col in [king_col - 1, king_col + 1] {
+            if on_board(col, pawn_row) && self.cells[col as usize][pawn_row as usize].piece == Some(enemy_pawn) {
+                constraints.checkers += 1;
+                constraints.evasions |= bit([col as usize, pawn_row as usize]);
+            }
+        }
+        for &(dc, dr) in KNIGHT_OFFSETS.iter() {
+            let (col, row) = (king_col + dc, king_row + dr);
+            if on_board(col, row) && self.cells[col as usize][row as usize].piece == Some(enemy_knight) {
+                constraints.checkers += 1;
+                constraints.evasions |= bit([col as usize, row as usize]);
+            }
+        }
+
+        for (directions, diagonal) in [(&ROOK_DIRECTIONS, false), (&BISHOP_DIRECTIONS, true)] {
+            for &(dc, dr) in directions.iter() {
+                let mut ray = 0;
+                let mut own_piece: Option<[usize; 2]> = None;
+                let (mut col, mut row) = (king_col + dc, king_row + dr);
+                while on_board(col, row) {
+                    let square = [col as usize, row as usize];
+                    ray |= bit(square);
+                    if let Some(piece) = self.cells[square[0]][square[1]].piece {
+                        if piece.color == color {
+                            if own_piece.is_some() {
+                                break;
+                            }
+                            own_piece = Some(square);
+                        } else {
+                            if is_slider_for(piece, diagonal) {
+                                match own_piece {
+                                    Some(pinned) => constraints.pins[pinned[0]][pinned[1]] = Some((dc, dr)),
+                                    None => {
+                                        constraints.checkers += 1;
+                                        constraints.evasions |= ray;
+                                    }
+                                }
+                            }
+                            break;
+                        }
+                    }
+                    col += dc;
+                    row += dr;
+                }
+            }
+        }
+        constraints
+    }
+
+    /// Drops the moves from `start` on that leave the king in check.
+    fn retain_legal(&self, constraints: &Constraints, start: usize, moves: &mut Vec<Move>) {
+        let mut i = start;
+        while i < moves.len() {
+            let mv = moves[i];
+            let legal = if self.is_en_passant(mv) {
+                self.is_en_passant_safe(mv, constraints.king)
+            } else {
+                let pinned_ok = match constraints.pins[mv.from[0]][mv.from[1]] {
+                    Some((dc, dr)) => {
+                        let col = mv.to[0] as isize - constraints.king[0] as isize;
+                        let row = mv.to[1] as isize - constraints.king[1] as isize;
+                        col * dr == row * dc
+                    }
+                    None => true,
+                };
+                pinned_ok && (constraints.checkers == 0 || constraints.evasions & bit(mv.to) != 0)
+            };
+            if legal {
+                i += 1;
+            } else {
+                moves.swap_remove(i);
+            }
+        }
+    }
+
+    fn is_en_passant(&self, mv: Move) -> bool {
+        Some(mv.to) == self.en_passant
+            && mv.from[0] != mv.to[0]
+            && self.cells[mv.from[0]][mv.from[1]]
+                .piece
+                .is_some_and(|piece| piece.type_of_piece == TypePiece::Pawn)
+    }
+
+    /// En passant lifts two pawns off the same rank at once, which can
+    /// uncover a rook along that rank, so it is checked on the board as it
+    /// would look afterwards.
+    fn is_en_passant_safe(&self, mv: Move, king: [usize; 2]) -> bool {
+        let captured = [mv.to[0], mv.from[1]];
+        let color = self.side_to_move;
+        let pawn = self.cells[mv.from[0]][mv.from[1]].piece;
+        !Self::is_attacked_on(king, color.opposite(), |square| {
+            if square == mv.from || square == captured {
+                None
+            } else if square == mv.to {
+                pawn
+            } else {
+                self.cells[square[0]][square[1]].piece
+            }
+        })
+    }
+
+    fn king_moves(&self, constraints: &Constraints, moves: &mut Vec<Move>) {
+        let king = constraints.king;
+        let color = self.side_to_move;
+        // The king is lifted off the board so sliders see through its square.
+        let without_king = |square: [usize; 2]| {
+            if square == king {
+                None
+            } else {
+                self.cells[square[0]][square[1]].piece
+            }
+        };
+        for &(dc, dr) in KING_OFFSETS.iter() {
+            let (col, row) = (king[0] as isize + dc, king[1] as isize + dr);
+            if !on_board(col, row) {
+                continue;
+            }
+            let to = [col as usize, row as usize];
+            if self.cells[to[0]][to[1]].piece.is_some_and(|piece| piece.color == color) {
+                continue;
+            }
+            if !Self::is_attacked_on(to, color.opposite(), without_king) {
+                moves.push(Move::new(king, to));
+            }
+        }
+
+        if constraints.checkers == 0 {
+            moves.extend(self.castling_moves());
+        }
+    }
+
+    fn pawn_moves(&self, from: [usize; 2], color: ChessColor, moves: &mut Vec<Move>) {
+        let (direction, start_row, last_row) = match color {
+            ChessColor::White => (1isize, 1, 7),
+            ChessColor::Black => (-1isize, 6, 0),
+        };
+        let row = from[1] as isize + direction;
+        if !(0..8).contains(&row) {
+            return;
+        }
+        let row = row as usize;
+        let push = |to: [usize; 2], moves: &mut Vec<Move>| {
+            if to[1] == last_row {
+                for promotion in PROMOTIONS {
+                    moves.push(Move {
+                        from,
+                        to,
+                        promotion: Some(promotion),
+                    });
+                }
+            } else {
+                moves.push(Move::new(from, to));
+            }
+        };
+
+        if self.cells[from[0]][row].piece.is_none() {
+            push([from[0], row], moves);
+            if from[1] == start_row {
+                let double = (row as isize + direction) as usize;
+                if self.cells[from[0]][double].piece.is_none() {
+                    push([from[0], double], moves);
+                }
+            }
+        }
+        for col in [from[0] as isize - 1, from[0] as isize + 1] {
+            if !(0..8).contains(&col) {
+                continue;
+            }
+            let to = [col as usize, row];
+            let capture = match self.cells[to[0]][to[1]].piece {
+                Some(piece) => piece.color != color,
+                None => self.en_passant == Some(to),
+            };
+            if capture {
+                push(to, moves);
+            }
+        }
+    }
+
+    fn step_moves(&self, from: [usize; 2], color: ChessColor, offsets: &[(isize, isize)], moves: &mut Vec<Move>) {
+        for &(dc, dr) in offsets {
+            let (col, row) = (from[0] as isize + dc, from[1] as isize + dr);
+            if !on_board(col, row) {
+                continue;
+            }
+            let to = [col as usize, row as usize];
+            if !self.cells[to[0]][to[1]].piece.is_some_and(|piece| piece.color == color) {
+                moves.push(Move::new(from, to));
+            }
+        }
+    }
+
+    fn slide_moves(&self, from: [usize; 2], color: ChessColor, directions: &[(isize, isize)], moves: &mut Vec<Move>) {
+        for &(dc, dr) in directions {
+            let (mut col, mut row) = (from[0] as isize + dc, from[1] as isize + dr);
+            while on_board(col, row) {
+                let to = [col as usize, row as usize];
+                match self.cells[to[0]][to[1]].piece {
+                    Some(piece) => {
+                        if piece.color != color {
+                            moves.push(Move::new(from, to));
+                        }
+                        break;
+                    }
+                    None => moves.push(Move::new(from, to)),
+                }
+                col += dc;
+                row += dr;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Grid, START_FEN};
+
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+    /// Leaf nodes of the legal move tree `depth` plies deep.
+    fn perft(grid: &mut Grid, depth: u32) -> u64 {
+        let moves = grid.legal_moves();
+        if depth <= 1 {
+            return if depth == 0 { 1 } else { moves.len() as u64 };
+        }
+        let mut nodes = 0;
+        for mv in moves {
+            let undo = grid.make_move(mv);
+            nodes += perft(grid, depth - 1);
+            grid.unmake_move(mv, undo);
+        }
+        nodes
+    }
+
+    fn assert_perft(fen: &str, expected: &[u64]) {
+        let mut grid = Grid::from_fen(fen).unwrap();
+        for (depth, &nodes) in expected.iter().enumerate() {
+            assert_eq!(perft(&mut grid, depth as u32 + 1), nodes, "{} at depth {}", fen, depth + 1);
+        }
+    }
+
+    #[test]
+    fn perft_start_position() {
+        assert_perft(START_FEN, &[20, 400, 8902, 197_281]);
+    }
+
+    #[test]
+    fn perft_kiwipete() {
+        assert_perft(KIWIPETE, &[48, 2039, 97_862]);
+    }
+
+    #[test]
+    fn perft_endgame_with_en_passant_pins() {
+        assert_perft("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", &[14, 191, 2812, 43_238]);
+    }
+
+    #[test]
+    fn perft_promotions_and_castling_through_check() {
+        assert_perft("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", &[6, 264, 9467]);
+        assert_perft("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", &[44, 1486, 62_379]);
+    }
+
+    /// Chess960 positions from the published perft results, with castling
+    /// rights given as Shredder-FEN files.
+    #[test]
+    fn perft_chess960() {
+        assert_perft("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", &[21, 528, 12_189, 326_672]);
+        assert_perft("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9", &[21, 807, 18_002, 667_366]);
+        // Castling written as the king taking its rook finds the same moves.
+        let mut grid = Grid::from_fen(KIWIPETE).unwrap();
+        grid.set_chess960(true);
+        assert_eq!(perft(&mut grid, 3), 97_862);
+    }
+
+    /// Every move two plies deep is taken back to the same FEN and hash,
+    /// and the hash kept up to date matches one computed from scratch.
+    #[test]
+    fn make_unmake_restores_position() {
+        let mut grid = Grid::from_fen(KIWIPETE).unwrap();
+        let fen = grid.to_fen();
+        let hash = grid.position_hash();
+        for mv in grid.legal_moves() {
+            let undo = grid.make_move(mv);
+            assert_eq!(grid.position_hash(), grid.compute_hash(), "after {}", mv.to_uci());
+            for reply in grid.legal_moves() {
+                let reply_undo = grid.make_move(reply);
+                assert_eq!(grid.position_hash(), grid.compute_hash(), "after {} {}", mv.to_uci(), reply.to_uci());
+                grid.unmake_move(reply, reply_undo);
+            }
+            grid.unmake_move(mv, undo);
+            assert_eq!(grid.to_fen(), fen, "after taking back {}", mv.to_uci());
+            assert_eq!(grid.position_hash(), hash, "after taking back {}", mv.to_uci());
+        }
+    }
+}
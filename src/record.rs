@@ -0,0 +1,207 @@
+use chess_wasm::eco::Opening;
+use chess_wasm::{ChessColor, Grid, Move, START_FEN};
+
+const PGN_LINE_LENGTH: usize = 79;
+
+/// How a game ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameResult {
+    /// Won by the given side.
+    Checkmate(ChessColor),
+    /// Won by the given side when the other one ran out of time.
+    Timeout(ChessColor),
+    /// A flag fell, but the side with time left could never have mated.
+    TimeoutDraw,
+    /// Drawn for the reason given, e.g. `"stalemate"`.
+    Draw(&'static str),
+}
+
+impl GameResult {
+    /// The result as shown to the players.
+    pub fn message(&self) -> String {
+        match self {
+            GameResult::Checkmate(winner) => format!("{:?} wins by checkmate", winner),
+            GameResult::Timeout(winner) => format!("{:?} wins on time", winner),
+            GameResult::TimeoutDraw => "Draw by timeout vs insufficient material".to_string(),
+            GameResult::Draw(reason) => format!("Draw by {}", reason),
+        }
+    }
+
+    /// The PGN result token.
+    fn pgn_result(&self) -> &'static str {
+        match self {
+            GameResult::Checkmate(ChessColor::White) | GameResult::Timeout(ChessColor::White) => "1-0",
+            GameResult::Checkmate(ChessColor::Black) | GameResult::Timeout(ChessColor::Black) => "0-1",
+            GameResult::TimeoutDraw | GameResult::Draw(_) => "1/2-1/2",
+        }
+    }
+
+    /// One of the values PGN allows for the `Termination` tag.
+    fn termination(&self) -> &'static str {
+        match self {
+            GameResult::Timeout(_) | GameResult::TimeoutDraw => "time forfeit",
+            GameResult::Checkmate(_) | GameResult::Draw(_) => "normal",
+        }
+    }
+}
+
+/// The moves of a game from its starting position, kept once the board has
+/// been reset so the game can still be looked at.
+#[derive(Clone, Debug)]
+pub struct GameRecord {
+    pub start: Grid,
+    pub moves: Vec<Move>,
+    /// Plies, counted from 0, at which the player to move took a hint.
+    pub hints: Vec<usize>,
+    /// How it ended, or `None` while it is still being played.
+    pub result: Option<GameResult>,
+}
+
+impl GameRecord {
+    /// The starting position followed by the position after each move.
+    pub fn positions(&self) -> Vec<Grid> {
+        let mut position = self.start.clone();
+        let mut positions = vec![position.clone()];
+        for &mv in &self.moves {
+            position.make_move(mv);
+            positions.push(position.clone());
+        }
+        positions
+    }
+
+    /// Hints taken by `color`.
+    pub fn hints_by(&self, color: ChessColor) -> usize {
+        let first_mover = self.start.side_to_move();
+        self.hints
+            .iter()
+            .filter(|&&ply| ply.is_multiple_of(2) == (color == first_mover))
+            .count()
+    }
+
+    /// The PGN result token, `*` while the game is going on.
+    fn pgn_result(&self) -> &'static str {
+        self.result.as_ref().map_or("*", GameResult::pgn_result)
+    }
+
+    /// The moves in SAN with move numbers, each move played after taking
+    /// a hint followed by a `{hint}` comment.
+    fn movetext(&self) -> String {
+        let mut words = Vec::new();
+        let mut position = self.start.clone();
+        for (ply, &mv) in self.moves.iter().enumerate() {
+            // Black's moves only get their number at the start or after a comment.
+            let numbered =
+                ply == 0 || position.side_to_move() == ChessColor::White || self.hints.contains(&(ply - 1));
+            words.push(if numbered { position.san_line(&[mv]) } else { position.san(mv) });
+            if self.hints.contains(&ply) {
+                words.push("{hint}".to_string());
+            }
+            position.make_move(mv);
+        }
+        words.join(" ")
+    }
+
+    /// The game in PGN, tagged with its opening when it has one.
+    pub fn to_pgn(&self, opening: Option<&Opening>) -> String {
+        let result = self.pgn_result();
+        let mut tags = vec![
+            ("Event", "Casual game".to_string()),
+            ("Site", "chess-wasm".to_string()),
+            ("Date", "????.??.??".to_string()),
+            ("Round", "-".to_string()),
+            ("White", "?".to_string()),
+            ("Black", "?".to_string()),
+            ("Result", result.to_string()),
+        ];
+        let start = self.start.to_fen();
+        if self.start.chess960() {
+            tags.push(("Variant", "Chess960".to_string()));
+        }
+        if start != START_FEN || self.start.chess960() {
+            tags.push(("SetUp", "1".to_string()));
+            tags.push(("FEN", start));
+        }
+        if let Some(opening) = opening {
+            tags.push(("ECO", opening.code.clone()));
+            tags.push(("Opening", opening.name.clone()));
+        }
+        if let Some(ending) = &self.result {
+            tags.push(("Termination", ending.termination().to_string()));
+        }
+        let mut pgn = String::new();
+        for (name, value) in tags {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            pgn.push_str(&format!("[{} \"{}\"]\n", name, value));
+        }
+        pgn.push('\n');
+        // Movetext lines are kept under 80 characters.
+        let mut movetext = self.movetext();
+        // How the game ended in words, which the tags can't say.
+        if let Some(ending) = &self.result {
+            movetext.push_str(&format!(" {{{}}}", ending.message()));
+        }
+        let mut line = String::new();
+        for word in movetext.split_whitespace().chain([result]) {
+            if !line.is_empty() && line.len() + 1 + word.len() > PGN_LINE_LENGTH {
+                pgn.push_str(&line);
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+        pgn.push_str(&line);
+        pgn.push('\n');
+        pgn
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fools_mate() -> GameRecord {
+        let start = Grid::new();
+        let mut position = start.clone();
+        let mut moves = Vec::new();
+        for text in ["f2f3", "e7e5", "g2g4", "d8h4"] {
+            let mv = position.parse_uci_move(text).unwrap();
+            position.make_move(mv);
+            moves.push(mv);
+        }
+        GameRecord {
+            start,
+            moves,
+            hints: Vec::new(),
+            result: Some(GameResult::Checkmate(ChessColor::Black)),
+        }
+    }
+
+    #[test]
+    fn hints_are_marked() {
+        let record = GameRecord {
+            hints: vec![1, 2],
+            ..fools_mate()
+        };
+        let pgn = record.to_pgn(None);
+        assert!(pgn.contains("1. f3 e5 {hint} 2. g4 {hint} 2... Qh4# {Black wins by checkmate} 0-1"));
+    }
+
+    #[test]
+    fn termination_uses_pgn_values() {
+        let pgn = fools_mate().to_pgn(None);
+        assert!(pgn.contains("[Result \"0-1\"]"));
+        assert!(pgn.contains("[Termination \"normal\"]"));
+        assert!(pgn.ends_with("1. f3 e5 2. g4 Qh4# {Black wins by checkmate} 0-1\n"));
+
+        let record = GameRecord {
+            result: Some(GameResult::TimeoutDraw),
+            ..fools_mate()
+        };
+        let pgn = record.to_pgn(None);
+        assert!(pgn.contains("[Result \"1/2-1/2\"]"));
+        assert!(pgn.contains("[Termination \"time forfeit\"]"));
+    }
+}
@@ -0,0 +1,442 @@
+//! Post-game review: every position of a game is searched to a fixed depth
+//! and each move is scored by how much worse it was than the engine's.
+
+use chess_wasm::search::{mate_distance, SearchLimits, Searcher};
+use chess_wasm::{ChessColor, Grid, Move};
+use macroquad::prelude::*;
+
+use crate::analysis::{draw_arrow, score_text, SearchSlice};
+use crate::layout::PANEL_TEXT;
+use crate::record::GameRecord;
+
+const REVIEW_DEPTH: u32 = 6;
+const REVIEW_TT_MB: usize = 16;
+/// Scores are capped here so a missed mate counts like a lost queen or so,
+/// not like thousands of centipawns.
+const SCORE_CAP: i32 = 1000;
+const LINE_HEIGHT: f32 = 18.0;
+
+const PLAYED_ARROW: Color = Color::new(0.15, 0.45, 0.75, 0.6);
+const BEST_ARROW: Color = Color::new(0.15, 0.65, 0.25, 0.85);
+const SELECTED_ROW: Color = Color::new(1.0, 1.0, 1.0, 0.12);
+
+/// From best to worst.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Classification {
+    Best,
+    Good,
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl Classification {
+    /// Centipawns lost at which a move stops being good, an inaccuracy and
+    /// a mistake.
+    fn from_loss(loss: i32) -> Self {
+        match loss {
+            0 => Classification::Best,
+            1..=49 => Classification::Good,
+            50..=99 => Classification::Inaccuracy,
+            100..=299 => Classification::Mistake,
+            _ => Classification::Blunder,
+        }
+    }
+
+    /// Annotation symbol appended to the move.
+    fn symbol(self) -> &'static str {
+        match self {
+            Classification::Best | Classification::Good => "",
+            Classification::Inaccuracy => "?!",
+            Classification::Mistake => "?",
+            Classification::Blunder => "??",
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            Classification::Best => Color::new(0.5, 0.85, 0.5, 1.0),
+            Classification::Good => PANEL_TEXT,
+            Classification::Inaccuracy => YELLOW,
+            Classification::Mistake => ORANGE,
+            Classification::Blunder => RED,
+        }
+    }
+}
+
+/// One move of the game, judged against the engine.
+#[derive(Clone, Debug)]
+pub struct ReviewedMove {
+    pub mv: Move,
+    /// Move number and SAN, e.g. `12... Nf6`.
+    pub text: String,
+    pub best: Option<Move>,
+    /// Centipawns the move gave away compared with the best one.
+    pub loss: i32,
+    pub classification: Classification,
+    /// 0 to 100, from the drop in winning chances.
+    pub accuracy: f32,
+    pub color: ChessColor,
+}
+
+/// Totals for one player.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PlayerSummary {
+    pub moves: usize,
+    /// Mean of the move accuracies, 0 to 100.
+    pub accuracy: f32,
+    /// Average centipawn loss.
+    pub average_loss: i32,
+    pub inaccuracies: usize,
+    pub mistakes: usize,
+    pub blunders: usize,
+}
+
+pub struct Review {
+    pub active: bool,
+    record: Option<GameRecord>,
+    positions: Vec<Grid>,
+    /// Search results of the positions analysed so far, in order, from the
+    /// point of view of the side to move there.
+    scores: Vec<i32>,
+    best_moves: Vec<Option<Move>>,
+    moves: Vec<ReviewedMove>,
+    searcher: Searcher,
+    slice: SearchSlice,
+    /// Index into `positions` of the position shown.
+    selected: usize,
+    show_best: bool,
+}
+
+impl Default for Review {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Review {
+    pub fn new() -> Self {
+        Review {
+            active: false,
+            record: None,
+            positions: Vec::new(),
+            scores: Vec::new(),
+            best_moves: Vec::new(),
+            moves: Vec::new(),
+            searcher: Searcher::new(REVIEW_TT_MB),
+            slice: SearchSlice::new(),
+            selected: 0,
+            show_best: true,
+        }
+    }
+
+    /// Starts reviewing `record`, showing its final position.
+    pub fn start(&mut self, record: GameRecord) {
+        self.positions = record.positions();
+        self.record = Some(record);
+        self.scores.clear();
+        self.best_moves.clear();
+        self.moves.clear();
+        self.searcher.tt.clear();
+        self.selected = self.positions.len() - 1;
+        self.active = true;
+    }
+
+    pub fn stop(&mut self) {
+        self.active = false;
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.scores.len() == self.positions.len()
+    }
+
+    /// The position to show instead of the game while reviewing.
+    pub fn position(&self) -> Option<&Grid> {
+        self.active.then(|| &self.positions[self.selected])
+    }
+
+    /// Hashes of the game's positions before the one shown.
+    pub fn earlier_positions(&self) -> Vec<u64> {
+        self.positions[..self.selected].iter().map(Grid::position_hash).collect()
+    }
+
+    /// Searches the next position for a slice of this frame.
+    pub fn update(&mut self) {
+        if !self.active || self.is_done() {
+            return;
+        }
+        let ply = self.scores.len();
+        let mut grid = self.positions[ply].clone();
+        let history: Vec<u64> = self.positions[..ply].iter().map(Grid::position_hash).collect();
+        self.searcher.set_history(&history);
+        let limits = SearchLimits::depth(REVIEW_DEPTH);
+        let result = self.slice.search(&mut self.searcher, &mut grid, limits);
+        // The search ends early on a mate, so that counts as finished too.
+        if result.depth < REVIEW_DEPTH && mate_distance(result.score).is_none() {
+            return;
+        }
+        self.scores.push(result.score);
+        self.best_moves.push(result.best_move);
+        if self.scores.len() >= 2 {
+            self.judge(self.scores.len() - 2);
+        }
+    }
+
+    /// Compares move `ply` with the best move of the position before it.
+    fn judge(&mut self, ply: usize) {
+        let Some(record) = &self.record else {
+            return;
+        };
+        let mv = record.moves[ply];
+        let before = &self.positions[ply];
+        let best = self.best_moves[ply];
+        // Both from the point of view of the player who moved.
+        let best_score = self.scores[ply].clamp(-SCORE_CAP, SCORE_CAP);
+        // The best move loses nothing, whatever the next search makes of it.
+        let played_score = if best == Some(mv) {
+            best_score
+        } else {
+            (-self.scores[ply + 1]).clamp(-SCORE_CAP, SCORE_CAP)
+        };
+        let loss = (best_score - played_score).max(0);
+        self.moves.push(ReviewedMove {
+            mv,
+            text: before.san_line(&[mv]),
+            best,
+            loss,
+            classification: Classification::from_loss(loss),
+            accuracy: accuracy(best_score, played_score),
+            color: before.side_to_move(),
+        });
+    }
+
+    pub fn summary(&self, color: ChessColor) -> PlayerSummary {
+        let moves: Vec<&ReviewedMove> = self.moves.iter().filter(|reviewed| reviewed.color == color).collect();
+        let count = |classification| moves.iter().filter(|reviewed| reviewed.classification == classification).count();
+        let mut summary = PlayerSummary {
+            moves: moves.len(),
+            inaccuracies: count(Classification::Inaccuracy),
+            mistakes: count(Classification::Mistake),
+            blunders: count(Classification::Blunder),
+            ..Default::default()
+        };
+        if !moves.is_empty() {
+            summary.accuracy = moves.iter().map(|reviewed| reviewed.accuracy).sum::<f32>() / moves.len() as f32;
+            summary.average_loss = moves.iter().map(|reviewed| reviewed.loss).sum::<i32>() / moves.len() as i32;
+        }
+        summary
+    }
+
+    /// Moves the shown position `delta` plies through the game.
+    pub fn step(&mut self, delta: isize) {
+        let last = self.positions.len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(delta).min(last);
+    }
+
+    pub fn toggle_best(&mut self) {
+        self.show_best = !self.show_best;
+    }
+
+    /// Jumps to the move clicked in the move list, given its content area.
+    pub fn click(&mut self, x: f32, y: f32, panel: Rect) {
+        if !panel.contains(Vec2::new(x, y)) {
+            return;
+        }
+        let row = ((y - panel.y) / LINE_HEIGHT) as usize;
+        let ply = first_row(self.selected, visible_rows(panel)) + row;
+        if ply < self.positions.len() - 1 {
+            self.selected = ply + 1;
+        }
+    }
+
+    /// Panel title, with the progress while positions are still searched.
+    pub fn title(&self) -> String {
+        if self.is_done() {
+            "Review".to_string()
+        } else {
+            format!("Review {}/{}", self.scores.len(), self.positions.len())
+        }
+    }
+
+    /// Arrows for the move that led to the shown position and, if it was
+    /// not the engine's choice, the move it preferred.
+    pub fn draw_arrows(&self, cell_size: f32, offset_x: f32, offset_y: f32) {
+        if !self.active || self.selected == 0 {
+            return;
+        }
+        let Some(record) = &self.record else {
+            return;
+        };
+        let played = record.moves[self.selected - 1];
+        draw_arrow(played, PLAYED_ARROW, 0.12 * cell_size, cell_size, offset_x, offset_y);
+        if let Some(Some(best)) = self.best_moves.get(self.selected - 1) {
+            if self.show_best && *best != played {
+                draw_arrow(*best, BEST_ARROW, 0.18 * cell_size, cell_size, offset_x, offset_y);
+            }
+        }
+    }
+
+    /// The game one move per row, annotated once judged, inside the move
+    /// list's content area.
+    pub fn draw_moves(&self, panel: Rect) {
+        let Some(record) = &self.record else {
+            return;
+        };
+        let visible = visible_rows(panel);
+        let first = first_row(self.selected, visible);
+        for ply in first..record.moves.len().min(first + visible) {
+            let y = panel.y + LINE_HEIGHT * (ply - first + 1) as f32;
+            if ply + 1 == self.selected {
+                draw_rectangle(panel.x - 4.0, y - LINE_HEIGHT + 4.0, panel.w + 8.0, LINE_HEIGHT, SELECTED_ROW);
+            }
+            let Some(reviewed) = self.moves.get(ply) else {
+                let text = self.positions[ply].san_line(&[record.moves[ply]]);
+                draw_text(&text, panel.x, y, 18.0, GRAY);
+                continue;
+            };
+            let classification = reviewed.classification;
+            let mut text = format!("{}{}", reviewed.text, classification.symbol());
+            if record.hints.contains(&ply) {
+                text.push_str(" (hint)");
+            }
+            draw_text(&text, panel.x, y, 18.0, classification.color());
+            if reviewed.loss > 0 {
+                let loss = format!("-{:.2}", reviewed.loss as f32 / 100.0);
+                let width = measure_text(&loss, None, 16, 1.0).width;
+                draw_text(&loss, panel.right() - width, y, 16.0, classification.color());
+            }
+        }
+    }
+
+    /// Progress or accuracies, and the shown move against the engine's,
+    /// inside the engine panel's content area.
+    pub fn draw_summary(&self, panel: Rect) {
+        let mut lines = Vec::new();
+        for (color, name) in [(ChessColor::White, "White"), (ChessColor::Black, "Black")] {
+            let summary = self.summary(color);
+            if summary.moves > 0 {
+                lines.push(format!(
+                    "{} {:.1}% acpl {} ?!{} ?{} ??{}",
+                    name,
+                    summary.accuracy,
+                    summary.average_loss,
+                    summary.inaccuracies,
+                    summary.mistakes,
+                    summary.blunders
+                ));
+            }
+        }
+        match self.selected.checked_sub(1).and_then(|ply| self.moves.get(ply)) {
+            Some(reviewed) => {
+                let mut text = format!("{}{}", reviewed.text, reviewed.classification.symbol());
+                let before = &self.positions[self.selected - 1];
+                if let Some(best) = reviewed.best.filter(|&best| best != reviewed.mv) {
+                    let score = match before.side_to_move() {
+                        ChessColor::White => self.scores[self.selected - 1],
+                        ChessColor::Black => -self.scores[self.selected - 1],
+                    };
+                    text.push_str(&format!(" best {} {}", before.san(best), score_text(score)));
+                }
+                lines.push(text);
+            }
+            None if self.selected == 0 => lines.push("Start position".to_string()),
+            None => {}
+        }
+        if let Some(record) = &self.record {
+            if !record.hints.is_empty() {
+                lines.push(format!(
+                    "Hints: White {}, Black {}",
+                    record.hints_by(ChessColor::White),
+                    record.hints_by(ChessColor::Black)
+                ));
+            }
+            if let Some(result) = &record.result {
+                lines.push(result.message());
+            }
+        }
+        lines.push("Left/Right: step  B: best move  R: close".to_string());
+
+        for (i, line) in lines.iter().enumerate() {
+            draw_text(line, panel.x, panel.y + 16.0 * (i + 1) as f32, 16.0, PANEL_TEXT);
+        }
+    }
+}
+
+fn visible_rows(panel: Rect) -> usize {
+    ((panel.h / LINE_HEIGHT) as usize).max(1)
+}
+
+/// First move listed, so that the shown one stays in view.
+fn first_row(selected: usize, visible: usize) -> usize {
+    selected.saturating_sub(visible)
+}
+
+/// Expected score in percent for a centipawn advantage, using the curve
+/// fitted to rated games that Lichess uses for its accuracy figures.
+fn win_chance(centipawns: i32) -> f32 {
+    50.0 + 50.0 * (2.0 / (1.0 + (-0.003_682_08 * centipawns as f32).exp()) - 1.0)
+}
+
+/// Accuracy of a move, 0 to 100, from the drop in winning chances between
+/// the best move's score and its own, with Lichess's formula. Losing
+/// nothing is 100.
+fn accuracy(best_score: i32, played_score: i32) -> f32 {
+    let win_drop = win_chance(best_score) - win_chance(played_score);
+    if win_drop <= 0.0 {
+        return 100.0;
+    }
+    (103.1668 * (-0.04354 * win_drop).exp() - 3.1669).clamp(0.0, 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classification_thresholds() {
+        for (loss, expected) in [
+            (0, Classification::Best),
+            (1, Classification::Good),
+            (49, Classification::Good),
+            (50, Classification::Inaccuracy),
+            (99, Classification::Inaccuracy),
+            (100, Classification::Mistake),
+            (299, Classification::Mistake),
+            (300, Classification::Blunder),
+            (SCORE_CAP * 2, Classification::Blunder),
+        ] {
+            assert_eq!(Classification::from_loss(loss), expected, "loss {}", loss);
+        }
+    }
+
+    #[test]
+    fn win_chances() {
+        assert_eq!(win_chance(0), 50.0);
+        for centipawns in [1, 50, 300, SCORE_CAP] {
+            assert!((win_chance(centipawns) + win_chance(-centipawns) - 100.0).abs() < 1e-3);
+            assert!(win_chance(centipawns) > win_chance(centipawns - 1));
+        }
+        assert!(win_chance(SCORE_CAP) > 95.0 && win_chance(SCORE_CAP) < 100.0);
+    }
+
+    #[test]
+    fn accuracy_falls_with_the_loss() {
+        for score in [-SCORE_CAP, -200, 0, 150, SCORE_CAP] {
+            assert_eq!(accuracy(score, score), 100.0);
+            // Doing better than the engine expected is no more than perfect.
+            assert_eq!(accuracy(score, score + 50), 100.0);
+        }
+        for best in [-300, 0, 300] {
+            let mut previous = (100.0, Classification::Best);
+            for loss in (0..=2 * SCORE_CAP).step_by(10) {
+                let current = (accuracy(best, best - loss), Classification::from_loss(loss));
+                assert!((0.0..=100.0).contains(&current.0));
+                assert!(current.0 <= previous.0, "best {} loss {}", best, loss);
+                assert!(current.1 >= previous.1, "loss {}", loss);
+                previous = current;
+            }
+        }
+        // Giving away a pawn from equality costs about a third.
+        assert!((60.0..70.0).contains(&accuracy(0, -100)));
+    }
+}
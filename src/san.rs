@@ -0,0 +1,159 @@
+//! Standard algebraic notation, as used in move lists and PGN.
+
+use crate::{ChessColor, Grid, Move, Piece, TypePiece};
+
+fn piece_letter(type_of_piece: TypePiece) -> char {
+    Piece {
+        type_of_piece,
+        color: ChessColor::White,
+    }
+    .fen_char()
+}
+
+impl Grid {
+    /// `mv` written in SAN, e.g. `Nbd2`, `exd6`, `O-O`, `e8=Q+`. The move
+    /// has to be legal in this position.
+    pub fn san(&self, mv: Move) -> String {
+        let Some(piece) = self.find_cell(mv.from).piece else {
+            return mv.to_uci();
+        };
+        let mut text = String::new();
+        if let Some((_, rook_to)) = self.castling_rook_move(mv) {
+            text.push_str(if rook_to[0] == 5 { "O-O" } else { "O-O-O" });
+        } else {
+            let capture = self.find_cell(mv.to).piece.is_some()
+                || (piece.type_of_piece == TypePiece::Pawn && mv.from[0] != mv.to[0]);
+            let destination = Grid::position_to_chess_notation(mv.to);
+            if piece.type_of_piece == TypePiece::Pawn {
+                if capture {
+                    text.push((b'a' + mv.from[0] as u8) as char);
+                    text.push('x');
+                }
+                text.push_str(&destination);
+                if let Some(promotion) = mv.promotion {
+                    text.push('=');
+                    text.push(piece_letter(promotion));
+                }
+            } else {
+                text.push(piece_letter(piece.type_of_piece));
+                text.push_str(&self.disambiguation(mv, piece));
+                if capture {
+                    text.push('x');
+                }
+                text.push_str(&destination);
+            }
+        }
+
+        let mut after = self.clone();
+        after.make_move(mv);
+        if after.is_in_check() {
+            text.push(if after.legal_moves().is_empty() { '#' } else { '+' });
+        }
+        text
+    }
+
+    /// File, rank or both of the origin when another piece of the same kind
+    /// could also go to the destination.
+    fn disambiguation(&self, mv: Move, piece: Piece) -> String {
+        let rivals: Vec<[usize; 2]> = self
+            .legal_moves()
+            .into_iter()
+            .filter(|other| {
+                other.to == mv.to && other.from != mv.from && self.find_cell(other.from).piece == Some(piece)
+            })
+            .map(|other| other.from)
+            .collect();
+        if rivals.is_empty() {
+            return String::new();
+        }
+        let square = Grid::position_to_chess_notation(mv.from);
+        if rivals.iter().all(|rival| rival[0] != mv.from[0]) {
+            square[..1].to_string()
+        } else if rivals.iter().all(|rival| rival[1] != mv.from[1]) {
+            square[1..].to_string()
+        } else {
+            square
+        }
+    }
+
+    /// A line of moves from this position in SAN, with move numbers, e.g.
+    /// `12... Nf6 13. e5`. Stops at the first illegal move.
+    pub fn san_line(&self, moves: &[Move]) -> String {
+        let mut position = self.clone();
+        let mut parts = Vec::new();
+        for (i, &mv) in moves.iter().enumerate() {
+            if !position.legal_moves().contains(&mv) {
+                break;
+            }
+            match position.side_to_move() {
+                ChessColor::White => parts.push(format!("{}.", position.fullmove_number)),
+                ChessColor::Black if i == 0 => parts.push(format!("{}...", position.fullmove_number)),
+                ChessColor::Black => {}
+            }
+            parts.push(position.san(mv));
+            position.make_move(mv);
+        }
+        parts.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn san(fen: &str, text: &str) -> String {
+        let grid = Grid::from_fen(fen).unwrap();
+        let mv = grid.parse_uci_move(text).unwrap_or_else(|| panic!("{} is not legal", text));
+        grid.san(mv)
+    }
+
+    #[test]
+    fn disambiguation() {
+        assert_eq!(san("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1", "b1d2"), "Nbd2");
+        assert_eq!(san("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1", "a1a3"), "R1a3");
+        assert_eq!(san("4k3/8/8/8/8/Q7/8/Q1Q1K3 w - - 0 1", "a1b2"), "Qa1b2");
+        // Only pieces that can legally go there count: the pinned knight doesn't.
+        assert_eq!(san("4k3/8/8/8/8/8/8/1N2KN1r w - - 0 1", "b1d2"), "Nd2");
+    }
+
+    #[test]
+    fn pawns() {
+        assert_eq!(san("8/4P2k/8/8/8/8/8/K7 w - - 0 1", "e7e8q"), "e8=Q");
+        assert_eq!(san("3r3k/4P3/8/8/8/8/8/K7 w - - 0 1", "e7d8n"), "exd8=N");
+        assert_eq!(san("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), "exd6");
+    }
+
+    #[test]
+    fn check_and_mate() {
+        assert_eq!(san("7k/4P3/8/8/8/8/8/K7 w - - 0 1", "e7e8q"), "e8=Q+");
+        assert_eq!(san("k7/8/1K6/8/8/8/8/7R w - - 0 1", "h1h8"), "Rh8#");
+    }
+
+    #[test]
+    fn castling() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        assert_eq!(san(fen, "e1g1"), "O-O");
+        assert_eq!(san(fen, "e1c1"), "O-O-O");
+        // Chess960 castling where the king is already on its square.
+        assert_eq!(san("6kr/8/8/8/8/8/8/6KR w Kk - 0 1", "g1h1"), "O-O");
+        assert_eq!(san("1rk5/8/8/8/8/8/8/1RK5 w Qq - 0 1", "c1b1"), "O-O-O");
+    }
+
+    #[test]
+    fn lines() {
+        let mut position = Grid::new();
+        let mut moves = Vec::new();
+        for text in ["e2e4", "e7e5", "g1f3"] {
+            let mv = position.parse_uci_move(text).unwrap();
+            position.make_move(mv);
+            moves.push(mv);
+        }
+        let grid = Grid::new();
+        assert_eq!(grid.san_line(&moves), "1. e4 e5 2. Nf3");
+        let mut after = grid.clone();
+        after.make_move(moves[0]);
+        assert_eq!(after.san_line(&moves[1..]), "1... e5 2. Nf3");
+        // An illegal move ends the line.
+        assert_eq!(after.san_line(&[moves[1], moves[0]]), "1... e5");
+    }
+}
@@ -0,0 +1,858 @@
+//! Alpha-beta search over `Grid`.
+
+use std::cmp::Reverse;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::endgame::{self, Outcome};
+use crate::eval::evaluate;
+use crate::syzygy::{Tablebase, Wdl};
+use crate::tt::{Bound, TranspositionTable, DEFAULT_TT_MB};
+use crate::{ChessColor, Grid, Move, TypePiece};
+
+pub const MATE: i32 = 30_000;
+const INFINITY: i32 = 32_000;
+/// Scores beyond this are mates, counted in plies from the root.
+const MATE_BOUND: i32 = MATE - 1_000;
+/// Deepest ply killer moves are kept for.
+const MAX_PLY: usize = 64;
+/// Score of a position the tablebases call won, less the plies to reach it;
+/// below the mate scores as the mate itself may be far off.
+pub const TB_WIN: i32 = MATE_BOUND - 200;
+/// How often, in nodes, the stop flag and the clock are looked at.
+const STOP_CHECK_INTERVAL: u64 = 2048;
+/// History scores are halved once one of them passes this, so they stay
+/// below the killer move scores.
+const HISTORY_LIMIT: i32 = 50_000;
+
+// Ordering scores, highest searched first.
+const HASH_MOVE: i32 = 1_000_000;
+const GOOD_CAPTURE: i32 = 200_000;
+const PROMOTION: i32 = 150_000;
+const KILLER: [i32; 2] = [100_000, 90_000];
+const LOSING_CAPTURE: i32 = -200_000;
+
+/// Counters for one search, to measure how well moves are ordered.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SearchStats {
+    /// Nodes of the main search.
+    pub nodes: u64,
+    /// Nodes of the capture-only search at the leaves.
+    pub quiescence_nodes: u64,
+    pub tt_hits: u64,
+    pub tt_cutoffs: u64,
+    pub beta_cutoffs: u64,
+    /// Beta cutoffs produced by the first move searched.
+    pub first_move_cutoffs: u64,
+    /// Captures skipped in quiescence because they lose material.
+    pub losing_captures_pruned: u64,
+    /// Positions scored from the endgame tablebases or the solved endgames.
+    pub tablebase_hits: u64,
+}
+
+impl SearchStats {
+    pub fn total_nodes(&self) -> u64 {
+        self.nodes + self.quiescence_nodes
+    }
+
+    /// Share of cutoffs found on the first move, the usual measure of
+    /// ordering quality.
+    pub fn first_move_cutoff_rate(&self) -> f32 {
+        if self.beta_cutoffs == 0 {
+            0.0
+        } else {
+            self.first_move_cutoffs as f32 / self.beta_cutoffs as f32
+        }
+    }
+}
+
+/// When to end a search. Without any limit it runs until stopped.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SearchLimits {
+    /// Deepest iteration to search.
+    pub depth: Option<u32>,
+    /// No new iteration is started once this much time has passed.
+    pub soft_time: Option<Duration>,
+    /// The iteration in progress is abandoned once this much time has passed.
+    pub hard_time: Option<Duration>,
+    /// The iteration in progress is abandoned after this many nodes.
+    pub nodes: Option<u64>,
+    /// Number of best root moves to find lines for; 0 counts as 1.
+    pub multipv: usize,
+    /// Searching on the opponent's time: the time limits only start to run
+    /// once the ponder hit handle is set.
+    pub ponder: bool,
+}
+
+impl SearchLimits {
+    pub fn depth(depth: u32) -> Self {
+        SearchLimits {
+            depth: Some(depth),
+            ..Default::default()
+        }
+    }
+}
+
+/// One of the best root moves with the line expected to follow it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PvLine {
+    /// Centipawns from the side to move's point of view.
+    pub score: i32,
+    pub moves: Vec<Move>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SearchResult {
+    pub best_move: Option<Move>,
+    /// Centipawns from the side to move's point of view.
+    pub score: i32,
+    pub depth: u32,
+    pub pv: Vec<Move>,
+    /// Best lines first, as many as `SearchLimits::multipv` asked for and
+    /// the position has moves.
+    pub lines: Vec<PvLine>,
+    pub nodes: u64,
+    pub stats: SearchStats,
+    /// Permille of the transposition table filled by this search.
+    pub hashfull: u32,
+}
+
+pub struct Searcher {
+    pub tt: TranspositionTable,
+    stats: SearchStats,
+    killers: [[Option<Move>; 2]; MAX_PLY],
+    /// Cutoff counts of quiet moves, by colour, origin and destination.
+    history: Box<[[[i32; 64]; 64]; 2]>,
+    stop: Arc<AtomicBool>,
+    /// Set when the move pondered on is played, starting the clock.
+    ponderhit: Arc<AtomicBool>,
+    /// Whether the search has time limits, and whether they wait for a
+    /// ponder hit.
+    timed: bool,
+    pondering: bool,
+    /// Set once the current iteration has to be thrown away.
+    aborted: bool,
+    /// Whether the current iteration may be abandoned at all.
+    can_abort: bool,
+    start: Option<Instant>,
+    hard_time: Option<Duration>,
+    node_limit: Option<u64>,
+    /// Root moves left out, as they already head a better MultiPV line.
+    excluded: Vec<Move>,
+    root_best: Option<Move>,
+    tablebase: Option<Arc<Tablebase>>,
+    /// Root moves the solved endgames or the tablebases allow, by position
+    /// hash; empty when the root is in neither.
+    tablebase_root: Option<(u64, Vec<Move>)>,
+    root_moves: Vec<Move>,
+    /// Root moves the caller restricted the search to; all when empty.
+    search_moves: Vec<Move>,
+    /// Hashes of the game's positions before the root, oldest first.
+    game: Vec<u64>,
+    /// The game's positions followed by those on the way to the current node.
+    path: Vec<u64>,
+    /// Hash of the last root searched, so resuming the same search in
+    /// slices doesn't start a new one each time.
+    last_root: Option<u64>,
+}
+
+impl Default for Searcher {
+    fn default() -> Self {
+        Self::new(DEFAULT_TT_MB)
+    }
+}
+
+/// Whether `score` is a mate or tablebase score, counted from the root.
+fn is_ply_relative(score: i32) -> bool {
+    score.abs() > TB_WIN - 2 * MAX_PLY as i32
+}
+
+/// Mate and tablebase scores are stored relative to the node so they stay
+/// correct when the same position is reached at a different ply.
+fn score_to_tt(score: i32, ply: u32) -> i32 {
+    if is_ply_relative(score) {
+        score + score.signum() * ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: u32) -> i32 {
+    if is_ply_relative(score) {
+        score - score.signum() * ply as i32
+    } else {
+        score
+    }
+}
+
+/// Whether `score` is a tablebase win or loss rather than an evaluation.
+pub fn is_tablebase_score(score: i32) -> bool {
+    mate_distance(score).is_none() && is_ply_relative(score)
+}
+
+/// Moves to mate for a mate score, negative when the side to move is
+/// getting mated.
+pub fn mate_distance(score: i32) -> Option<i32> {
+    if score > MATE_BOUND {
+        Some((MATE - score + 1) / 2)
+    } else if score < -MATE_BOUND {
+        Some(-(MATE + score + 1) / 2)
+    } else {
+        None
+    }
+}
+
+/// How many times the position in `grid` has occurred, counting this one,
+/// given the hashes of the positions before it, oldest first. Positions
+/// before the last capture or pawn move can't repeat.
+pub fn repetitions(grid: &Grid, earlier: &[u64]) -> usize {
+    let current = grid.position_hash();
+    1 + earlier
+        .iter()
+        .rev()
+        .take(grid.halfmove_clock() as usize)
+        .skip(1)
+        .step_by(2)
+        .filter(|&&hash| hash == current)
+        .count()
+}
+
+/// Draws the arbiter applies without being asked, with `earlier` as for
+/// [`repetitions`].
+pub fn automatic_draw(grid: &Grid, earlier: &[u64]) -> Option<&'static str> {
+    if grid.is_stalemate() {
+        Some("stalemate")
+    } else if grid.is_insufficient_material() {
+        Some("insufficient material")
+    } else if repetitions(grid, earlier) >= 5 {
+        Some("fivefold repetition")
+    } else if grid.halfmove_clock() >= 150 {
+        Some("the 75-move rule")
+    } else {
+        None
+    }
+}
+
+/// Draws the side to move may claim.
+pub fn claimable_draw(grid: &Grid, earlier: &[u64]) -> Option<&'static str> {
+    if repetitions(grid, earlier) >= 3 {
+        Some("threefold repetition")
+    } else if grid.halfmove_clock() >= 100 {
+        Some("the fifty-move rule")
+    } else {
+        None
+    }
+}
+
+fn square_index(square: [usize; 2]) -> usize {
+    square[1] * 8 + square[0]
+}
+
+fn color_index(color: ChessColor) -> usize {
+    match color {
+        ChessColor::White => 0,
+        ChessColor::Black => 1,
+    }
+}
+
+/// Attacker rank for MVV-LVA, with the king last.
+fn attacker_rank(type_of_piece: TypePiece) -> i32 {
+    match type_of_piece {
+        TypePiece::Pawn => 1,
+        TypePiece::Knight => 2,
+        TypePiece::Bishop => 3,
+        TypePiece::Rook => 4,
+        TypePiece::Queen => 5,
+        TypePiece::King => 6,
+    }
+}
+
+/// The piece a move takes, counting en passant but not the rook a king
+/// castles with in Chess960.
+fn captured_piece(grid: &Grid, mv: Move) -> Option<TypePiece> {
+    let mover = grid.find_cell(mv.from).piece?;
+    if let Some(piece) = grid.find_cell(mv.to).piece {
+        return (piece.color != mover.color).then_some(piece.type_of_piece);
+    }
+    (mover.type_of_piece == TypePiece::Pawn && mv.from[0] != mv.to[0]).then_some(TypePiece::Pawn)
+}
+
+/// Most valuable victim first, then least valuable attacker.
+fn mvv_lva(grid: &Grid, mv: Move, victim: TypePiece) -> i32 {
+    let attacker = grid.find_cell(mv.from).piece.map_or(TypePiece::Pawn, |piece| piece.type_of_piece);
+    victim.value() * 100 - attacker_rank(attacker)
+}
+
+impl Searcher {
+    pub fn new(tt_megabytes: usize) -> Self {
+        Searcher {
+            tt: TranspositionTable::new(tt_megabytes),
+            stats: SearchStats::default(),
+            killers: [[None; 2]; MAX_PLY],
+            history: Box::new([[[0; 64]; 64]; 2]),
+            stop: Arc::new(AtomicBool::new(false)),
+            ponderhit: Arc::new(AtomicBool::new(false)),
+            timed: false,
+            pondering: false,
+            aborted: false,
+            can_abort: false,
+            start: None,
+            hard_time: None,
+            node_limit: None,
+            excluded: Vec::new(),
+            root_best: None,
+            tablebase: None,
+            tablebase_root: None,
+            root_moves: Vec::new(),
+            search_moves: Vec::new(),
+            game: Vec::new(),
+            path: Vec::new(),
+            last_root: None,
+        }
+    }
+
+    /// Flag that ends the running search when set, usable from another
+    /// thread. `search_with` leaves it alone, so a stop sent before the
+    /// search got going is not lost; clear it before starting.
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    /// Flag that starts the clock of a `ponder` search when set, usable from
+    /// another thread. Like the stop flag it is left for the caller to clear.
+    pub fn ponderhit_handle(&self) -> Arc<AtomicBool> {
+        self.ponderhit.clone()
+    }
+
+    /// Endgame tablebases to score positions from, in the tree and at the
+    /// root, where only the moves keeping the best result are searched.
+    pub fn set_tablebase(&mut self, tablebase: Option<Arc<Tablebase>>) {
+        self.tablebase = tablebase;
+        self.tablebase_root = None;
+    }
+
+    /// Hashes of the positions played before the one to be searched, oldest
+    /// first, so the search sees the repetitions they lead to.
+    pub fn set_history(&mut self, history: &[u64]) {
+        self.game = history.to_vec();
+    }
+
+    /// Legal root moves to choose from, or all of them when empty.
+    pub fn set_search_moves(&mut self, moves: &[Move]) {
+        self.search_moves = moves.to_vec();
+    }
+
+    /// Iterative deepening up to `depth` plies.
+    pub fn search(&mut self, grid: &mut Grid, depth: u32) -> SearchResult {
+        self.stop.store(false, Ordering::Relaxed);
+        self.search_with(grid, SearchLimits::depth(depth), |_| {})
+    }
+
+    /// Iterative deepening within `limits`, calling `on_iteration` after
+    /// every completed depth. The first iteration always completes. Calls
+    /// for the root of the previous one carry on with its table entries and
+    /// move ordering as they are.
+    pub fn search_with(
+        &mut self,
+        grid: &mut Grid,
+        limits: SearchLimits,
+        mut on_iteration: impl FnMut(&SearchResult),
+    ) -> SearchResult {
+        let root = grid.position_hash();
+        if self.last_root != Some(root) {
+            self.tt.new_search();
+            self.age_history();
+            self.last_root = Some(root);
+        }
+        self.stats = SearchStats::default();
+        self.killers = [[None; 2]; MAX_PLY];
+        self.aborted = false;
+        self.hard_time = limits.hard_time;
+        self.node_limit = limits.nodes;
+        // Only read the clock when asked to, as wasm has none.
+        self.timed = limits.soft_time.is_some() || limits.hard_time.is_some();
+        self.pondering = limits.ponder;
+        self.start = None;
+        self.start_clock();
+
+        self.path.clone_from(&self.game);
+        self.root_moves = self.tablebase_root_moves(grid);
+        if !self.search_moves.is_empty() {
+            // The tablebases only narrow the choice down when they keep one of the moves asked for.
+            let allowed: Vec<Move> = self
+                .root_moves
+                .iter()
+                .filter(|mv| self.search_moves.contains(mv))
+                .copied()
+                .collect();
+            self.root_moves = if allowed.is_empty() { self.search_moves.clone() } else { allowed };
+        }
+        let root_move_count = match self.root_moves.len() {
+            0 => grid.legal_moves().len(),
+            count => count,
+        };
+        let line_count = limits.multipv.max(1).min(root_move_count);
+        let mut result = SearchResult::default();
+        for current in 1..=limits.depth.unwrap_or(MAX_PLY as u32).max(1) {
+            self.can_abort = current > 1;
+            let mut lines: Vec<PvLine> = Vec::with_capacity(line_count);
+            let mut score = 0;
+            for _ in 0..line_count.max(1) {
+                self.excluded = lines.iter().map(|line| line.moves[0]).collect();
+                self.root_best = None;
+                score = self.negamax(grid, current, 0, -INFINITY, INFINITY);
+                if self.aborted {
+                    break;
+                }
+                let Some(mv) = self.root_best else {
+                    break;
+                };
+                let undo = grid.make_move(mv);
+                let mut moves = vec![mv];
+                moves.extend(self.principal_variation(grid, current - 1));
+                grid.unmake_move(mv, undo);
+                lines.push(PvLine { score, moves });
+            }
+            self.excluded.clear();
+            if self.aborted {
+                break;
+            }
+            let (best_move, score, pv) = match lines.first() {
+                Some(line) => (Some(line.moves[0]), line.score, line.moves.clone()),
+                None => (None, score, Vec::new()),
+            };
+            result = SearchResult {
+                best_move,
+                score,
+                depth: current,
+                pv,
+                lines,
+                nodes: self.stats.total_nodes(),
+                stats: self.stats,
+                hashfull: self.tt.hashfull(),
+            };
+            on_iteration(&result);
+            if score.abs() > MATE_BOUND || self.stop.load(Ordering::Relaxed) {
+                break;
+            }
+            self.start_clock();
+            if let (Some(start), Some(soft_time)) = (self.start, limits.soft_time) {
+                if start.elapsed() >= soft_time {
+                    break;
+                }
+            }
+        }
+        result
+    }
+
+    /// The root moves the solved endgames or the tablebases keep, worked
+    /// out once per position.
+    fn tablebase_root_moves(&mut self, grid: &Grid) -> Vec<Move> {
+        let key = grid.position_hash();
+        match &self.tablebase_root {
+            Some((cached, moves)) if *cached == key => moves.clone(),
+            _ => {
+                let moves = endgame::best_moves(grid)
+                    .or_else(|| self.tablebase.as_ref()?.best_moves(grid))
+                    .unwrap_or_default();
+                self.tablebase_root = Some((key, moves.clone()));
+                moves
+            }
+        }
+    }
+
+    /// Whether the position with `key` came up before with the same side to
+    /// move since the last capture or pawn move. A single repetition is
+    /// scored as a draw, as whatever could be done from it could be done
+    /// the first time.
+    fn is_repetition(&self, key: u64, halfmove_clock: u32) -> bool {
+        self.path
+            .iter()
+            .rev()
+            .take(halfmove_clock as usize)
+            .skip(1)
+            .step_by(2)
+            .any(|&hash| hash == key)
+    }
+
+    /// Starts the time limits running, unless still waiting for a ponder hit.
+    fn start_clock(&mut self) {
+        let waiting = self.pondering && !self.ponderhit.load(Ordering::Relaxed);
+        if self.timed && self.start.is_none() && !waiting {
+            self.start = Some(Instant::now());
+        }
+    }
+
+    /// Notices a stop request or an expired hard limit every few nodes.
+    fn should_abort(&mut self) -> bool {
+        if self.aborted {
+            return true;
+        }
+        if !self.can_abort || !self.stats.total_nodes().is_multiple_of(STOP_CHECK_INTERVAL) {
+            return false;
+        }
+        self.start_clock();
+        let out_of_time = match (self.start, self.hard_time) {
+            (Some(start), Some(hard_time)) => start.elapsed() >= hard_time,
+            _ => false,
+        };
+        let out_of_nodes = self.node_limit.is_some_and(|limit| self.stats.total_nodes() >= limit);
+        self.aborted = out_of_time || out_of_nodes || self.stop.load(Ordering::Relaxed);
+        self.aborted
+    }
+
+    fn negamax(&mut self, grid: &mut Grid, depth: u32, ply: u32, mut alpha: i32, beta: i32) -> i32 {
+        if depth == 0 {
+            return self.quiescence(grid, ply, alpha, beta);
+        }
+        self.stats.nodes += 1;
+        if self.should_abort() {
+            return 0;
+        }
+        let key = grid.position_hash();
+        if ply > 0 && self.is_repetition(key, grid.halfmove_clock()) {
+            return 0;
+        }
+
+        let mut hash_move = None;
+        if let Some(entry) = self.tt.probe(key) {
+            self.stats.tt_hits += 1;
+            hash_move = entry.best_move;
+            if ply > 0 && entry.depth as u32 >= depth {
+                let score = score_from_tt(entry.score, ply);
+                let cutoff = match entry.bound {
+                    Bound::Exact => true,
+                    Bound::Lower => score >= beta,
+                    Bound::Upper => score <= alpha,
+                };
+                if cutoff {
+                    self.stats.tt_cutoffs += 1;
+                    return score;
+                }
+            }
+        }
+
+        let mut moves = grid.legal_moves();
+        if moves.is_empty() {
+            return if grid.is_in_check() { -MATE + ply as i32 } else { 0 };
+        }
+        if ply > 0 && (grid.halfmove_clock() >= 100 || grid.is_insufficient_material()) {
+            return 0;
+        }
+        if ply > 0 {
+            if let Some(outcome) = endgame::probe(grid) {
+                self.stats.tablebase_hits += 1;
+                let score = match outcome {
+                    Outcome::Win(plies) => MATE - (ply + plies) as i32,
+                    Outcome::Loss(plies) => -MATE + (ply + plies) as i32,
+                    Outcome::Draw => 0,
+                };
+                self.tt.store(key, depth, score_to_tt(score, ply), Bound::Exact, None);
+                return score;
+            }
+        }
+        // Probing right after a capture or pawn move is enough to catch
+        // every way into the tables, and keeps the fifty-move count out of it.
+        if ply > 0 && grid.halfmove_clock() == 0 {
+            if let Some(wdl) = self.tablebase.as_ref().and_then(|tablebase| tablebase.probe_wdl(grid)) {
+                self.stats.tablebase_hits += 1;
+                let score = match wdl {
+                    Wdl::Win => TB_WIN - ply as i32,
+                    Wdl::Loss => -TB_WIN + ply as i32,
+                    // Nearly a draw, but still worth playing on.
+                    other => 2 * other as i32,
+                };
+                self.tt.store(key, depth, score_to_tt(score, ply), Bound::Exact, None);
+                return score;
+            }
+        }
+
+        if ply == 0 && !self.root_moves.is_empty() {
+            moves.retain(|mv| self.root_moves.contains(mv));
+        }
+        if ply == 0 && !self.excluded.is_empty() {
+            moves.retain(|mv| !self.excluded.contains(mv));
+        }
+        self.order_moves(grid, &mut moves, hash_move, ply);
+
+        let original_alpha = alpha;
+        let mut best_score = -INFINITY;
+        let mut best_move = None;
+        self.path.push(key);
+        for (i, mv) in moves.into_iter().enumerate() {
+            let quiet = captured_piece(grid, mv).is_none() && mv.promotion.is_none();
+            let undo = grid.make_move(mv);
+            let score = -self.negamax(grid, depth - 1, ply + 1, -beta, -alpha);
+            grid.unmake_move(mv, undo);
+
+            if score > best_score {
+                best_score = score;
+                best_move = Some(mv);
+            }
+            if score > alpha {
+                alpha = score;
+            }
+            if alpha >= beta {
+                self.stats.beta_cutoffs += 1;
+                if i == 0 {
+                    self.stats.first_move_cutoffs += 1;
+                }
+                if quiet {
+                    self.record_cutoff(grid.side_to_move(), mv, depth, ply);
+                }
+                break;
+            }
+        }
+        self.path.pop();
+        if self.aborted {
+            return 0;
+        }
+        if ply == 0 {
+            self.root_best = best_move;
+            // A MultiPV line is not the position's best move.
+            if !self.excluded.is_empty() {
+                return best_score;
+            }
+        }
+
+        let bound = if best_score >= beta {
+            Bound::Lower
+        } else if best_score > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.tt.store(key, depth, score_to_tt(best_score, ply), bound, best_move);
+        best_score
+    }
+
+    /// Searches captures only, so the evaluation is never taken in the
+    /// middle of an exchange. In check every evasion is tried instead.
+    fn quiescence(&mut self, grid: &mut Grid, ply: u32, mut alpha: i32, beta: i32) -> i32 {
+        self.stats.quiescence_nodes += 1;
+        if self.should_abort() {
+            return 0;
+        }
+        let in_check = grid.is_in_check();
+        if !in_check {
+            let stand_pat = evaluate(grid);
+            if stand_pat >= beta {
+                return stand_pat;
+            }
+            alpha = alpha.max(stand_pat);
+        }
+
+        let mut moves = grid.legal_moves();
+        if moves.is_empty() && in_check {
+            return -MATE + ply as i32;
+        }
+        if !in_check {
+            let mut pruned = 0;
+            moves.retain(|&mv| match captured_piece(grid, mv) {
+                Some(_) if grid.see(mv) < 0 => {
+                    pruned += 1;
+                    false
+                }
+                Some(_) => true,
+                None => mv.promotion == Some(TypePiece::Queen),
+            });
+            self.stats.losing_captures_pruned += pruned;
+        }
+        moves.sort_by_cached_key(|&mv| {
+            Reverse(captured_piece(grid, mv).map_or(0, |victim| mvv_lva(grid, mv, victim)))
+        });
+
+        let mut best_score = if in_check { -INFINITY } else { alpha };
+        for mv in moves {
+            let undo = grid.make_move(mv);
+            let score = -self.quiescence(grid, ply + 1, -beta, -alpha);
+            grid.unmake_move(mv, undo);
+            if score > best_score {
+                best_score = score;
+            }
+            if score > alpha {
+                alpha = score;
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+        best_score
+    }
+
+    fn order_moves(&self, grid: &Grid, moves: &mut [Move], hash_move: Option<Move>, ply: u32) {
+        let killers = self.killers.get(ply as usize).copied().unwrap_or([None; 2]);
+        let color = color_index(grid.side_to_move());
+        moves.sort_by_cached_key(|&mv| {
+            let score = if Some(mv) == hash_move {
+                HASH_MOVE
+            } else if let Some(victim) = captured_piece(grid, mv) {
+                let base = if grid.see(mv) < 0 { LOSING_CAPTURE } else { GOOD_CAPTURE };
+                base + mvv_lva(grid, mv, victim)
+            } else if mv.promotion == Some(TypePiece::Queen) {
+                PROMOTION
+            } else if Some(mv) == killers[0] {
+                KILLER[0]
+            } else if Some(mv) == killers[1] {
+                KILLER[1]
+            } else {
+                self.history[color][square_index(mv.from)][square_index(mv.to)]
+            };
+            Reverse(score)
+        });
+    }
+
+    /// Remembers a quiet move that caused a cutoff as a killer for this ply
+    /// and in the history table.
+    fn record_cutoff(&mut self, color: ChessColor, mv: Move, depth: u32, ply: u32) {
+        if let Some(killers) = self.killers.get_mut(ply as usize) {
+            if killers[0] != Some(mv) {
+                killers[1] = killers[0];
+                killers[0] = Some(mv);
+            }
+        }
+        let entry = &mut self.history[color_index(color)][square_index(mv.from)][square_index(mv.to)];
+        *entry += (depth * depth) as i32;
+        if *entry > HISTORY_LIMIT {
+            self.age_history();
+        }
+    }
+
+    fn age_history(&mut self) {
+        self.history
+            .iter_mut()
+            .flatten()
+            .flatten()
+            .for_each(|score| *score /= 2);
+    }
+
+    /// Follows best moves through the table, stopping at repetitions.
+    fn principal_variation(&self, grid: &Grid, depth: u32) -> Vec<Move> {
+        let mut line = Vec::new();
+        let mut position = grid.clone();
+        let mut seen = vec![position.position_hash()];
+        while line.len() < depth as usize {
+            let Some(mv) = self.tt.probe(position.position_hash()).and_then(|entry| entry.best_move) else {
+                break;
+            };
+            if !position.legal_moves().contains(&mv) {
+                break;
+            }
+            position.make_move(mv);
+            line.push(mv);
+            if seen.contains(&position.position_hash()) {
+                break;
+            }
+            seen.push(position.position_hash());
+        }
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tt_scores_round_trip() {
+        for score in [0, 350, -350, TB_WIN - 7, -TB_WIN + 7, MATE - 5, -MATE + 5] {
+            for ply in [0, 3, 20] {
+                assert_eq!(score_from_tt(score_to_tt(score, ply), ply), score);
+            }
+        }
+        // A tablebase win found 3 plies down is 2 plies nearer seen from 5 down.
+        assert_eq!(score_from_tt(score_to_tt(TB_WIN - 3, 3), 5), TB_WIN - 5);
+        assert_eq!(score_from_tt(score_to_tt(-MATE + 10, 4), 2), -MATE + 8);
+    }
+
+    /// A bare king facing a queen heads for the repetition it has been offered.
+    #[test]
+    fn repetition_is_a_draw() {
+        let mut grid = Grid::from_fen("k7/7p/8/8/8/8/q7/6K1 w - - 0 1").unwrap();
+        let mut history = Vec::new();
+        for text in ["g1h1", "a2a3", "h1g1", "a3a2"] {
+            history.push(grid.position_hash());
+            let mv = grid.parse_uci_move(text).unwrap();
+            grid.make_move(mv);
+        }
+        let mut searcher = Searcher::new(1);
+        assert!(searcher.search(&mut grid.clone(), 4).score < -500);
+        searcher.tt.clear();
+        searcher.set_history(&history);
+        let result = searcher.search(&mut grid, 4);
+        assert_eq!(result.score, 0);
+        assert_eq!(result.best_move.map(|mv| mv.to_uci()).as_deref(), Some("g1h1"));
+    }
+
+    /// Knights out and back: the starting position comes up once more each time.
+    #[test]
+    fn repetition_draws() {
+        let mut grid = Grid::new();
+        let mut earlier = Vec::new();
+        for count in 2..=5 {
+            for text in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+                earlier.push(grid.position_hash());
+                grid.make_move(grid.parse_uci_move(text).unwrap());
+            }
+            assert_eq!(repetitions(&grid, &earlier), count);
+            assert_eq!(claimable_draw(&grid, &earlier).is_some(), count >= 3);
+            assert_eq!(automatic_draw(&grid, &earlier).is_some(), count >= 5);
+        }
+        assert_eq!(automatic_draw(&grid, &earlier), Some("fivefold repetition"));
+        // A pawn move makes everything before it unrepeatable.
+        earlier.push(grid.position_hash());
+        grid.make_move(grid.parse_uci_move("e2e4").unwrap());
+        assert_eq!(repetitions(&grid, &earlier), 1);
+    }
+
+    #[test]
+    fn move_rule_draws() {
+        for (halfmove, claimable, automatic) in [
+            (99, None, None),
+            (100, Some("the fifty-move rule"), None),
+            (149, Some("the fifty-move rule"), None),
+            (150, Some("the fifty-move rule"), Some("the 75-move rule")),
+        ] {
+            let grid = Grid::from_fen(&format!("4k3/8/8/8/8/8/4P3/4K2R w - - {} 90", halfmove)).unwrap();
+            assert_eq!(claimable_draw(&grid, &[]), claimable, "halfmove {}", halfmove);
+            assert_eq!(automatic_draw(&grid, &[]), automatic, "halfmove {}", halfmove);
+        }
+    }
+
+    /// Slices of one search neither age the table nor decay the history.
+    #[test]
+    fn slices_continue_the_same_search() {
+        let mut grid = Grid::new();
+        let mut searcher = Searcher::new(1);
+        let slice = SearchLimits {
+            nodes: Some(500),
+            ..SearchLimits::depth(20)
+        };
+        searcher.search_with(&mut grid, slice, |_| {});
+        // A slot no move ever lands in.
+        searcher.history[0][0][0] = 1000;
+        searcher.search_with(&mut grid, slice, |_| {});
+        assert_eq!(searcher.history[0][0][0], 1000);
+        grid.make_move(grid.parse_uci_move("e2e4").unwrap());
+        searcher.search_with(&mut grid, slice, |_| {});
+        assert_eq!(searcher.history[0][0][0], 500);
+    }
+
+    /// A ponder search ignores its time limits until the ponder hit.
+    #[test]
+    fn ponder_waits_for_the_hit() {
+        let limits = SearchLimits {
+            depth: Some(4),
+            soft_time: Some(Duration::ZERO),
+            hard_time: Some(Duration::ZERO),
+            ponder: true,
+            ..Default::default()
+        };
+        let mut searcher = Searcher::new(1);
+        assert_eq!(searcher.search_with(&mut Grid::new(), limits, |_| {}).depth, 4);
+        searcher.tt.clear();
+        searcher.ponderhit_handle().store(true, Ordering::Relaxed);
+        assert_eq!(searcher.search_with(&mut Grid::new(), limits, |_| {}).depth, 1);
+    }
+}
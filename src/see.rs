@@ -0,0 +1,208 @@
+//! Static exchange evaluation.
+//!
+//! Plays out every capture on one square, each side always recapturing with
+//! its least valuable piece, and scores the result for the side starting
+//! the exchange. Pieces already used are lifted off the board, so sliders
+//! lined up behind them join in as x-ray attackers.
+
+use crate::{ChessColor, Grid, Move, Piece, TypePiece, BISHOP_DIRECTIONS, KNIGHT_OFFSETS, ROOK_DIRECTIONS};
+
+/// Exchange values in centipawns. The king is only ever captured last.
+fn see_value(type_of_piece: TypePiece) -> i32 {
+    match type_of_piece {
+        TypePiece::Pawn => 100,
+        TypePiece::Knight | TypePiece::Bishop => 300,
+        TypePiece::Rook => 500,
+        TypePiece::Queen => 900,
+        TypePiece::King => 20_000,
+    }
+}
+
+fn bit(square: [usize; 2]) -> u64 {
+    1 << (square[1] * 8 + square[0])
+}
+
+impl Grid {
+    /// Material won (positive) or lost (negative) by playing `mv` and then
+    /// letting both sides capture on its destination for as long as it pays.
+    /// Pins are not considered. Quiet moves score what the moving piece
+    /// risks by standing on the new square.
+    pub fn see(&self, mv: Move) -> i32 {
+        let Some(mover) = self.cells[mv.from[0]][mv.from[1]].piece else {
+            return 0;
+        };
+        let mut removed = bit(mv.from);
+        let mut victim = self.cells[mv.to[0]][mv.to[1]]
+            .piece
+            .filter(|piece| piece.color != mover.color)
+            .map(|piece| piece.type_of_piece);
+        if victim.is_none() && mover.type_of_piece == TypePiece::Pawn && mv.from[0] != mv.to[0] {
+            victim = Some(TypePiece::Pawn);
+            removed |= bit([mv.to[0], mv.from[1]]);
+        }
+
+        let mut gain = [0i32; 32];
+        gain[0] = victim.map_or(0, see_value);
+        let mut on_square = see_value(mover.type_of_piece);
+        if let Some(promotion) = mv.promotion {
+            gain[0] += see_value(promotion) - see_value(TypePiece::Pawn);
+            on_square = see_value(promotion);
+        }
+
+        let mut side = mover.color.opposite();
+        let mut depth = 0;
+        while depth + 1 < gain.len() {
+            let Some((square, attacker)) = self.least_valuable_attacker(mv.to, side, removed) else {
+                break;
+            };
+            // A king may only take when nothing can take it back.
+            if attacker == TypePiece::King
+                && self
+                    .least_valuable_attacker(mv.to, side.opposite(), removed | bit(square))
+                    .is_some()
+            {
+                break;
+            }
+            depth += 1;
+            gain[depth] = on_square - gain[depth - 1];
+            removed |= bit(square);
+            on_square = see_value(attacker);
+            side = side.opposite();
+        }
+
+        while depth > 0 {
+            gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
+            depth -= 1;
+        }
+        gain[0]
+    }
+
+    /// Cheapest piece of `color` attacking `target`, ignoring the squares in
+    /// `removed`.
+    fn least_valuable_attacker(
+        &self,
+        target: [usize; 2],
+        color: ChessColor,
+        removed: u64,
+    ) -> Option<([usize; 2], TypePiece)> {
+        let piece_at = |col: isize, row: isize| -> Option<Piece> {
+            if !(0..8).contains(&col) || !(0..8).contains(&row) {
+                return None;
+            }
+            let square = [col as usize, row as usize];
+            if removed & bit(square) != 0 {
+                None
+            } else {
+                self.cells[square[0]][square[1]].piece
+            }
+        };
+        let is = |piece: Option<Piece>, type_of_piece: TypePiece| {
+            piece.is_some_and(|piece| piece.color == color && piece.type_of_piece == type_of_piece)
+        };
+        let (col, row) = (target[0] as isize, target[1] as isize);
+
+        let pawn_row = match color {
+            ChessColor::White => row - 1,
+            ChessColor::Black => row + 1,
+        };
+        for pawn_col in [col - 1, col + 1] {
+            if is(piece_at(pawn_col, pawn_row), TypePiece::Pawn) {
+                return Some(([pawn_col as usize, pawn_row as usize], TypePiece::Pawn));
+            }
+        }
+        for &(dc, dr) in KNIGHT_OFFSETS.iter() {
+            if is(piece_at(col + dc, row + dr), TypePiece::Knight) {
+                return Some(([(col + dc) as usize, (row + dr) as usize], TypePiece::Knight));
+            }
+        }
+
+        // First piece met along each ray, diagonals then lines.
+        let mut sliders = Vec::with_capacity(8);
+        for (directions, diagonal) in [(&BISHOP_DIRECTIONS, true), (&ROOK_DIRECTIONS, false)] {
+            for &(dc, dr) in directions.iter() {
+                let (mut c, mut r) = (col + dc, row + dr);
+                while (0..8).contains(&c) && (0..8).contains(&r) {
+                    if let Some(piece) = piece_at(c, r) {
+                        sliders.push(([c as usize, r as usize], piece, diagonal));
+                        break;
+                    }
+                    c += dc;
+                    r += dr;
+                }
+            }
+        }
+        for (type_of_piece, diagonal) in [
+            (TypePiece::Bishop, Some(true)),
+            (TypePiece::Rook, Some(false)),
+            (TypePiece::Queen, None),
+        ] {
+            let found = sliders.iter().find(|&&(_, piece, on_diagonal)| {
+                is(Some(piece), type_of_piece) && diagonal.is_none_or(|diagonal| diagonal == on_diagonal)
+            });
+            if let Some(&(square, _, _)) = found {
+                return Some((square, type_of_piece));
+            }
+        }
+
+        for dc in -1..=1 {
+            for dr in -1..=1 {
+                if (dc, dr) != (0, 0) && is(piece_at(col + dc, row + dr), TypePiece::King) {
+                    return Some(([(col + dc) as usize, (row + dr) as usize], TypePiece::King));
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn see(fen: &str, text: &str) -> i32 {
+        let grid = Grid::from_fen(fen).unwrap();
+        let mv = grid.parse_uci_move(text).unwrap_or_else(|| panic!("{} is not legal", text));
+        grid.see(mv)
+    }
+
+    #[test]
+    fn exchanges() {
+        // Pawn takes a knight and is taken back.
+        assert_eq!(see("4k3/8/3p4/4n3/3P4/8/8/4K3 w - - 0 1", "d4e5"), 200);
+        // Queen takes a defended pawn.
+        assert_eq!(see("4k3/3p4/4p3/8/8/8/4Q3/4K3 w - - 0 1", "e2e6"), -800);
+        // A free piece.
+        assert_eq!(see("4k3/8/8/4n3/8/8/8/4RK2 w - - 0 1", "e1e5"), 300);
+    }
+
+    #[test]
+    fn x_rays() {
+        // The rook behind wins the knight back after the recapture, so Black leaves it.
+        assert_eq!(see("4r1k1/8/8/4n3/8/8/4R3/4RK2 w - - 0 1", "e2e5"), 300);
+        assert_eq!(see("4r1k1/8/8/4n3/8/8/4R3/5K2 w - - 0 1", "e2e5"), -200);
+        // A queen behind a bishop.
+        assert_eq!(see("4k3/8/5n2/3p4/8/1B6/Q7/4K3 w - - 0 1", "b3d5"), 100);
+        assert_eq!(see("4k3/8/5n2/3p4/8/1B6/8/4K3 w - - 0 1", "b3d5"), -200);
+    }
+
+    #[test]
+    fn en_passant() {
+        assert_eq!(see("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), 100);
+        assert_eq!(see("4k3/2p5/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), 0);
+    }
+
+    #[test]
+    fn promotion_captures() {
+        assert_eq!(see("r3k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7a8q"), 1300);
+        // The new queen is lost to the knight: a rook for a pawn is left.
+        assert_eq!(see("r3k3/1Pn5/8/8/8/8/8/4K3 w - - 0 1", "b7a8q"), 400);
+    }
+
+    #[test]
+    fn king_recaptures() {
+        // The king takes the rook when nothing guards it...
+        assert_eq!(see("8/8/3k4/4p3/8/8/8/4RK2 w - - 0 1", "e1e5"), -400);
+        // ...but not while the bishop still does.
+        assert_eq!(see("8/8/3k4/4p3/8/2B5/8/4RK2 w - - 0 1", "e1e5"), 100);
+    }
+}
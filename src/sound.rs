@@ -0,0 +1,168 @@
+//! Sound effects.
+//!
+//! The clips are synthesised at startup rather than shipped as files, so the
+//! native and wasm builds get the same sounds without extra assets. Playback
+//! needs the `audio` feature, on by default; without it the settings still
+//! work but nothing is heard.
+
+#[cfg(feature = "audio")]
+use macroquad::audio::{load_sound_from_bytes, play_sound, PlaySoundParams, Sound};
+
+use crate::storage;
+
+#[cfg(feature = "audio")]
+const SAMPLE_RATE: u32 = 22050;
+const MUTED_KEY: &str = "sound_muted";
+const VOLUME_KEY: &str = "sound_volume";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SoundEffect {
+    Move,
+    Capture,
+    Castle,
+    Check,
+    Promotion,
+    LowTime,
+    GameOver,
+}
+
+#[cfg(feature = "audio")]
+const ALL_EFFECTS: [SoundEffect; 7] = [
+    SoundEffect::Move,
+    SoundEffect::Capture,
+    SoundEffect::Castle,
+    SoundEffect::Check,
+    SoundEffect::Promotion,
+    SoundEffect::LowTime,
+    SoundEffect::GameOver,
+];
+
+pub struct Sounds {
+    muted: bool,
+    volume: f32,
+    #[cfg(feature = "audio")]
+    clips: Vec<(SoundEffect, Sound)>,
+}
+
+impl Sounds {
+    pub async fn load() -> Self {
+        let muted = storage::load(MUTED_KEY).is_some_and(|value| value == "true");
+        let volume = storage::load(VOLUME_KEY)
+            .and_then(|value| value.parse::<f32>().ok())
+            .unwrap_or(0.7)
+            .clamp(0.0, 1.0);
+
+        #[cfg(feature = "audio")]
+        let clips = {
+            let mut clips = Vec::new();
+            for effect in ALL_EFFECTS {
+                let bytes = wav_bytes(&synthesize(effect));
+                match load_sound_from_bytes(&bytes).await {
+                    Ok(sound) => clips.push((effect, sound)),
+                    Err(err) => eprintln!("Could not load {:?} sound: {}", effect, err),
+                }
+            }
+            clips
+        };
+
+        Sounds {
+            muted,
+            volume,
+            #[cfg(feature = "audio")]
+            clips,
+        }
+    }
+
+    pub fn play(&self, effect: SoundEffect) {
+        if self.muted || self.volume <= 0.0 {
+            return;
+        }
+        #[cfg(feature = "audio")]
+        if let Some((_, sound)) = self.clips.iter().find(|(e, _)| *e == effect) {
+            play_sound(
+                sound,
+                PlaySoundParams {
+                    looped: false,
+                    volume: self.volume,
+                },
+            );
+        }
+        #[cfg(not(feature = "audio"))]
+        let _ = effect;
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    pub fn toggle_mute(&mut self) {
+        self.muted = !self.muted;
+        storage::save(MUTED_KEY, if self.muted { "true" } else { "false" });
+    }
+
+    pub fn change_volume(&mut self, delta: f32) {
+        self.volume = ((self.volume + delta) * 10.0).round().clamp(0.0, 10.0) / 10.0;
+        storage::save(VOLUME_KEY, &self.volume.to_string());
+    }
+}
+
+/// A sequence of decaying tones; `noise` mixes in a little white noise to
+/// give captures a harder attack.
+#[cfg(feature = "audio")]
+fn tones(notes: &[(f32, f32)], noise: f32) -> Vec<f32> {
+    let mut samples = Vec::new();
+    let mut seed: u32 = 0x1234_5678;
+    for &(frequency, duration) in notes {
+        let count = (duration * SAMPLE_RATE as f32) as usize;
+        for n in 0..count {
+            let t = n as f32 / SAMPLE_RATE as f32;
+            let envelope = (-t * 6.0 / duration).exp();
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            let white = (seed >> 16) as f32 / 32768.0 - 1.0;
+            let wave = (t * frequency * std::f32::consts::TAU).sin();
+            samples.push(envelope * ((1.0 - noise) * wave + noise * white) * 0.8);
+        }
+    }
+    samples
+}
+
+#[cfg(feature = "audio")]
+fn synthesize(effect: SoundEffect) -> Vec<f32> {
+    match effect {
+        SoundEffect::Move => tones(&[(220.0, 0.08)], 0.3),
+        SoundEffect::Capture => tones(&[(180.0, 0.12)], 0.6),
+        SoundEffect::Castle => tones(&[(220.0, 0.07), (260.0, 0.08)], 0.3),
+        SoundEffect::Check => tones(&[(660.0, 0.18)], 0.0),
+        SoundEffect::Promotion => tones(&[(523.0, 0.08), (659.0, 0.08), (784.0, 0.14)], 0.0),
+        SoundEffect::LowTime => tones(&[(880.0, 0.1), (0.0, 0.05), (880.0, 0.1)], 0.0),
+        SoundEffect::GameOver => tones(&[(523.0, 0.15), (392.0, 0.15), (262.0, 0.35)], 0.0),
+    }
+}
+
+/// Wraps mono samples in a 16-bit PCM WAV container.
+#[cfg(feature = "audio")]
+fn wav_bytes(samples: &[f32]) -> Vec<u8> {
+    let data_len = (samples.len() * 2) as u32;
+    let mut bytes = Vec::with_capacity(44 + data_len as usize);
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+    bytes.extend_from_slice(b"WAVEfmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    bytes.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+    bytes.extend_from_slice(&2u16.to_le_bytes());
+    bytes.extend_from_slice(&16u16.to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    bytes
+}
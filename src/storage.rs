@@ -0,0 +1,75 @@
+//! Tiny key/value store used to remember settings between sessions.
+//!
+//! Native builds keep a `key=value` file next to the executable's working
+//! directory, the wasm build goes through `localStorage` using the
+//! `chess_storage` plugin registered in `index.html`.
+
+#[cfg(not(target_arch = "wasm32"))]
+const SETTINGS_FILE: &str = "chess-wasm.cfg";
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read_all() -> Vec<(String, String)> {
+    std::fs::read_to_string(SETTINGS_FILE)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load(key: &str) -> Option<String> {
+    read_all()
+        .into_iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, value)| value)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save(key: &str, value: &str) {
+    let mut entries = read_all();
+    match entries.iter_mut().find(|(k, _)| k == key) {
+        Some(entry) => entry.1 = value.to_string(),
+        None => entries.push((key.to_string(), value.to_string())),
+    }
+    let contents: String = entries
+        .iter()
+        .map(|(k, v)| format!("{}={}\n", k, v))
+        .collect();
+    if let Err(err) = std::fs::write(SETTINGS_FILE, contents) {
+        eprintln!("Could not save settings: {}", err);
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+extern "C" {
+    fn chess_storage_get(key: *const u8, key_len: u32, buf: *mut u8, buf_len: u32) -> i32;
+    fn chess_storage_set(key: *const u8, key_len: u32, value: *const u8, value_len: u32);
+}
+
+/// Lets the JS side check that the plugin matches this build.
+#[cfg(target_arch = "wasm32")]
+#[no_mangle]
+pub extern "C" fn chess_storage_crate_version() -> u32 {
+    1
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn load(key: &str) -> Option<String> {
+    let mut buf = vec![0u8; 256];
+    let len = unsafe {
+        chess_storage_get(key.as_ptr(), key.len() as u32, buf.as_mut_ptr(), buf.len() as u32)
+    };
+    if len < 0 || len as usize > buf.len() {
+        return None;
+    }
+    buf.truncate(len as usize);
+    String::from_utf8(buf).ok()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn save(key: &str, value: &str) {
+    unsafe {
+        chess_storage_set(key.as_ptr(), key.len() as u32, value.as_ptr(), value.len() as u32);
+    }
+}
@@ -0,0 +1,349 @@
+//! Syzygy endgame tablebases: win/draw/loss (`.rtbw`) and distance to
+//! zeroing (`.rtbz`) files read from a local directory. Tables are loaded
+//! the first time a position needs them. `TableWriter` writes such files.
+
+mod table;
+mod write;
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::{ChessColor, Grid, Move, TypePiece};
+use table::{piece_code, Kind, Table, MAX_PIECES};
+pub use write::TableWriter;
+
+/// A tablebase result for the side to move. Cursed wins and blessed losses
+/// are wins and losses the fifty-move rule turns into draws.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wdl {
+    Loss = -2,
+    BlessedLoss = -1,
+    Draw = 0,
+    CursedWin = 1,
+    Win = 2,
+}
+
+impl Wdl {
+    fn from_value(value: i32) -> Wdl {
+        match value {
+            ..=-2 => Wdl::Loss,
+            -1 => Wdl::BlessedLoss,
+            0 => Wdl::Draw,
+            1 => Wdl::CursedWin,
+            _ => Wdl::Win,
+        }
+    }
+
+    fn signum(self) -> i32 {
+        (self as i32).signum()
+    }
+}
+
+impl std::ops::Neg for Wdl {
+    type Output = Wdl;
+
+    fn neg(self) -> Wdl {
+        Wdl::from_value(-(self as i32))
+    }
+}
+
+/// DTZ in plies of a position whose best move is a capture or pawn move.
+fn dtz_before_zeroing(wdl: Wdl) -> i32 {
+    match wdl {
+        Wdl::Win => 1,
+        Wdl::CursedWin => 101,
+        Wdl::BlessedLoss => -101,
+        Wdl::Loss => -1,
+        Wdl::Draw => 0,
+    }
+}
+
+/// Letters in the order table names use them.
+const NAME_ORDER: [(TypePiece, char); 6] = [
+    (TypePiece::King, 'K'),
+    (TypePiece::Queen, 'Q'),
+    (TypePiece::Rook, 'R'),
+    (TypePiece::Bishop, 'B'),
+    (TypePiece::Knight, 'N'),
+    (TypePiece::Pawn, 'P'),
+];
+
+fn type_index(type_of_piece: TypePiece) -> u8 {
+    match type_of_piece {
+        TypePiece::Pawn => 1,
+        TypePiece::Knight => 2,
+        TypePiece::Bishop => 3,
+        TypePiece::Rook => 4,
+        TypePiece::Queen => 5,
+        TypePiece::King => 6,
+    }
+}
+
+/// The pieces of `grid` as (square, piece code), by square, and each
+/// side's part of a table name.
+fn material(grid: &Grid) -> (Vec<(usize, u8)>, String, String) {
+    let mut pieces = Vec::new();
+    let mut counts = HashMap::new();
+    for square in 0..64 {
+        if let Some(piece) = grid.find_cell([square % 8, square / 8]).piece {
+            let white = piece.color == ChessColor::White;
+            pieces.push((square, piece_code(type_index(piece.type_of_piece), white)));
+            *counts.entry((piece.type_of_piece, white)).or_insert(0) += 1;
+        }
+    }
+    let side = |white: bool| -> String {
+        NAME_ORDER
+            .iter()
+            .flat_map(|&(type_of_piece, letter)| {
+                std::iter::repeat_n(letter, counts.get(&(type_of_piece, white)).copied().unwrap_or(0))
+            })
+            .collect()
+    };
+    let (white, black) = (side(true), side(false));
+    (pieces, white, black)
+}
+
+/// Tables read so far by name and kind, `None` for files that could not be.
+type LoadedTables = HashMap<(String, Kind), Option<Arc<Table>>>;
+
+/// What a table said about a position.
+enum Probe {
+    Value(i32),
+    /// The DTZ table only has the other side to move.
+    OtherSide,
+}
+
+pub struct Tablebase {
+    directory: PathBuf,
+    /// Names like `KRvK` of the WDL tables in the directory.
+    available: HashSet<String>,
+    max_pieces: usize,
+    loaded: Mutex<LoadedTables>,
+}
+
+impl Tablebase {
+    /// Finds the tables in `directory`; they are read as they are needed.
+    pub fn open(directory: impl AsRef<Path>) -> Result<Tablebase, String> {
+        let directory = directory.as_ref();
+        let entries = std::fs::read_dir(directory)
+            .map_err(|error| format!("cannot read {}: {}", directory.display(), error))?;
+        let available: HashSet<String> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().to_str()?.strip_suffix(".rtbw").map(str::to_string))
+            .filter(|name| name.chars().filter(|&c| c != 'v').count() <= MAX_PIECES)
+            .collect();
+        if available.is_empty() {
+            return Err(format!("no Syzygy tables in {}", directory.display()));
+        }
+        let max_pieces = available.iter().map(|name| name.len() - 1).max().unwrap_or(0);
+        Ok(Tablebase {
+            directory: directory.to_path_buf(),
+            available,
+            max_pieces,
+            loaded: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Number of WDL tables found.
+    pub fn len(&self) -> usize {
+        self.available.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.available.is_empty()
+    }
+
+    /// Most pieces, kings included, of any table found.
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    /// Whether `grid` could be in the tables: few enough pieces and no
+    /// castling rights, which the tables leave out.
+    pub fn covers(&self, grid: &Grid) -> bool {
+        if grid.castling_rights() != 0 {
+            return false;
+        }
+        let mut count = 0;
+        for square in 0..64 {
+            if grid.find_cell([square % 8, square / 8]).piece.is_some() {
+                count += 1;
+                if count > self.max_pieces {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Win, draw or loss for the side to move, ignoring how far the
+    /// fifty-move count has got.
+    pub fn probe_wdl(&self, grid: &Grid) -> Option<Wdl> {
+        if !self.covers(grid) {
+            return None;
+        }
+        self.search(&mut grid.clone(), false).map(|(wdl, _)| wdl)
+    }
+
+    /// Plies to the next capture or pawn move on the quickest way to win,
+    /// or the slowest way to lose: positive when winning, negative when
+    /// losing, 0 for draws. Wins the fifty-move rule spoils count 100 more.
+    pub fn probe_dtz(&self, grid: &Grid) -> Option<i32> {
+        if !self.covers(grid) {
+            return None;
+        }
+        self.dtz(&mut grid.clone())
+    }
+
+    /// The moves of `grid` that keep the best result, quickest to a capture,
+    /// pawn move or mate when winning and slowest when losing.
+    pub fn best_moves(&self, grid: &Grid) -> Option<Vec<Move>> {
+        if !self.covers(grid) {
+            return None;
+        }
+        let mut grid = grid.clone();
+        let mut ranked = Vec::new();
+        for mv in grid.legal_moves() {
+            let undo = grid.make_move(mv);
+            let dtz = if grid.halfmove_clock() == 0 {
+                self.search(&mut grid, false).map(|(wdl, _)| dtz_before_zeroing(-wdl))
+            } else {
+                self.dtz(&mut grid).map(|dtz| -dtz - dtz.signum())
+            };
+            let mates = grid.is_in_check() && grid.legal_moves().is_empty();
+            grid.unmake_move(mv, undo);
+            let dtz = if mates { 1 } else { dtz? };
+            let rank = match dtz {
+                1.. => 10_000 - dtz,
+                0 => 0,
+                _ => -10_000 - dtz,
+            };
+            ranked.push((mv, rank));
+        }
+        let best = ranked.iter().map(|&(_, rank)| rank).max()?;
+        Some(ranked.into_iter().filter(|&(_, rank)| rank == best).map(|(mv, _)| mv).collect())
+    }
+
+    fn table(&self, name: &str, kind: Kind) -> Option<Arc<Table>> {
+        let mut loaded = self.loaded.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        loaded
+            .entry((name.to_string(), kind))
+            .or_insert_with(|| {
+                let extension = match kind {
+                    Kind::Wdl => "rtbw",
+                    Kind::Dtz => "rtbz",
+                };
+                let path = self.directory.join(format!("{}.{}", name, extension));
+                let bytes = std::fs::read(path).ok()?;
+                Table::new(name, kind, bytes).ok().map(Arc::new)
+            })
+            .clone()
+    }
+
+    /// Looks `grid` up in its table of `kind`; `wdl` is its result when
+    /// reading DTZ.
+    fn probe_table(&self, grid: &Grid, kind: Kind, wdl: Wdl) -> Option<Probe> {
+        let (pieces, white, black) = material(grid);
+        if pieces.len() == 2 {
+            return Some(Probe::Value(0));
+        }
+        // Tables are named with the stronger side first.
+        let (name, black_stronger) = if self.available.contains(&format!("{}v{}", white, black)) {
+            (format!("{}v{}", white, black), false)
+        } else if self.available.contains(&format!("{}v{}", black, white)) {
+            (format!("{}v{}", black, white), true)
+        } else {
+            return None;
+        };
+        let table = self.table(&name, kind)?;
+        let black_to_move = grid.side_to_move() == ChessColor::Black;
+        Some(match table.probe(&pieces, black_to_move, black_stronger, wdl) {
+            Some(value) => Probe::Value(value),
+            None => Probe::OtherSide,
+        })
+    }
+
+    /// WDL of `grid`, and whether a capture (or with `zeroing_moves` a pawn
+    /// move) is the best move. Captures are tried first, as the tables
+    /// leave out en passant and do not care what happens when a capture
+    /// wins anyway.
+    fn search(&self, grid: &mut Grid, zeroing_moves: bool) -> Option<(Wdl, bool)> {
+        let moves = grid.legal_moves();
+        let mut best = Wdl::Loss;
+        let mut tried = 0;
+        for &mv in &moves {
+            let pawn_move = grid.find_cell(mv.from).piece.is_some_and(|piece| piece.type_of_piece == TypePiece::Pawn);
+            let undo = grid.make_move(mv);
+            if undo.captured.is_none() && !(zeroing_moves && pawn_move) {
+                grid.unmake_move(mv, undo);
+                continue;
+            }
+            tried += 1;
+            let value = self.search(grid, false).map(|(wdl, _)| -wdl);
+            grid.unmake_move(mv, undo);
+            let value = value?;
+            if value > best {
+                best = value;
+                if value >= Wdl::Win {
+                    return Some((value, true));
+                }
+            }
+        }
+        let all_tried = tried > 0 && tried == moves.len();
+        let value = if all_tried {
+            best
+        } else {
+            match self.probe_table(grid, Kind::Wdl, Wdl::Draw)? {
+                Probe::Value(value) => Wdl::from_value(value),
+                Probe::OtherSide => return None,
+            }
+        };
+        if best >= value {
+            return Some((best, best > Wdl::Draw || all_tried));
+        }
+        Some((value, false))
+    }
+
+    fn dtz(&self, grid: &mut Grid) -> Option<i32> {
+        let (wdl, zeroing_best) = self.search(grid, true)?;
+        if wdl == Wdl::Draw {
+            return Some(0);
+        }
+        if zeroing_best {
+            return Some(dtz_before_zeroing(wdl));
+        }
+        match self.probe_table(grid, Kind::Dtz, wdl)? {
+            Probe::Value(dtz) => {
+                let cursed = matches!(wdl, Wdl::CursedWin | Wdl::BlessedLoss);
+                Some((dtz + if cursed { 100 } else { 0 }) * wdl.signum())
+            }
+            // Look one move ahead from the side the table has.
+            Probe::OtherSide => {
+                let mut best = i32::MAX;
+                for mv in grid.legal_moves() {
+                    let undo = grid.make_move(mv);
+                    let zeroing = grid.halfmove_clock() == 0;
+                    let dtz = if zeroing {
+                        self.search(grid, false).map(|(wdl, _)| -dtz_before_zeroing(wdl))
+                    } else {
+                        self.dtz(grid).map(|dtz| -dtz)
+                    };
+                    let mates = grid.is_in_check() && grid.legal_moves().is_empty();
+                    grid.unmake_move(mv, undo);
+                    let mut dtz = dtz?;
+                    if dtz == 1 && mates {
+                        best = 1;
+                    }
+                    if !zeroing {
+                        dtz += dtz.signum();
+                    }
+                    if dtz < best && dtz.signum() == wdl.signum() {
+                        best = dtz;
+                    }
+                }
+                Some(if best == i32::MAX { -1 } else { best })
+            }
+        }
+    }
+}
@@ -0,0 +1,860 @@
+//! Decoding of single `.rtbw`/`.rtbz` files: the position is turned into an
+//! index, and the value at that index is read from Huffman-coded blocks
+//! of recursively paired symbols.
+
+use std::sync::OnceLock;
+
+use super::Wdl;
+
+pub const MAX_PIECES: usize = 7;
+
+pub const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+pub const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+
+// Flags of a table's pairs data.
+const STM: u8 = 1;
+const MAPPED: u8 = 2;
+pub const WIN_PLIES: u8 = 4;
+pub const LOSS_PLIES: u8 = 8;
+const WIDE: u8 = 16;
+pub const SINGLE_VALUE: u8 = 128;
+
+// Flags of the whole file.
+pub const SPLIT: u8 = 1;
+pub const HAS_PAWNS: u8 = 2;
+
+/// Added to White's piece codes, 1 to 6 for pawn to king, for Black's.
+pub const BLACK: u8 = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Kind {
+    Wdl,
+    Dtz,
+}
+
+/// Encoding tables shared by every file.
+struct Maps {
+    /// Squares a2 to h7 numbered so the pawn nearest the edge, then nearest
+    /// its own side, gets the highest number.
+    pawns: [usize; 64],
+    /// Squares below the a1-h8 diagonal, 0 to 27.
+    b1h1h7: [usize; 64],
+    /// Squares of the a1-d1-d4 triangle, the diagonal ones last.
+    a1d1d4: [usize; 64],
+    /// The 462 placements of two kings with the first in the triangle.
+    kk: [[usize; 64]; 10],
+    binomial: [[u64; 64]; MAX_PIECES],
+    lead_pawn_idx: [[u64; 64]; MAX_PIECES],
+    lead_pawns_size: [[u64; 4]; MAX_PIECES],
+}
+
+/// Rank minus file: negative below the a1-h8 diagonal, 0 on it.
+fn off_diagonal(square: usize) -> i32 {
+    (square / 8) as i32 - (square % 8) as i32
+}
+
+fn is_king_move_or_same(a: usize, b: usize) -> bool {
+    (a / 8).abs_diff(b / 8) <= 1 && (a % 8).abs_diff(b % 8) <= 1
+}
+
+fn maps() -> &'static Maps {
+    static MAPS: OnceLock<Maps> = OnceLock::new();
+    MAPS.get_or_init(|| {
+        let mut maps = Maps {
+            pawns: [0; 64],
+            b1h1h7: [0; 64],
+            a1d1d4: [0; 64],
+            kk: [[0; 64]; 10],
+            binomial: [[0; 64]; MAX_PIECES],
+            lead_pawn_idx: [[0; 64]; MAX_PIECES],
+            lead_pawns_size: [[0; 4]; MAX_PIECES],
+        };
+
+        maps.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..MAX_PIECES.min(n + 1) {
+                maps.binomial[k][n] = if k > 0 { maps.binomial[k - 1][n - 1] } else { 0 }
+                    + if k < n { maps.binomial[k][n - 1] } else { 0 };
+            }
+        }
+
+        let mut code = 0;
+        for square in 0..64 {
+            if off_diagonal(square) < 0 {
+                maps.b1h1h7[square] = code;
+                code += 1;
+            }
+        }
+
+        let mut code = 0;
+        let mut diagonal = Vec::new();
+        for square in 0..28 {
+            if square % 8 > 3 {
+                continue;
+            }
+            if off_diagonal(square) < 0 {
+                maps.a1d1d4[square] = code;
+                code += 1;
+            } else if off_diagonal(square) == 0 {
+                diagonal.push(square);
+            }
+        }
+        for square in diagonal {
+            maps.a1d1d4[square] = code;
+            code += 1;
+        }
+
+        // Both kings on the diagonal are numbered last.
+        let mut both_on_diagonal = Vec::new();
+        let mut code = 0;
+        for idx in 0..10 {
+            for first in 0..28 {
+                let in_triangle = first % 8 <= 3 && off_diagonal(first) <= 0;
+                if !in_triangle || maps.a1d1d4[first] != idx {
+                    continue;
+                }
+                for second in 0..64 {
+                    if is_king_move_or_same(first, second) {
+                        continue;
+                    }
+                    if off_diagonal(first) == 0 && off_diagonal(second) > 0 {
+                        continue;
+                    }
+                    if off_diagonal(first) == 0 && off_diagonal(second) == 0 {
+                        both_on_diagonal.push((idx, second));
+                    } else {
+                        maps.kk[idx][second] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (idx, second) in both_on_diagonal {
+            maps.kk[idx][second] = code;
+            code += 1;
+        }
+
+        let mut available = 47;
+        for lead_pawns in 1..=5 {
+            for file in 0..4 {
+                let mut idx = 0;
+                for rank in 1..7 {
+                    let square = 8 * rank + file;
+                    if lead_pawns == 1 {
+                        maps.pawns[square] = available;
+                        available -= 1;
+                        maps.pawns[square ^ 7] = available;
+                        available = available.saturating_sub(1);
+                    }
+                    maps.lead_pawn_idx[lead_pawns][square] = idx;
+                    idx += maps.binomial[lead_pawns - 1][maps.pawns[square]];
+                }
+                maps.lead_pawns_size[lead_pawns][file] = idx;
+            }
+        }
+        maps
+    })
+}
+
+/// Decoding data for one side to move and, in pawn tables, one file of the
+/// leading pawn. Offsets point into the file's bytes.
+#[derive(Clone, Debug, Default)]
+struct PairsData {
+    flags: u8,
+    min_sym_len: usize,
+    block_size: usize,
+    span: usize,
+    num_blocks: usize,
+    block_length_size: usize,
+    sparse_index_size: usize,
+    lowest_sym: usize,
+    btree: usize,
+    block_length: usize,
+    sparse_index: usize,
+    data: usize,
+    /// `base64[l]` is the lowest symbol of length `l + min_sym_len`,
+    /// left-aligned in 64 bits.
+    base64: Vec<u64>,
+    /// Values, minus one, each symbol expands to.
+    symlen: Vec<u8>,
+    pieces: [u8; MAX_PIECES],
+    group_idx: [u64; MAX_PIECES + 1],
+    /// Pieces encoded together, ended by a 0.
+    group_len: [usize; MAX_PIECES + 1],
+    /// Start of the DTZ value map for win, loss, cursed win and blessed loss.
+    map_idx: [usize; 4],
+}
+
+pub struct Table {
+    kind: Kind,
+    bytes: Vec<u8>,
+    piece_count: usize,
+    has_pawns: bool,
+    has_unique_pieces: bool,
+    /// Pawns of the leading side, then of the other one.
+    pawn_count: [usize; 2],
+    /// Both sides have the same material, so only White to move is stored.
+    symmetric: bool,
+    /// By side to move, then by file of the leading pawn.
+    items: [[PairsData; 4]; 2],
+    map: usize,
+}
+
+fn le_u16(bytes: &[u8], at: usize) -> usize {
+    match bytes.get(at..at + 2) {
+        Some(b) => u16::from_le_bytes([b[0], b[1]]) as usize,
+        None => 0,
+    }
+}
+
+fn le_u32(bytes: &[u8], at: usize) -> usize {
+    match bytes.get(at..at + 4) {
+        Some(b) => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize,
+        None => 0,
+    }
+}
+
+fn be_u32(bytes: &[u8], at: usize) -> u64 {
+    match bytes.get(at..at + 4) {
+        Some(b) => u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as u64,
+        None => 0,
+    }
+}
+
+fn byte(bytes: &[u8], at: usize) -> u8 {
+    bytes.get(at).copied().unwrap_or(0)
+}
+
+/// Piece counts of one side of a table name like `KRPvKR`, indexed by file
+/// piece code without the colour bit.
+pub fn side_counts(side: &str) -> Result<[usize; 7], String> {
+    let mut counts = [0; 7];
+    for letter in side.chars() {
+        let code = match letter {
+            'P' => 1,
+            'N' => 2,
+            'B' => 3,
+            'R' => 4,
+            'Q' => 5,
+            'K' => 6,
+            _ => return Err(format!("bad piece '{}' in table name", letter)),
+        };
+        counts[code] += 1;
+    }
+    Ok(counts)
+}
+
+impl Table {
+    /// Reads the table `name` (e.g. `KQvKR`) from the bytes of its file.
+    pub fn new(name: &str, kind: Kind, bytes: Vec<u8>) -> Result<Table, String> {
+        let (white, black) = name.split_once('v').ok_or("table name has no 'v'")?;
+        let (white, black) = (side_counts(white)?, side_counts(black)?);
+        let (white_pawns, black_pawns) = (white[1], black[1]);
+        // The side with fewer pawns leads, as that compresses better.
+        let white_leads = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+        let mut table = Table {
+            kind,
+            bytes,
+            piece_count: white.iter().chain(&black).sum(),
+            has_pawns: white_pawns + black_pawns > 0,
+            has_unique_pieces: (1..6).any(|code| white[code] == 1 || black[code] == 1),
+            pawn_count: if white_leads {
+                [white_pawns, black_pawns]
+            } else {
+                [black_pawns, white_pawns]
+            },
+            symmetric: white == black,
+            items: Default::default(),
+            map: 0,
+        };
+        if table.piece_count > MAX_PIECES {
+            return Err(format!("{} has more than {} pieces", name, MAX_PIECES));
+        }
+        table.parse()?;
+        Ok(table)
+    }
+
+    pub fn sides(&self) -> usize {
+        if self.kind == Kind::Wdl && !self.symmetric {
+            2
+        } else {
+            1
+        }
+    }
+
+    pub fn files(&self) -> usize {
+        if self.has_pawns {
+            4
+        } else {
+            1
+        }
+    }
+
+    /// Number of values stored for `side` to move and `file`.
+    pub fn size(&self, side: usize, file: usize) -> u64 {
+        let pairs = &self.items[side][file];
+        let groups = pairs.group_len.iter().position(|&len| len == 0).unwrap_or(MAX_PIECES);
+        pairs.group_idx[groups]
+    }
+
+    fn parse(&mut self) -> Result<(), String> {
+        let magic = match self.kind {
+            Kind::Wdl => WDL_MAGIC,
+            Kind::Dtz => DTZ_MAGIC,
+        };
+        if self.bytes.get(..4) != Some(&magic[..]) {
+            return Err("not a Syzygy table".to_string());
+        }
+        let flags = byte(&self.bytes, 4);
+        if (flags & HAS_PAWNS != 0) != self.has_pawns || (flags & SPLIT != 0) == self.symmetric {
+            return Err("table does not match its name".to_string());
+        }
+        let mut data = 5;
+        let both_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        for file in 0..self.files() {
+            let first = byte(&self.bytes, data);
+            let second = if both_pawns { byte(&self.bytes, data + 1) } else { 0xFF };
+            let order = [[first & 0xF, second & 0xF], [first >> 4, second >> 4]];
+            data += 1 + both_pawns as usize;
+            for k in 0..self.piece_count {
+                let code = byte(&self.bytes, data);
+                for side in 0..self.sides() {
+                    self.items[side][file].pieces[k] = if side == 1 { code >> 4 } else { code & 0xF };
+                }
+                data += 1;
+            }
+            for (side, order) in order.into_iter().enumerate().take(self.sides()) {
+                self.set_groups(side, file, order);
+            }
+        }
+        data += data & 1;
+
+        for file in 0..self.files() {
+            for side in 0..self.sides() {
+                data = self.set_sizes(side, file, data);
+            }
+        }
+        if self.kind == Kind::Dtz {
+            data = self.set_dtz_map(data);
+        }
+        for file in 0..self.files() {
+            for side in 0..self.sides() {
+                let pairs = &mut self.items[side][file];
+                pairs.sparse_index = data;
+                data += pairs.sparse_index_size * 6;
+            }
+        }
+        for file in 0..self.files() {
+            for side in 0..self.sides() {
+                let pairs = &mut self.items[side][file];
+                pairs.block_length = data;
+                data += pairs.block_length_size * 2;
+            }
+        }
+        let mut end = data;
+        for file in 0..self.files() {
+            for side in 0..self.sides() {
+                let pairs = &mut self.items[side][file];
+                data = (data + 0x3F) & !0x3F;
+                pairs.data = data;
+                data += pairs.num_blocks * pairs.block_size;
+                if pairs.num_blocks > 0 {
+                    end = data;
+                }
+            }
+        }
+        if end > self.bytes.len() {
+            return Err("table file is truncated".to_string());
+        }
+        Ok(())
+    }
+
+    /// Splits the pieces into groups encoded together and works out what
+    /// each group's index is multiplied by. `order` says which of the
+    /// leading group and the other side's pawns come first.
+    fn set_groups(&mut self, side: usize, file: usize, order: [u8; 2]) {
+        let maps = maps();
+        let (has_pawns, has_unique_pieces, piece_count) = (self.has_pawns, self.has_unique_pieces, self.piece_count);
+        let both_pawns = has_pawns && self.pawn_count[1] > 0;
+        let pairs = &mut self.items[side][file];
+        let mut first_len: i32 = if has_pawns {
+            0
+        } else if has_unique_pieces {
+            3
+        } else {
+            2
+        };
+        let mut n = 0;
+        pairs.group_len[0] = 1;
+        for i in 1..piece_count {
+            first_len -= 1;
+            if first_len > 0 || pairs.pieces[i] == pairs.pieces[i - 1] {
+                pairs.group_len[n] += 1;
+            } else {
+                n += 1;
+                pairs.group_len[n] = 1;
+            }
+        }
+        n += 1;
+        pairs.group_len[n] = 0;
+
+        let mut next = if both_pawns { 2 } else { 1 };
+        let mut free_squares = 64 - pairs.group_len[0] - if both_pawns { pairs.group_len[1] } else { 0 };
+        let mut idx: u64 = 1;
+        let mut k = 0;
+        while next < n || k == order[0] as usize || k == order[1] as usize {
+            if k == order[0] as usize {
+                pairs.group_idx[0] = idx;
+                idx *= if has_pawns {
+                    maps.lead_pawns_size[pairs.group_len[0]][file]
+                } else if has_unique_pieces {
+                    31332
+                } else {
+                    462
+                };
+            } else if k == order[1] as usize {
+                pairs.group_idx[1] = idx;
+                idx *= maps.binomial[pairs.group_len[1]][48 - pairs.group_len[0]];
+            } else {
+                pairs.group_idx[next] = idx;
+                idx *= maps.binomial[pairs.group_len[next]][free_squares];
+                free_squares -= pairs.group_len[next];
+                next += 1;
+            }
+            k += 1;
+        }
+        pairs.group_idx[n] = idx;
+    }
+
+    /// Reads the block sizes and the Huffman code; returns where the next
+    /// part of the header starts.
+    fn set_sizes(&mut self, side: usize, file: usize, mut data: usize) -> usize {
+        let bytes = &self.bytes;
+        let pairs = &mut self.items[side][file];
+        pairs.flags = byte(bytes, data);
+        data += 1;
+        if pairs.flags & SINGLE_VALUE != 0 {
+            pairs.min_sym_len = byte(bytes, data) as usize;
+            return data + 1;
+        }
+        let groups = pairs.group_len.iter().position(|&len| len == 0).unwrap_or(MAX_PIECES);
+        let table_size = pairs.group_idx[groups] as usize;
+        pairs.block_size = 1 << byte(bytes, data);
+        pairs.span = 1 << byte(bytes, data + 1);
+        pairs.sparse_index_size = table_size.div_ceil(pairs.span);
+        let padding = byte(bytes, data + 2) as usize;
+        pairs.num_blocks = le_u32(bytes, data + 3);
+        pairs.block_length_size = pairs.num_blocks + padding;
+        let max_sym_len = byte(bytes, data + 7) as usize;
+        pairs.min_sym_len = byte(bytes, data + 8) as usize;
+        data += 9;
+        pairs.lowest_sym = data;
+
+        // Canonical Huffman: longer codes have lower values, so the lowest
+        // code of each length can be worked out from the next longer one.
+        let lengths = (max_sym_len + 1).saturating_sub(pairs.min_sym_len);
+        pairs.base64 = vec![0; lengths];
+        for i in (0..lengths.saturating_sub(1)).rev() {
+            let lowest = le_u16(bytes, pairs.lowest_sym + 2 * i) as u64;
+            let next_lowest = le_u16(bytes, pairs.lowest_sym + 2 * (i + 1)) as u64;
+            pairs.base64[i] = (pairs.base64[i + 1].wrapping_add(lowest).wrapping_sub(next_lowest)) / 2;
+        }
+        for (i, base) in pairs.base64.iter_mut().enumerate() {
+            *base = base.checked_shl((64 - i - pairs.min_sym_len) as u32).unwrap_or(0);
+        }
+        data += lengths * 2;
+
+        let symbols = le_u16(bytes, data);
+        data += 2;
+        pairs.btree = data;
+        pairs.symlen = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+        for symbol in 0..symbols {
+            if !visited[symbol] {
+                pairs.symlen[symbol] = set_symlen(bytes, pairs.btree, &mut pairs.symlen, &mut visited, symbol);
+            }
+        }
+        data + symbols * 3 + (symbols & 1)
+    }
+
+    /// Finds the maps DTZ values go through, in tables that have them.
+    fn set_dtz_map(&mut self, mut data: usize) -> usize {
+        self.map = data;
+        for file in 0..self.files() {
+            let pairs = &mut self.items[0][file];
+            if pairs.flags & MAPPED == 0 {
+                continue;
+            }
+            if pairs.flags & WIDE != 0 {
+                data += data & 1;
+                for i in 0..4 {
+                    pairs.map_idx[i] = (data - self.map) / 2 + 1;
+                    data += 2 * le_u16(&self.bytes, data) + 2;
+                }
+            } else {
+                for i in 0..4 {
+                    pairs.map_idx[i] = data - self.map + 1;
+                    data += byte(&self.bytes, data) as usize + 1;
+                }
+            }
+        }
+        data + (data & 1)
+    }
+
+    /// The stored value for `pieces` (square, piece code), sorted by square,
+    /// with `wdl` being the position's result when probing DTZ. `None` if
+    /// this DTZ table only has the other side to move.
+    ///
+    /// `black_stronger` says the table's first side is Black's pieces here.
+    pub fn probe(&self, pieces: &[(usize, u8)], black_to_move: bool, black_stronger: bool, wdl: Wdl) -> Option<i32> {
+        let (side, file, idx) = self.locate(pieces, black_to_move, black_stronger)?;
+        let value = self.decompress(&self.items[side][file], idx);
+        Some(self.map_score(file, value, wdl))
+    }
+
+    /// Side to move, file of the leading pawn and index the value for
+    /// `pieces` is stored at, as for `probe`.
+    pub fn locate(
+        &self,
+        pieces: &[(usize, u8)],
+        black_to_move: bool,
+        black_stronger: bool,
+    ) -> Option<(usize, usize, u64)> {
+        let maps = maps();
+        // Symmetric tables only hold White to move: turn the board around.
+        let flip = (self.symmetric && black_to_move) || black_stronger;
+        let (flip_color, flip_squares) = if flip { (BLACK, 56) } else { (0, 0) };
+        let stm = (flip ^ black_to_move) as usize;
+
+        let mut squares = [0usize; MAX_PIECES];
+        let mut codes = [0u8; MAX_PIECES];
+        let mut size = 0;
+        let mut lead_pawns = 0;
+        let mut file = 0;
+        let mut lead_pawn = None;
+        if self.has_pawns {
+            let pawn = self.items[0][0].pieces[0] ^ flip_color;
+            lead_pawn = Some(pawn);
+            for &(square, code) in pieces {
+                if code == pawn {
+                    squares[size] = square ^ flip_squares;
+                    size += 1;
+                }
+            }
+            lead_pawns = size;
+            let lead = (0..lead_pawns).fold(0, |best, i| {
+                if maps.pawns[squares[i]] > maps.pawns[squares[best]] {
+                    i
+                } else {
+                    best
+                }
+            });
+            squares.swap(0, lead);
+            file = (squares[0] % 8).min(7 - squares[0] % 8);
+        }
+
+        if self.kind == Kind::Dtz {
+            let stored = (self.items[0][file].flags & STM) as usize;
+            // Symmetric pawnless tables serve both sides to move.
+            if stored != stm && (self.has_pawns || !self.symmetric) {
+                return None;
+            }
+        }
+
+        for &(square, code) in pieces {
+            if Some(code) == lead_pawn {
+                continue;
+            }
+            squares[size] = square ^ flip_squares;
+            codes[size] = code ^ flip_color;
+            size += 1;
+        }
+
+        let side = if self.kind == Kind::Wdl { stm } else { 0 };
+        let pairs = &self.items[side][file];
+        // Put the pieces in the order the table lists them.
+        for i in lead_pawns..size.saturating_sub(1) {
+            for j in i + 1..size {
+                if pairs.pieces[i] == codes[j] {
+                    codes.swap(i, j);
+                    squares.swap(i, j);
+                    break;
+                }
+            }
+        }
+
+        // Mirror so the leading piece is on files a to d.
+        if squares[0] % 8 > 3 {
+            for square in &mut squares[..size] {
+                *square ^= 7;
+            }
+        }
+
+        let mut idx: u64;
+        if self.has_pawns {
+            idx = maps.lead_pawn_idx[lead_pawns][squares[0]];
+            squares[1..lead_pawns].sort_by_key(|&square| maps.pawns[square]);
+            for (i, &square) in squares.iter().enumerate().take(lead_pawns).skip(1) {
+                idx += maps.binomial[i][maps.pawns[square]];
+            }
+        } else {
+            // Then below the fifth rank and below the a1-h8 diagonal.
+            if squares[0] / 8 > 3 {
+                for square in &mut squares[..size] {
+                    *square ^= 56;
+                }
+            }
+            for i in 0..pairs.group_len[0] {
+                let off = off_diagonal(squares[i]);
+                if off == 0 {
+                    continue;
+                }
+                if off > 0 {
+                    for square in &mut squares[i..size] {
+                        *square = ((*square >> 3) | (*square << 3)) & 63;
+                    }
+                }
+                break;
+            }
+            idx = if self.has_unique_pieces {
+                let adjust1 = (squares[1] > squares[0]) as usize;
+                let adjust2 = (squares[2] > squares[0]) as usize + (squares[2] > squares[1]) as usize;
+                let rank = |square: usize| square / 8;
+                (if off_diagonal(squares[0]) != 0 {
+                    (maps.a1d1d4[squares[0]] * 63 + (squares[1] - adjust1)) * 62 + squares[2] - adjust2
+                } else if off_diagonal(squares[1]) != 0 {
+                    (6 * 63 + rank(squares[0]) * 28 + maps.b1h1h7[squares[1]]) * 62 + squares[2] - adjust2
+                } else if off_diagonal(squares[2]) != 0 {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + rank(squares[0]) * 7 * 28
+                        + (rank(squares[1]) - adjust1) * 28
+                        + maps.b1h1h7[squares[2]]
+                } else {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + 4 * 7 * 28
+                        + rank(squares[0]) * 7 * 6
+                        + (rank(squares[1]) - adjust1) * 6
+                        + (rank(squares[2]) - adjust2)
+                }) as u64
+            } else {
+                maps.kk[maps.a1d1d4[squares[0]]][squares[1]] as u64
+            };
+        }
+
+        // The other groups, each as a combination of the squares left.
+        idx *= pairs.group_idx[0];
+        let mut start = pairs.group_len[0];
+        let mut remaining_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        let mut next = 1;
+        while pairs.group_len[next] != 0 {
+            let len = pairs.group_len[next];
+            squares[start..start + len].sort_unstable();
+            let mut n = 0;
+            for i in 0..len {
+                let square = squares[start + i];
+                let adjust = squares[..start].iter().filter(|&&earlier| square > earlier).count();
+                n += maps.binomial[i + 1][square - adjust - 8 * remaining_pawns as usize];
+            }
+            remaining_pawns = false;
+            idx += n * pairs.group_idx[next];
+            start += len;
+            next += 1;
+        }
+
+        Some((side, file, idx))
+    }
+
+    /// The WDL value, 0 (loss) to 4 (win), or the raw DTZ value at `idx`.
+    fn decompress(&self, pairs: &PairsData, idx: u64) -> i32 {
+        if pairs.flags & SINGLE_VALUE != 0 {
+            return pairs.min_sym_len as i32;
+        }
+        let bytes = &self.bytes;
+        let block_length = |block: usize| le_u16(bytes, pairs.block_length + 2 * block) as i64;
+
+        // The sparse index points near the value; walk to its block.
+        let span = pairs.span as u64;
+        let entry = pairs.sparse_index + 6 * (idx / span) as usize;
+        let mut block = le_u32(bytes, entry);
+        let mut offset = le_u16(bytes, entry + 4) as i64;
+        offset += (idx % span) as i64 - (span / 2) as i64;
+        while offset < 0 && block > 0 {
+            block -= 1;
+            offset += block_length(block) + 1;
+        }
+        while offset > block_length(block) && block + 1 < pairs.block_length_size {
+            offset -= block_length(block) + 1;
+            block += 1;
+        }
+
+        // Skip whole symbols until the one holding the value.
+        let mut at = pairs.data + block * pairs.block_size;
+        let mut buffer = (be_u32(bytes, at) << 32) | be_u32(bytes, at + 4);
+        at += 8;
+        let mut buffer_bits = 64;
+        let mut symbol;
+        loop {
+            let mut len = 0;
+            while len + 1 < pairs.base64.len() && buffer < pairs.base64[len] {
+                len += 1;
+            }
+            let base = pairs.base64.get(len).copied().unwrap_or(0);
+            symbol = (buffer - base).checked_shr((64 - len - pairs.min_sym_len) as u32).unwrap_or(0) as u16;
+            symbol = symbol.wrapping_add(le_u16(bytes, pairs.lowest_sym + 2 * len) as u16);
+            let count = pairs.symlen.get(symbol as usize).map_or(1, |&len| len as i64 + 1);
+            if offset < count {
+                break;
+            }
+            offset -= count;
+            let len = len + pairs.min_sym_len;
+            buffer <<= len;
+            buffer_bits -= len;
+            if buffer_bits <= 32 {
+                buffer_bits += 32;
+                buffer |= be_u32(bytes, at) << (64 - buffer_bits);
+                at += 4;
+            }
+        }
+
+        // Then expand it down to the single value.
+        let mut symbol = symbol as usize;
+        while pairs.symlen.get(symbol).is_some_and(|&len| len != 0) {
+            let left = btree_left(bytes, pairs.btree, symbol);
+            let count = pairs.symlen.get(left).map_or(1, |&len| len as i64 + 1);
+            if offset < count {
+                symbol = left;
+            } else {
+                offset -= count;
+                symbol = btree_right(bytes, pairs.btree, symbol);
+            }
+        }
+        btree_left(bytes, pairs.btree, symbol) as i32
+    }
+
+    /// WDL as -2 to 2, or DTZ in plies, as stored values may be in moves.
+    fn map_score(&self, file: usize, value: i32, wdl: Wdl) -> i32 {
+        if self.kind == Kind::Wdl {
+            return value - 2;
+        }
+        let pairs = &self.items[0][file];
+        let mut value = value;
+        if pairs.flags & MAPPED != 0 {
+            let map = match wdl {
+                Wdl::Win | Wdl::Draw => 0,
+                Wdl::Loss => 1,
+                Wdl::CursedWin => 2,
+                Wdl::BlessedLoss => 3,
+            };
+            let index = pairs.map_idx[map] + value as usize;
+            value = if pairs.flags & WIDE != 0 {
+                le_u16(&self.bytes, self.map + 2 * index) as i32
+            } else {
+                byte(&self.bytes, self.map + index) as i32
+            };
+        }
+        let in_moves = match wdl {
+            Wdl::Win => pairs.flags & WIN_PLIES == 0,
+            Wdl::Loss => pairs.flags & LOSS_PLIES == 0,
+            Wdl::CursedWin | Wdl::BlessedLoss => true,
+            Wdl::Draw => false,
+        };
+        if in_moves {
+            value *= 2;
+        }
+        value + 1
+    }
+}
+
+fn btree_left(bytes: &[u8], btree: usize, symbol: usize) -> usize {
+    let at = btree + 3 * symbol;
+    ((byte(bytes, at + 1) as usize & 0xF) << 8) | byte(bytes, at) as usize
+}
+
+fn btree_right(bytes: &[u8], btree: usize, symbol: usize) -> usize {
+    let at = btree + 3 * symbol;
+    ((byte(bytes, at + 2) as usize) << 4) | (byte(bytes, at + 1) as usize >> 4)
+}
+
+/// Number of values, minus one, `symbol` stands for: a leaf stands for
+/// one, a pair for the sum of its two halves.
+fn set_symlen(bytes: &[u8], btree: usize, symlen: &mut [u8], visited: &mut [bool], symbol: usize) -> u8 {
+    visited[symbol] = true;
+    let right = btree_right(bytes, btree, symbol);
+    if right == 0xFFF {
+        return 0;
+    }
+    let left = btree_left(bytes, btree, symbol);
+    for half in [left, right] {
+        if half < symlen.len() && !visited[half] {
+            symlen[half] = set_symlen(bytes, btree, symlen, visited, half);
+        }
+    }
+    let length = |half: usize| symlen.get(half).copied().unwrap_or(0);
+    length(left).wrapping_add(length(right)).wrapping_add(1)
+}
+
+/// Piece code of a board piece in the files' numbering.
+pub fn piece_code(type_index: u8, white: bool) -> u8 {
+    type_index + if white { 0 } else { BLACK }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binomials() {
+        let maps = maps();
+        for n in 0..64u64 {
+            let mut expected = 1;
+            for k in 0..MAX_PIECES as u64 {
+                assert_eq!(maps.binomial[k as usize][n as usize], expected, "{} choose {}", n, k);
+                expected = expected * n.saturating_sub(k) / (k + 1);
+            }
+        }
+    }
+
+    #[test]
+    fn square_maps_number_every_square_once() {
+        let maps = maps();
+        let mut below: Vec<usize> = (0..64).filter(|&sq| off_diagonal(sq) < 0).map(|sq| maps.b1h1h7[sq]).collect();
+        below.sort();
+        assert_eq!(below, (0..28).collect::<Vec<_>>());
+
+        let triangle: Vec<usize> = (0..64).filter(|&sq| sq % 8 <= 3 && off_diagonal(sq) <= 0).collect();
+        assert_eq!(triangle.len(), 10);
+        let mut codes: Vec<usize> = triangle.iter().map(|&sq| maps.a1d1d4[sq]).collect();
+        codes.sort();
+        assert_eq!(codes, (0..10).collect::<Vec<_>>());
+        // a1, b2, c3 and d4 come last.
+        for (square, code) in [(0, 6), (9, 7), (18, 8), (27, 9)] {
+            assert_eq!(maps.a1d1d4[square], code);
+        }
+
+        let mut pawns: Vec<usize> = (8..56).map(|sq| maps.pawns[sq]).collect();
+        pawns.sort();
+        assert_eq!(pawns, (0..48).collect::<Vec<_>>());
+        assert_eq!(maps.pawns[8], 47);
+        assert_eq!(maps.pawns[15], 46);
+    }
+
+    /// Every placement of the two kings Syzygy keeps gets its own number
+    /// below 462.
+    #[test]
+    fn king_pairs() {
+        let maps = maps();
+        let mut codes = Vec::new();
+        for first in (0..64).filter(|&sq| sq % 8 <= 3 && off_diagonal(sq) <= 0) {
+            for second in 0..64 {
+                let mirrored_away = off_diagonal(first) == 0 && off_diagonal(second) > 0;
+                if !is_king_move_or_same(first, second) && !mirrored_away {
+                    codes.push(maps.kk[maps.a1d1d4[first]][second]);
+                }
+            }
+        }
+        codes.sort();
+        assert_eq!(codes, (0..462).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,437 @@
+//! Writing `.rtbw`/`.rtbz` files for tables worked out some other way. Each
+//! value goes where `Table::locate` looks for it; the values are then
+//! paired and Huffman-coded into blocks the way `Table` reads them.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use super::table::{
+    side_counts, Kind, Table, BLACK, DTZ_MAGIC, HAS_PAWNS, LOSS_PLIES, SINGLE_VALUE, SPLIT, WDL_MAGIC, WIN_PLIES,
+};
+use super::{material, Wdl};
+use crate::{ChessColor, Grid};
+
+/// Bytes per block, as a power of two.
+const BLOCK_SIZE: u8 = 6;
+/// Values between sparse index entries, as a power of two.
+const SPAN: u8 = 10;
+/// Most values in a block, so sparse index offsets fit in 16 bits.
+const BLOCK_VALUES: usize = 32_768;
+/// Most symbols, values included; the symbol tree numbers them in 12 bits.
+const MAX_SYMBOLS: usize = 1_024;
+/// Fewest times a pair has to occur to become a symbol.
+const MIN_PAIRS: usize = 8;
+/// Right half of a symbol that is a value.
+const LEAF: u16 = 0xFFF;
+
+/// Piece codes of table `name` in the order its files list them: leading
+/// pawns and then the other side's, or without pawns the pieces there is
+/// one of, then the rest with equal pieces together.
+fn piece_order(name: &str) -> Result<Vec<u8>, String> {
+    let (white, black) = name.split_once('v').ok_or("table name has no 'v'")?;
+    let mut left = [side_counts(white)?, side_counts(black)?];
+    let code = |side: usize, code: usize| code as u8 + if side == 1 { BLACK } else { 0 };
+    let mut order = Vec::new();
+    let (white_pawns, black_pawns) = (left[0][1], left[1][1]);
+    let white_leads = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+    if white_pawns + black_pawns > 0 {
+        for side in if white_leads { [0, 1] } else { [1, 0] } {
+            order.extend(std::iter::repeat_n(code(side, 1), left[side][1]));
+            left[side][1] = 0;
+        }
+    } else {
+        for (side, counts) in left.iter_mut().enumerate() {
+            for piece in (1..7).rev() {
+                if counts[piece] == 1 {
+                    order.push(code(side, piece));
+                    counts[piece] = 0;
+                }
+            }
+        }
+    }
+    for (side, counts) in left.iter().enumerate() {
+        for piece in (1..7).rev() {
+            order.extend(std::iter::repeat_n(code(side, piece), counts[piece]));
+        }
+    }
+    Ok(order)
+}
+
+/// The start of a file of table `name`, up to its block sizes, and how many
+/// sides and files those are given for.
+fn header(name: &str, kind: Kind) -> Result<(Vec<u8>, usize), String> {
+    let (white, black) = name.split_once('v').ok_or("table name has no 'v'")?;
+    let (white, black) = (side_counts(white)?, side_counts(black)?);
+    let order = piece_order(name)?;
+    let has_pawns = white[1] + black[1] > 0;
+    let both_pawns = white[1] > 0 && black[1] > 0;
+    let symmetric = white == black;
+    let mut bytes = match kind {
+        Kind::Wdl => WDL_MAGIC,
+        Kind::Dtz => DTZ_MAGIC,
+    }
+    .to_vec();
+    bytes.push(if symmetric { 0 } else { SPLIT } | if has_pawns { HAS_PAWNS } else { 0 });
+    let files = if has_pawns { 4 } else { 1 };
+    for _ in 0..files {
+        // The leading group is indexed first, then the other side's pawns.
+        bytes.push(0);
+        if both_pawns {
+            bytes.push(0x11);
+        }
+        bytes.extend(order.iter().map(|&code| code | (code << 4)));
+    }
+    bytes.resize(bytes.len().next_multiple_of(2), 0);
+    let sides = if kind == Kind::Wdl && !symmetric { 2 } else { 1 };
+    Ok((bytes, sides * files))
+}
+
+/// Collects the results of the positions of one table, then writes its WDL
+/// and DTZ files.
+pub struct TableWriter {
+    name: String,
+    /// The table's files with every value left out, to find where each
+    /// position goes.
+    wdl: Table,
+    dtz: Table,
+    /// By side to move, then file of the leading pawn; `None` where no
+    /// position was added, which gets whatever compresses best.
+    wdl_values: Vec<Vec<Vec<Option<u16>>>>,
+    /// DTZ in plies minus one with the table's first side to move, by file.
+    dtz_values: Vec<Vec<Option<u16>>>,
+}
+
+impl TableWriter {
+    /// A writer for the table `name`, e.g. `KRvK`, with no positions yet.
+    pub fn new(name: &str) -> Result<TableWriter, String> {
+        let empty = |kind: Kind| -> Result<Table, String> {
+            let (mut bytes, parts) = header(name, kind)?;
+            for _ in 0..parts {
+                bytes.extend([SINGLE_VALUE, 0]);
+            }
+            Table::new(name, kind, bytes)
+        };
+        let (wdl, dtz) = (empty(Kind::Wdl)?, empty(Kind::Dtz)?);
+        let values = |table: &Table, side: usize| -> Vec<Vec<Option<u16>>> {
+            (0..table.files()).map(|file| vec![None; table.size(side, file) as usize]).collect()
+        };
+        Ok(TableWriter {
+            name: name.to_string(),
+            wdl_values: (0..wdl.sides()).map(|side| values(&wdl, side)).collect(),
+            dtz_values: values(&dtz, 0),
+            wdl,
+            dtz,
+        })
+    }
+
+    /// Adds a position of the table with its result for the side to move
+    /// and its DTZ in plies, as `Tablebase::probe_dtz` gives it. Positions
+    /// the table stores once, like mirror images, must agree.
+    pub fn add(&mut self, grid: &Grid, wdl: Wdl, dtz: i32) -> Result<(), String> {
+        let (pieces, white, black) = material(grid);
+        let black_stronger = if format!("{}v{}", white, black) == self.name {
+            false
+        } else if format!("{}v{}", black, white) == self.name {
+            true
+        } else {
+            return Err(format!("{} is not a {} position", grid.to_fen(), self.name));
+        };
+        if matches!(wdl, Wdl::CursedWin | Wdl::BlessedLoss) {
+            return Err("cursed wins and blessed losses cannot be written".to_string());
+        }
+        if dtz.signum() != wdl.signum() {
+            return Err(format!("DTZ {} of {} does not match {:?}", dtz, grid.to_fen(), wdl));
+        }
+        let black_to_move = grid.side_to_move() == ChessColor::Black;
+        if let Some((side, file, idx)) = self.wdl.locate(&pieces, black_to_move, black_stronger) {
+            store(&mut self.wdl_values[side][file][idx as usize], (wdl as i32 + 2) as u16, grid)?;
+        }
+        if dtz != 0 {
+            if let Some((_, file, idx)) = self.dtz.locate(&pieces, black_to_move, black_stronger) {
+                store(&mut self.dtz_values[file][idx as usize], (dtz.unsigned_abs() - 1) as u16, grid)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The `.rtbw` and `.rtbz` files.
+    pub fn finish(self) -> Result<(Vec<u8>, Vec<u8>), String> {
+        let mut wdl_parts = Vec::new();
+        for file in 0..self.wdl.files() {
+            for side in 0..self.wdl.sides() {
+                wdl_parts.push(encode(0, &self.wdl_values[side][file])?);
+            }
+        }
+        let dtz_parts = self
+            .dtz_values
+            .iter()
+            .map(|values| encode(WIN_PLIES | LOSS_PLIES, values))
+            .collect::<Result<Vec<_>, _>>()?;
+        let wdl = assemble(header(&self.name, Kind::Wdl)?.0, &wdl_parts, Kind::Wdl);
+        let dtz = assemble(header(&self.name, Kind::Dtz)?.0, &dtz_parts, Kind::Dtz);
+        Ok((wdl, dtz))
+    }
+}
+
+/// Puts `value` in `slot`, which a position stored at the same index may
+/// have filled already.
+fn store(slot: &mut Option<u16>, value: u16, grid: &Grid) -> Result<(), String> {
+    if value >= LEAF {
+        return Err(format!("value {} of {} is too large", value, grid.to_fen()));
+    }
+    match *slot {
+        Some(stored) if stored != value => {
+            Err(format!("{} is stored with a position of another value", grid.to_fen()))
+        }
+        _ => {
+            *slot = Some(value);
+            Ok(())
+        }
+    }
+}
+
+/// One side to move and file of a table, coded.
+#[derive(Default)]
+struct Part {
+    sizes: Vec<u8>,
+    sparse_index: Vec<u8>,
+    block_lengths: Vec<u8>,
+    blocks: Vec<u8>,
+}
+
+/// Lays out the parts after `header`, each kind of data together.
+fn assemble(mut bytes: Vec<u8>, parts: &[Part], kind: Kind) -> Vec<u8> {
+    for part in parts {
+        bytes.extend(&part.sizes);
+    }
+    // Where the DTZ maps would go; values here are never mapped.
+    if kind == Kind::Dtz {
+        bytes.resize(bytes.len().next_multiple_of(2), 0);
+    }
+    for part in parts {
+        bytes.extend(&part.sparse_index);
+    }
+    for part in parts {
+        bytes.extend(&part.block_lengths);
+    }
+    for part in parts.iter().filter(|part| !part.blocks.is_empty()) {
+        bytes.resize(bytes.len().next_multiple_of(64), 0);
+        bytes.extend(&part.blocks);
+    }
+    bytes
+}
+
+/// A value, or two symbols standing for their values one after the other.
+#[derive(Clone, Copy)]
+struct Symbol {
+    left: u16,
+    right: u16,
+    values: usize,
+}
+
+fn encode(flags: u8, values: &[Option<u16>]) -> Result<Part, String> {
+    // Indices no position was stored at repeat the value before them.
+    let mut last = values.iter().flatten().next().copied().unwrap_or(0);
+    let values: Vec<u16> = values
+        .iter()
+        .map(|value| {
+            last = value.unwrap_or(last);
+            last
+        })
+        .collect();
+    if values.iter().all(|&value| value == last) && last <= u8::MAX as u16 {
+        return Ok(Part {
+            sizes: vec![flags | SINGLE_VALUE, last as u8],
+            ..Part::default()
+        });
+    }
+
+    let (symbols, sequence) = pair(&values);
+    let lengths = code_lengths(symbols.len(), &sequence);
+    let max_len = lengths.iter().flatten().copied().max().unwrap_or(0);
+    let min_len = lengths.iter().flatten().copied().min().unwrap_or(0);
+    if max_len > 32 {
+        return Err("Huffman code longer than 32 bits".to_string());
+    }
+
+    // Canonical code: longer codes get the lower symbol numbers and values,
+    // and symbols only used inside others come last.
+    let mut order: Vec<usize> = (0..symbols.len()).collect();
+    order.sort_by_key(|&symbol| (lengths[symbol].is_none(), Reverse(lengths[symbol]), symbol));
+    let mut number = vec![0; symbols.len()];
+    for (n, &symbol) in order.iter().enumerate() {
+        number[symbol] = n;
+    }
+    let mut count = vec![0; max_len + 2];
+    for &len in lengths.iter().flatten() {
+        count[len] += 1;
+    }
+    let mut lowest = vec![0; max_len + 2];
+    let mut base = vec![0u64; max_len + 2];
+    for len in (min_len..max_len).rev() {
+        lowest[len] = lowest[len + 1] + count[len + 1];
+        base[len] = (base[len + 1] + count[len + 1] as u64) / 2;
+    }
+    let code = |symbol: usize| -> (u64, usize) {
+        let len = lengths[symbol].unwrap_or(0);
+        (base[len] + (number[symbol] - lowest[len]) as u64, len)
+    };
+
+    let block_bytes = 1 << BLOCK_SIZE;
+    let mut part = Part::default();
+    let mut starts = Vec::new();
+    let mut block = vec![0u8; block_bytes];
+    let (mut bits, mut in_block, mut start) = (0, 0, 0);
+    let end_block = |part: &mut Part, block: &mut Vec<u8>, in_block: usize| {
+        part.blocks.extend(block.iter());
+        part.block_lengths.extend(((in_block - 1) as u16).to_le_bytes());
+        block.fill(0);
+    };
+    for &symbol in &sequence {
+        let (value, len) = code(symbol as usize);
+        let symbol_values = symbols[symbol as usize].values;
+        if bits + len > 8 * block_bytes || in_block + symbol_values > BLOCK_VALUES {
+            end_block(&mut part, &mut block, in_block);
+            starts.push(start);
+            start += in_block;
+            (bits, in_block) = (0, 0);
+        }
+        for bit in (0..len).rev() {
+            if (value >> bit) & 1 == 1 {
+                block[bits / 8] |= 0x80 >> (bits % 8);
+            }
+            bits += 1;
+        }
+        in_block += symbol_values;
+    }
+    end_block(&mut part, &mut block, in_block);
+    starts.push(start);
+
+    // Each entry points at the middle of its span of values.
+    let span = 1 << SPAN;
+    for chunk in 0..values.len().div_ceil(span) {
+        let target = chunk * span + span / 2;
+        let block = starts.partition_point(|&start| start <= target) - 1;
+        part.sparse_index.extend((block as u32).to_le_bytes());
+        part.sparse_index.extend(((target - starts[block]) as u16).to_le_bytes());
+    }
+
+    part.sizes = vec![flags, BLOCK_SIZE, SPAN, 0];
+    part.sizes.extend((starts.len() as u32).to_le_bytes());
+    part.sizes.extend([max_len as u8, min_len as u8]);
+    for &lowest in &lowest[min_len..=max_len] {
+        part.sizes.extend((lowest as u16).to_le_bytes());
+    }
+    part.sizes.extend((symbols.len() as u16).to_le_bytes());
+    for &symbol in &order {
+        let Symbol { left, right, .. } = symbols[symbol];
+        let (left, right) = if right == LEAF {
+            (left, LEAF)
+        } else {
+            (number[left as usize] as u16, number[right as usize] as u16)
+        };
+        part.sizes.extend([left as u8, (left >> 8) as u8 | ((right & 0xF) << 4) as u8, (right >> 4) as u8]);
+    }
+    if symbols.len() % 2 == 1 {
+        part.sizes.push(0);
+    }
+    Ok(part)
+}
+
+/// Replaces the most frequent pair of neighbouring symbols with a new one
+/// for as long as that pays, starting from one symbol per value.
+fn pair(values: &[u16]) -> (Vec<Symbol>, Vec<u16>) {
+    let mut symbols = Vec::new();
+    let mut leaves = HashMap::new();
+    let mut sequence: Vec<u16> = values
+        .iter()
+        .map(|&value| {
+            *leaves.entry(value).or_insert_with(|| {
+                symbols.push(Symbol {
+                    left: value,
+                    right: LEAF,
+                    values: 1,
+                });
+                (symbols.len() - 1) as u16
+            })
+        })
+        .collect();
+    while symbols.len() < MAX_SYMBOLS {
+        let mut counts: HashMap<(u16, u16), usize> = HashMap::new();
+        let mut previous = None;
+        for window in sequence.windows(2) {
+            let pair = (window[0], window[1]);
+            // Of a run like `aaa` only one `aa` can be replaced.
+            if pair.0 == pair.1 && previous == Some(pair) {
+                previous = None;
+                continue;
+            }
+            previous = Some(pair);
+            // A symbol's length is stored in a byte.
+            if symbols[pair.0 as usize].values + symbols[pair.1 as usize].values <= 256 {
+                *counts.entry(pair).or_default() += 1;
+            }
+        }
+        let Some((pair, count)) = counts.into_iter().max_by_key(|&(pair, count)| (count, Reverse(pair))) else {
+            break;
+        };
+        if count < MIN_PAIRS {
+            break;
+        }
+        let symbol = symbols.len() as u16;
+        symbols.push(Symbol {
+            left: pair.0,
+            right: pair.1,
+            values: symbols[pair.0 as usize].values + symbols[pair.1 as usize].values,
+        });
+        let mut paired = Vec::with_capacity(sequence.len());
+        let mut i = 0;
+        while i < sequence.len() {
+            if i + 1 < sequence.len() && (sequence[i], sequence[i + 1]) == pair {
+                paired.push(symbol);
+                i += 2;
+            } else {
+                paired.push(sequence[i]);
+                i += 1;
+            }
+        }
+        sequence = paired;
+    }
+    (symbols, sequence)
+}
+
+/// Huffman code lengths by symbol, `None` for symbols only used inside
+/// others.
+fn code_lengths(symbol_count: usize, sequence: &[u16]) -> Vec<Option<usize>> {
+    let mut frequency = vec![0u64; symbol_count];
+    for &symbol in sequence {
+        frequency[symbol as usize] += 1;
+    }
+    let used: Vec<usize> = (0..symbol_count).filter(|&symbol| frequency[symbol] > 0).collect();
+    let mut lengths = vec![None; symbol_count];
+    if used.len() == 1 {
+        // A code needs two symbols; the second is never written.
+        lengths[used[0]] = Some(1);
+        lengths[(used[0] + 1) % symbol_count] = Some(1);
+        return lengths;
+    }
+    // Nodes are the used symbols, then the merged pairs of nodes.
+    let mut heap: BinaryHeap<Reverse<(u64, usize)>> =
+        used.iter().enumerate().map(|(node, &symbol)| Reverse((frequency[symbol], node))).collect();
+    let mut parent = vec![0; 2 * used.len() - 1];
+    let mut next = used.len();
+    while let (Some(Reverse((a, left))), Some(Reverse((b, right)))) = (heap.pop(), heap.pop()) {
+        parent[left] = next;
+        parent[right] = next;
+        heap.push(Reverse((a + b, next)));
+        next += 1;
+    }
+    let mut depth = vec![0; parent.len()];
+    for node in (0..parent.len() - 1).rev() {
+        depth[node] = depth[parent[node]] + 1;
+    }
+    for (node, &symbol) in used.iter().enumerate() {
+        lengths[symbol] = Some(depth[node]);
+    }
+    lengths
+}
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+
+use macroquad::prelude::*;
+
+use crate::storage;
+use chess_wasm::{ChessColor, Piece, TypePiece};
+
+pub struct BoardPalette {
+    pub name: &'static str,
+    pub light: Color,
+    pub dark: Color,
+    pub highlight: Color,
+}
+
+pub const BOARD_PALETTES: &[BoardPalette] = &[
+    BoardPalette {
+        name: "Classic",
+        light: WHITE,
+        dark: GRAY,
+        highlight: YELLOW,
+    },
+    BoardPalette {
+        name: "Wood",
+        light: Color::new(0.94, 0.85, 0.71, 1.0),
+        dark: Color::new(0.71, 0.53, 0.39, 1.0),
+        highlight: Color::new(0.80, 0.20, 0.20, 1.0),
+    },
+    BoardPalette {
+        name: "Green",
+        light: Color::new(0.93, 0.93, 0.82, 1.0),
+        dark: Color::new(0.46, 0.59, 0.34, 1.0),
+        highlight: Color::new(0.97, 0.85, 0.25, 1.0),
+    },
+    BoardPalette {
+        name: "Blue",
+        light: Color::new(0.87, 0.89, 0.90, 1.0),
+        dark: Color::new(0.55, 0.64, 0.72, 1.0),
+        highlight: Color::new(0.95, 0.60, 0.15, 1.0),
+    },
+];
+
+/// Directories holding a full piece set named `<color>-<piece>.png`,
+/// e.g. `newimg/white-pawn.png`.
+pub const PIECE_SETS: &[&str] = &["newimg", "glyphs"];
+
+const PALETTE_KEY: &str = "board_palette";
+const PIECE_SET_KEY: &str = "piece_set";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Theme {
+    palette: usize,
+    piece_set: usize,
+}
+
+impl Theme {
+    /// Restores the theme chosen in a previous session, falling back to the
+    /// defaults for anything missing or unknown.
+    pub fn load() -> Self {
+        let palette = storage::load(PALETTE_KEY)
+            .and_then(|name| BOARD_PALETTES.iter().position(|p| p.name == name))
+            .unwrap_or(0);
+        let piece_set = storage::load(PIECE_SET_KEY)
+            .and_then(|dir| PIECE_SETS.iter().position(|set| *set == dir))
+            .unwrap_or(0);
+        Theme { palette, piece_set }
+    }
+
+    pub fn save(&self) {
+        storage::save(PALETTE_KEY, self.palette().name);
+        storage::save(PIECE_SET_KEY, self.piece_set_dir());
+    }
+
+    pub fn palette(&self) -> &'static BoardPalette {
+        &BOARD_PALETTES[self.palette]
+    }
+
+    pub fn piece_set_dir(&self) -> &'static str {
+        PIECE_SETS[self.piece_set]
+    }
+
+    pub fn next_palette(&mut self) {
+        self.palette = (self.palette + 1) % BOARD_PALETTES.len();
+        self.save();
+    }
+
+    pub fn next_piece_set(&mut self) {
+        self.piece_set = (self.piece_set + 1) % PIECE_SETS.len();
+        self.save();
+    }
+}
+
+pub async fn load_piece_set(dir: &str) -> HashMap<Piece, Texture2D> {
+    let mut textures = HashMap::new();
+
+    for (color, color_name) in [(ChessColor::White, "white"), (ChessColor::Black, "black")] {
+        for (type_of_piece, piece_name) in [
+            (TypePiece::Pawn, "pawn"),
+            (TypePiece::Rook, "rook"),
+            (TypePiece::Bishop, "bishop"),
+            (TypePiece::Queen, "queen"),
+            (TypePiece::Knight, "knight"),
+            (TypePiece::King, "king"),
+        ] {
+            let path = format!("{}/{}-{}.png", dir, color_name, piece_name);
+            let texture = load_texture(&path).await.unwrap();
+            textures.insert(
+                Piece {
+                    type_of_piece,
+                    color,
+                },
+                texture,
+            );
+        }
+    }
+    textures
+}
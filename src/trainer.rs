@@ -0,0 +1,133 @@
+//! Endgame trainer: a won KQK, KRK or KPK position to convert with White
+//! against a defence that always holds out longest, with every move
+//! checked against the solved endgames.
+
+use chess_wasm::endgame::{self, Ending, Outcome};
+use chess_wasm::ChessColor;
+use macroquad::miniquad::date;
+use macroquad::prelude::*;
+use macroquad::rand;
+
+use crate::layout::PANEL_TEXT;
+use crate::App;
+
+/// Starting positions are at least this many plies from mate.
+const MIN_PLIES: u32 = 12;
+/// Seconds before the defence answers, so the player's move can be seen.
+const REPLY_DELAY: f64 = 0.6;
+
+pub struct Trainer {
+    pub active: bool,
+    ending: Ending,
+    /// Hash of the position on the board when last looked at.
+    position: u64,
+    /// When that position came up.
+    since: f64,
+    /// What the player could get with best play before their last move.
+    best: Option<Outcome>,
+    /// The player's result with best play the last time they had the move.
+    current: Option<Outcome>,
+    verdict: Option<(String, Color)>,
+}
+
+impl Default for Trainer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Trainer {
+    pub fn new() -> Self {
+        rand::srand(date::now() as u64);
+        Trainer {
+            active: false,
+            ending: Ending::Kpk,
+            position: 0,
+            since: 0.0,
+            best: None,
+            current: None,
+            verdict: None,
+        }
+    }
+
+    /// Sets up a position of the next ending on the board.
+    pub fn start(&mut self, app: &mut App) {
+        let next = Ending::ALL.iter().position(|&ending| ending == self.ending).map_or(0, |i| i + 1);
+        self.ending = Ending::ALL[next % Ending::ALL.len()];
+        let Some(grid) = endgame::random_win(self.ending, MIN_PLIES, rand::rand()) else {
+            return;
+        };
+        app.start_from(grid);
+        self.active = true;
+        self.position = 0;
+        self.best = None;
+        self.current = None;
+        self.verdict = None;
+    }
+
+    /// Judges the player's moves and answers them, until the game ends.
+    pub fn update(&mut self, app: &mut App) {
+        if !self.active {
+            return;
+        }
+        let grid = app.grid();
+        let Some(outcome) = endgame::probe(grid) else {
+            // The game is over and the board back to the start.
+            self.active = false;
+            return;
+        };
+        let player_to_move = grid.side_to_move() == ChessColor::White;
+        if grid.position_hash() != self.position {
+            self.position = grid.position_hash();
+            self.since = date::now();
+            if player_to_move {
+                self.best = Some(outcome);
+                self.current = Some(outcome);
+            } else if let Some(best) = self.best.take() {
+                self.verdict = Some(verdict(best, outcome));
+            }
+        }
+        if player_to_move || date::now() - self.since < REPLY_DELAY {
+            return;
+        }
+        let replies = endgame::best_moves(grid).unwrap_or_default();
+        if !replies.is_empty() {
+            let reply = replies[rand::rand() as usize % replies.len()];
+            app.play(reply);
+        }
+    }
+
+    /// The ending, the result with best play and how the last move did,
+    /// inside the engine panel's content area.
+    pub fn draw(&self, panel: Rect) {
+        let line_height = 18.0;
+        let goal = match self.ending {
+            Ending::Kpk => "promote and mate",
+            _ => "mate the lone king",
+        };
+        let text = format!("{}: {}", self.ending.name(), goal);
+        draw_text(&text, panel.x, panel.y + line_height, 18.0, PANEL_TEXT);
+        let status = match self.current.and_then(Outcome::moves_to_mate) {
+            Some(moves) => format!("Best play mates in {}", moves),
+            None => "Drawn with best play".to_string(),
+        };
+        draw_text(&status, panel.x, panel.y + line_height * 2.0, 16.0, PANEL_TEXT);
+        if let Some((text, color)) = &self.verdict {
+            draw_text(text, panel.x, panel.y + line_height * 3.0, 16.0, *color);
+        }
+        draw_text("G: next ending", panel.x, panel.y + line_height * 4.0, 16.0, PANEL_TEXT);
+    }
+}
+
+/// How the player's move did, from what they had before it and what the
+/// defence has after it.
+fn verdict(before: Outcome, after: Outcome) -> (String, Color) {
+    match (before, after) {
+        (Outcome::Win(best), Outcome::Loss(plies)) if plies + 1 == best => ("Best move".to_string(), GREEN),
+        (Outcome::Win(best), Outcome::Loss(plies)) => {
+            (format!("Mate in {} now, {} was possible", plies / 2, (best - 1) / 2), YELLOW)
+        }
+        (Outcome::Win(_), _) => ("The win is gone".to_string(), RED),
+        _ => ("Still a draw".to_string(), RED),
+    }
+}
@@ -0,0 +1,174 @@
+//! Transposition table shared by the searches.
+//!
+//! A fixed-size array indexed by the low bits of the position hash. Each
+//! slot keeps one entry; deeper results win over shallower ones, except
+//! that anything left over from a previous search can always be replaced.
+
+use crate::Move;
+
+/// Default size, small enough for the browser build.
+pub const DEFAULT_TT_MB: usize = 16;
+
+/// Upper bound on the table size. Wasm memory is limited to a few GB and
+/// browsers often refuse much less, so the wasm build stays well below.
+#[cfg(target_arch = "wasm32")]
+pub const MAX_TT_MB: usize = 256;
+#[cfg(not(target_arch = "wasm32"))]
+pub const MAX_TT_MB: usize = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    /// The score is exact.
+    Exact,
+    /// The search failed high: the real score is at least this.
+    Lower,
+    /// The search failed low: the real score is at most this.
+    Upper,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TtEntry {
+    pub key: u64,
+    pub best_move: Option<Move>,
+    pub score: i32,
+    pub depth: u8,
+    pub bound: Bound,
+    generation: u8,
+}
+
+pub struct TranspositionTable {
+    entries: Vec<Option<TtEntry>>,
+    generation: u8,
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        Self::new(DEFAULT_TT_MB)
+    }
+}
+
+/// Number of entries fitting in `megabytes`, kept within `MAX_TT_MB` and
+/// rounded down to a power of two.
+fn entries_for(megabytes: usize) -> usize {
+    let bytes = megabytes.clamp(1, MAX_TT_MB) * 1024 * 1024;
+    let fits = bytes / std::mem::size_of::<Option<TtEntry>>();
+    if fits.is_power_of_two() {
+        fits
+    } else {
+        fits.next_power_of_two() / 2
+    }
+}
+
+impl TranspositionTable {
+    /// Creates a table using at most `megabytes` of memory, rounded down to
+    /// a power of two number of entries.
+    pub fn new(megabytes: usize) -> Self {
+        TranspositionTable {
+            entries: vec![None; entries_for(megabytes)],
+            generation: 0,
+        }
+    }
+
+    pub fn resize(&mut self, megabytes: usize) {
+        *self = Self::new(megabytes);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.iter_mut().for_each(|entry| *entry = None);
+        self.generation = 0;
+    }
+
+    /// Marks everything stored so far as belonging to an older search.
+    pub fn new_search(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    fn index(&self, key: u64) -> usize {
+        (key as usize) & (self.entries.len() - 1)
+    }
+
+    pub fn probe(&self, key: u64) -> Option<TtEntry> {
+        self.entries[self.index(key)].filter(|entry| entry.key == key)
+    }
+
+    pub fn store(&mut self, key: u64, depth: u32, score: i32, bound: Bound, best_move: Option<Move>) {
+        let index = self.index(key);
+        let depth = depth.min(u8::MAX as u32) as u8;
+        let replace = match self.entries[index] {
+            None => true,
+            Some(old) => {
+                old.key == key || old.generation != self.generation || depth >= old.depth
+            }
+        };
+        if !replace {
+            return;
+        }
+        // Keep the old move when a shallower pass of the same position had none.
+        let best_move = best_move.or_else(|| {
+            self.entries[index]
+                .filter(|old| old.key == key)
+                .and_then(|old| old.best_move)
+        });
+        self.entries[index] = Some(TtEntry {
+            key,
+            best_move,
+            score,
+            depth,
+            bound,
+            generation: self.generation,
+        });
+    }
+
+    /// Permille of the first thousand slots filled by the current search,
+    /// as reported by UCI's `hashfull`.
+    pub fn hashfull(&self) -> u32 {
+        let sample = self.entries.len().min(1000);
+        let used = self.entries[..sample]
+            .iter()
+            .filter(|entry| entry.is_some_and(|entry| entry.generation == self.generation))
+            .count();
+        (used * 1000 / sample) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes() {
+        let entry = std::mem::size_of::<Option<TtEntry>>();
+        for megabytes in [0, 1, 3, DEFAULT_TT_MB, 100, MAX_TT_MB, MAX_TT_MB + 1, usize::MAX / (1 << 20)] {
+            let len = entries_for(megabytes);
+            assert!(len.is_power_of_two(), "{} MB", megabytes);
+            assert!(len * entry <= megabytes.clamp(1, MAX_TT_MB) << 20, "{} MB", megabytes);
+            // Rounding down never throws away half the memory or more.
+            assert!(len * entry * 2 > megabytes.clamp(1, MAX_TT_MB) << 20, "{} MB", megabytes);
+        }
+        assert_eq!(entries_for(MAX_TT_MB * 2), entries_for(MAX_TT_MB));
+    }
+
+    #[test]
+    fn replacement() {
+        let mut tt = TranspositionTable::new(1);
+        // Two keys sharing a slot.
+        let (deep, shallow) = (5, 5 + tt.entries.len() as u64);
+        tt.store(deep, 8, 10, Bound::Exact, None);
+        tt.store(shallow, 3, 20, Bound::Exact, None);
+        assert_eq!(tt.probe(deep).map(|entry| entry.depth), Some(8));
+        assert!(tt.probe(shallow).is_none());
+        // As deep is enough, and the same position is always updated.
+        tt.store(shallow, 8, 20, Bound::Lower, None);
+        assert_eq!(tt.probe(shallow).map(|entry| entry.score), Some(20));
+        tt.store(shallow, 1, 30, Bound::Upper, None);
+        assert_eq!(tt.probe(shallow).map(|entry| (entry.depth, entry.score)), Some((1, 30)));
+
+        // An entry from an earlier search gives way to anything.
+        tt.store(deep, 12, 10, Bound::Exact, None);
+        tt.new_search();
+        tt.store(shallow, 1, 40, Bound::Exact, None);
+        assert!(tt.probe(deep).is_none());
+        assert_eq!(tt.probe(shallow).map(|entry| entry.score), Some(40));
+        assert_eq!(tt.hashfull(), 1);
+    }
+}
@@ -0,0 +1,231 @@
+//! Lets an external UCI engine, run through `chess_wasm::uci_engine`, play
+//! one side or analyse the game. Native builds only.
+
+use chess_wasm::search::PvLine;
+use chess_wasm::uci_engine::{EngineEvent, EngineInfo, Score, UciEngine};
+use chess_wasm::{ChessColor, Grid};
+use macroquad::prelude::*;
+
+use crate::analysis::{mate_score, Analysis, LINES};
+use crate::layout::PANEL_TEXT;
+use crate::storage;
+use crate::App;
+
+const ENGINE_KEY: &str = "engine";
+/// Thinking time per move when the engine plays.
+const MOVE_TIME_MS: u32 = 1000;
+
+/// The line of `info` in `grid`, as far as its moves are legal.
+fn engine_line(info: &EngineInfo, grid: &Grid) -> PvLine {
+    let score = match info.score {
+        Score::Centipawns(cp) => cp,
+        Score::Mate(moves) => mate_score(moves),
+    };
+    let mut position = grid.clone();
+    let mut moves = Vec::new();
+    for text in &info.pv {
+        let Some(mv) = position.parse_uci_move(text) else {
+            break;
+        };
+        position.make_move(mv);
+        moves.push(mv);
+    }
+    PvLine { score, moves }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Role {
+    Opponent(ChessColor),
+    Analyser,
+}
+
+/// What the GUI uses the engine for, and the searches it has running.
+pub struct EngineSession {
+    /// Engine program followed by its arguments.
+    command: Option<Vec<String>>,
+    engine: Option<UciEngine>,
+    role: Option<Role>,
+    /// Position of the last `go`.
+    searching: Option<String>,
+    /// `go` commands still waiting for their `bestmove`.
+    pending: usize,
+    /// Last `UCI_Chess960` value sent.
+    chess960: bool,
+    status: String,
+}
+
+impl EngineSession {
+    /// Uses the engine given as `--engine <path> [args...]`, or the one
+    /// saved before.
+    pub fn from_args() -> Self {
+        let args: Vec<String> = std::env::args().skip_while(|arg| arg != "--engine").skip(1).collect();
+        let command = if args.is_empty() {
+            storage::load(ENGINE_KEY).map(|saved| saved.split_whitespace().map(str::to_string).collect())
+        } else {
+            storage::save(ENGINE_KEY, &args.join(" "));
+            Some(args)
+        };
+        EngineSession {
+            command,
+            engine: None,
+            role: None,
+            searching: None,
+            pending: 0,
+            chess960: false,
+            status: String::new(),
+        }
+    }
+
+    /// Lets the engine play the side to move, or takes it back.
+    pub fn toggle_opponent(&mut self, app: &App) {
+        match self.role {
+            Some(Role::Opponent(_)) => self.set_role(None),
+            _ => self.set_role(Some(Role::Opponent(app.side_to_move()))),
+        }
+    }
+
+    /// Whether an engine has been given to use.
+    pub fn has_engine(&self) -> bool {
+        self.command.is_some()
+    }
+
+    pub fn is_analysing(&self) -> bool {
+        self.role == Some(Role::Analyser)
+    }
+
+    /// Starts or ends analysis by the engine.
+    pub fn set_analysing(&mut self, analysing: bool) {
+        if analysing {
+            self.set_role(Some(Role::Analyser));
+        } else if self.is_analysing() {
+            self.set_role(None);
+        }
+    }
+
+    fn set_role(&mut self, role: Option<Role>) {
+        self.stop();
+        self.role = role;
+        if role.is_none() {
+            self.status.clear();
+            return;
+        }
+        if self.engine.is_none() {
+            let Some(command) = self.command.clone() else {
+                self.status = "No engine: start with --engine <path>".to_string();
+                self.role = None;
+                return;
+            };
+            match UciEngine::spawn(&command) {
+                Ok(engine) => {
+                    self.engine = Some(engine);
+                    self.chess960 = false;
+                }
+                Err(error) => {
+                    self.status = error;
+                    self.role = None;
+                    return;
+                }
+            }
+        }
+        self.status.clear();
+        let lines = if role == Some(Role::Analyser) { LINES } else { 1 };
+        self.send(&format!("setoption name MultiPV value {}", lines));
+    }
+
+    fn stop(&mut self) {
+        if self.searching.take().is_some() {
+            self.send("stop");
+        }
+    }
+
+    fn send(&mut self, command: &str) {
+        let Some(engine) = &mut self.engine else {
+            return;
+        };
+        if let Err(error) = engine.send(command) {
+            self.status = error;
+            self.engine = None;
+            self.role = None;
+        }
+    }
+
+    fn go(&mut self, position: String, chess960: bool, limit: &str) {
+        if chess960 != self.chess960 {
+            self.send(&format!("setoption name UCI_Chess960 value {}", chess960));
+            self.chess960 = chess960;
+        }
+        self.send(&position);
+        self.send(&format!("go {}", limit));
+        self.searching = Some(position);
+        self.pending += 1;
+    }
+
+    /// Handles what the engine said and starts searches the game needs.
+    pub fn update(&mut self, app: &mut App, analysis: &mut Analysis) {
+        let position = app.uci_position();
+        while let Some(event) = self.engine.as_mut().and_then(UciEngine::poll) {
+            match event {
+                EngineEvent::Info(info) => {
+                    if self.is_analysing() && self.searching.as_ref() == Some(&position) {
+                        let line = engine_line(&info, app.grid());
+                        analysis.set_line(app.grid(), info.multipv.saturating_sub(1) as usize, info.depth, line);
+                    }
+                }
+                EngineEvent::BestMove(best_move) => {
+                    self.pending = self.pending.saturating_sub(1);
+                    let current = self.pending == 0 && self.searching.as_ref() == Some(&position);
+                    if current && matches!(self.role, Some(Role::Opponent(_))) {
+                        self.searching = None;
+                        if !app.play_uci_move(&best_move) {
+                            self.status = format!("Engine played illegal move {}", best_move);
+                            self.role = None;
+                        }
+                        return;
+                    }
+                }
+                EngineEvent::Exited => {
+                    self.status = "Engine exited".to_string();
+                    self.engine = None;
+                    self.role = None;
+                    self.searching = None;
+                    self.pending = 0;
+                    return;
+                }
+            }
+        }
+
+        // The game moved on (a move, an undo or a new game).
+        if self.searching.is_some() && self.searching.as_ref() != Some(&position) {
+            self.stop();
+        }
+        if self.searching.is_some() {
+            return;
+        }
+        match self.role {
+            Some(Role::Opponent(color)) if app.side_to_move() == color => {
+                self.go(position, app.grid().chess960(), &format!("movetime {}", MOVE_TIME_MS));
+            }
+            Some(Role::Analyser) => self.go(position, app.grid().chess960(), "infinite"),
+            _ => {}
+        }
+    }
+
+    /// One status line at the bottom of the engine panel.
+    pub fn draw(&self, panel: Rect) {
+        let text = match self.role {
+            None => self.status.clone(),
+            Some(Role::Opponent(color)) if self.searching.is_some() => {
+                format!("{} thinking for {:?}", self.engine_name(), color)
+            }
+            Some(Role::Opponent(color)) => format!("{} plays {:?}", self.engine_name(), color),
+            Some(Role::Analyser) => format!("{} analysing", self.engine_name()),
+        };
+        if !text.is_empty() {
+            draw_text(&text, panel.x + 6.0, panel.bottom() - 6.0, 16.0, PANEL_TEXT);
+        }
+    }
+
+    fn engine_name(&self) -> &str {
+        self.engine.as_ref().map_or("Engine", |engine| engine.name.as_str())
+    }
+}
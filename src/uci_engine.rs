@@ -0,0 +1,204 @@
+//! The GUI's side of the UCI protocol: runs an engine as a child process
+//! and reads what it says. Native builds only.
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long the engine gets to answer `uci` and `isready`.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Score {
+    Centipawns(i32),
+    /// Moves to mate, negative when the engine is getting mated.
+    Mate(i32),
+}
+
+/// The useful part of an `info` line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EngineInfo {
+    pub depth: u32,
+    pub multipv: u32,
+    /// From the point of view of the side to move.
+    pub score: Score,
+    pub pv: Vec<String>,
+}
+
+impl EngineInfo {
+    /// Parses `info` lines that carry a score, ignoring the rest.
+    pub fn parse(line: &str) -> Option<EngineInfo> {
+        let mut tokens = line.split_whitespace();
+        if tokens.next() != Some("info") {
+            return None;
+        }
+        let mut info = EngineInfo {
+            depth: 0,
+            multipv: 1,
+            score: Score::Centipawns(0),
+            pv: Vec::new(),
+        };
+        let mut has_score = false;
+        while let Some(token) = tokens.next() {
+            match token {
+                "depth" => info.depth = tokens.next()?.parse().ok()?,
+                "multipv" => info.multipv = tokens.next()?.parse().ok()?,
+                "score" => {
+                    let kind = tokens.next()?;
+                    let value = tokens.next()?.parse().ok()?;
+                    info.score = match kind {
+                        "cp" => Score::Centipawns(value),
+                        "mate" => Score::Mate(value),
+                        _ => return None,
+                    };
+                    has_score = true;
+                }
+                "pv" => {
+                    info.pv = tokens.by_ref().map(str::to_string).collect();
+                }
+                // Anything after `string` is free text.
+                "string" => return None,
+                _ => {}
+            }
+        }
+        has_score.then_some(info)
+    }
+}
+
+pub enum EngineEvent {
+    Info(EngineInfo),
+    BestMove(String),
+    Exited,
+}
+
+pub struct UciEngine {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+    pub name: String,
+}
+
+impl UciEngine {
+    /// Starts the engine and waits for it to finish the UCI handshake.
+    pub fn spawn(command: &[String]) -> Result<Self, String> {
+        let (path, args) = command.split_first().ok_or("no engine given")?;
+        let mut child = Command::new(path)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|error| format!("cannot start {}: {}", path, error))?;
+        let stdin = child.stdin.take().ok_or("engine has no stdin")?;
+        let stdout = child.stdout.take().ok_or("engine has no stdout")?;
+
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut engine = UciEngine {
+            child,
+            stdin,
+            lines,
+            name: path.to_string(),
+        };
+        engine.send("uci")?;
+        engine.wait_for("uciok")?;
+        engine.send("isready")?;
+        engine.wait_for("readyok")?;
+        Ok(engine)
+    }
+
+    pub fn send(&mut self, command: &str) -> Result<(), String> {
+        writeln!(self.stdin, "{}", command)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|error| format!("engine stopped: {}", error))
+    }
+
+    fn wait_for(&mut self, expected: &str) -> Result<(), String> {
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match self.lines.recv_timeout(timeout) {
+                Ok(line) if line.trim() == expected => return Ok(()),
+                Ok(line) => {
+                    if let Some(name) = line.strip_prefix("id name ") {
+                        self.name = name.trim().to_string();
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => return Err(format!("engine did not answer {}", expected)),
+                Err(RecvTimeoutError::Disconnected) => return Err("engine exited".to_string()),
+            }
+        }
+    }
+
+    /// Next thing the engine said, without blocking.
+    pub fn poll(&mut self) -> Option<EngineEvent> {
+        loop {
+            let line = match self.lines.try_recv() {
+                Ok(line) => line,
+                Err(mpsc::TryRecvError::Empty) => return None,
+                Err(mpsc::TryRecvError::Disconnected) => return Some(EngineEvent::Exited),
+            };
+            if let Some(best_move) = line.strip_prefix("bestmove ") {
+                let best_move = best_move.split_whitespace().next().unwrap_or("0000");
+                return Some(EngineEvent::BestMove(best_move.to_string()));
+            }
+            if let Some(info) = EngineInfo::parse(&line) {
+                return Some(EngineEvent::Info(info));
+            }
+        }
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        // Give it a moment to leave on its own before killing it.
+        for _ in 0..20 {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_scores_and_lines() {
+        let info = EngineInfo::parse("info depth 12 seldepth 18 multipv 2 score cp -35 nodes 4000 pv e2e4 e7e5").unwrap();
+        assert_eq!(info.depth, 12);
+        assert_eq!(info.multipv, 2);
+        assert_eq!(info.score, Score::Centipawns(-35));
+        assert_eq!(info.pv, ["e2e4", "e7e5"]);
+
+        let info = EngineInfo::parse("info depth 5 score mate -3 pv h7h8q").unwrap();
+        assert_eq!(info.multipv, 1);
+        assert_eq!(info.score, Score::Mate(-3));
+        assert_eq!(info.pv, ["h7h8q"]);
+    }
+
+    #[test]
+    fn skips_lines_without_a_score() {
+        assert_eq!(EngineInfo::parse("info depth 3 nodes 100 nps 1000"), None);
+        assert_eq!(EngineInfo::parse("info string score cp 20"), None);
+        assert_eq!(EngineInfo::parse("bestmove e2e4"), None);
+        assert_eq!(EngineInfo::parse("info depth 3 score wdl 1 2 3"), None);
+    }
+}
@@ -0,0 +1,90 @@
+//! Zobrist keys used to fingerprint positions.
+//!
+//! The keys are generated at compile time from a fixed seed, so a given
+//! position hashes to the same value on every build and platform.
+
+use crate::{ChessColor, Piece, TypePiece};
+
+pub struct Keys {
+    pieces: [[u64; 64]; 12],
+    side: u64,
+    castling: [u64; 4],
+    en_passant: [u64; 8],
+}
+
+const fn next(state: &mut u64) -> u64 {
+    // xorshift64*
+    *state ^= *state >> 12;
+    *state ^= *state << 25;
+    *state ^= *state >> 27;
+    state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+}
+
+const fn generate() -> Keys {
+    let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+    let mut pieces = [[0u64; 64]; 12];
+    let mut piece = 0;
+    while piece < 12 {
+        let mut square = 0;
+        while square < 64 {
+            pieces[piece][square] = next(&mut state);
+            square += 1;
+        }
+        piece += 1;
+    }
+    let side = next(&mut state);
+    let mut castling = [0u64; 4];
+    let mut i = 0;
+    while i < 4 {
+        castling[i] = next(&mut state);
+        i += 1;
+    }
+    let mut en_passant = [0u64; 8];
+    let mut file = 0;
+    while file < 8 {
+        en_passant[file] = next(&mut state);
+        file += 1;
+    }
+    Keys {
+        pieces,
+        side,
+        castling,
+        en_passant,
+    }
+}
+
+pub static KEYS: Keys = generate();
+
+fn piece_index(piece: Piece) -> usize {
+    let type_index = match piece.type_of_piece {
+        TypePiece::Pawn => 0,
+        TypePiece::Knight => 1,
+        TypePiece::Bishop => 2,
+        TypePiece::Rook => 3,
+        TypePiece::Queen => 4,
+        TypePiece::King => 5,
+    };
+    match piece.color {
+        ChessColor::White => type_index,
+        ChessColor::Black => type_index + 6,
+    }
+}
+
+pub fn piece_key(piece: Piece, position: [usize; 2]) -> u64 {
+    KEYS.pieces[piece_index(piece)][position[1] * 8 + position[0]]
+}
+
+pub fn side_key() -> u64 {
+    KEYS.side
+}
+
+/// Combined key for a set of castling rights, one key per right.
+pub fn castling_key(rights: u8) -> u64 {
+    (0..4)
+        .filter(|bit| rights & (1 << bit) != 0)
+        .fold(0, |key, bit| key ^ KEYS.castling[bit])
+}
+
+pub fn en_passant_key(file: usize) -> u64 {
+    KEYS.en_passant[file]
+}
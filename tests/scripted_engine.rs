@@ -0,0 +1,66 @@
+//! Runs `scripted_engine` through the GUI's UCI client.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chess_wasm::uci_engine::{EngineEvent, UciEngine};
+
+/// Polls `engine` until it says something other than `info`, or gives up.
+fn next_event(engine: &mut UciEngine, infos: &mut Vec<Vec<String>>) -> Option<EngineEvent> {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while Instant::now() < deadline {
+        match engine.poll() {
+            Some(EngineEvent::Info(info)) => infos.push(info.pv),
+            Some(event) => return Some(event),
+            None => thread::sleep(Duration::from_millis(5)),
+        }
+    }
+    None
+}
+
+fn spawn(script: &[&str]) -> UciEngine {
+    let mut command = vec![env!("CARGO_BIN_EXE_scripted_engine").to_string()];
+    command.extend(script.iter().map(|text| text.to_string()));
+    UciEngine::spawn(&command).unwrap()
+}
+
+#[test]
+fn handshake_reads_the_name() {
+    assert_eq!(spawn(&[]).name, "Scripted");
+}
+
+#[test]
+fn plays_the_scripted_moves() {
+    let mut engine = spawn(&["e7e5", "g8f6"]);
+    let mut infos = Vec::new();
+
+    engine.send("position startpos moves e2e4").unwrap();
+    engine.send("go movetime 10").unwrap();
+    assert!(matches!(next_event(&mut engine, &mut infos), Some(EngineEvent::BestMove(mv)) if mv == "e7e5"));
+    assert_eq!(infos, [["e7e5"]]);
+
+    // An infinite search only answers on `stop`.
+    engine.send("position startpos moves e2e4 e7e5 g1f3").unwrap();
+    engine.send("go infinite").unwrap();
+    thread::sleep(Duration::from_millis(50));
+    assert!(engine.poll().is_some_and(|event| matches!(event, EngineEvent::Info(_))));
+    assert!(engine.poll().is_none());
+    engine.send("stop").unwrap();
+    assert!(matches!(next_event(&mut engine, &mut infos), Some(EngineEvent::BestMove(mv)) if mv == "g8f6"));
+}
+
+#[test]
+fn falls_back_to_a_legal_move() {
+    let mut engine = spawn(&["e2e5"]);
+    engine.send("position startpos").unwrap();
+    engine.send("go depth 1").unwrap();
+    let event = next_event(&mut engine, &mut Vec::new());
+    assert!(matches!(event, Some(EngineEvent::BestMove(mv)) if mv != "e2e5" && mv != "0000"));
+}
+
+#[test]
+fn notices_the_engine_leaving() {
+    let mut engine = spawn(&[]);
+    engine.send("quit").unwrap();
+    assert!(matches!(next_event(&mut engine, &mut Vec::new()), Some(EngineEvent::Exited)));
+}
@@ -0,0 +1,140 @@
+//! Checks the Syzygy prober against the endgames solved in memory, on the
+//! material both cover. `tests/syzygy` holds KQvK, KRvK and KPvK written
+//! from those solutions by `make_syzygy`, with DTZ in plies; the tables of
+//! the standard 3-4-5 set can be put there instead.
+
+use std::collections::HashSet;
+
+use chess_wasm::endgame::{self, Ending, Outcome};
+use chess_wasm::syzygy::{Tablebase, Wdl};
+use chess_wasm::{ChessColor, Grid, Move, Piece};
+
+const DIRECTORY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/syzygy");
+/// Only every this many placements is checked, to keep debug runs short.
+const SAMPLE: usize = 23;
+/// And only every this many of those positions has its best moves compared.
+const MOVES_SAMPLE: usize = 25;
+
+fn fen_square(board: &mut [[char; 8]; 8], square: usize, piece: char) {
+    board[7 - square / 8][square % 8] = piece;
+}
+
+/// A sample of the legal positions with White's king and `piece` against
+/// Black's king, either side to move.
+fn positions(piece: char) -> Vec<Grid> {
+    let mut grids = Vec::new();
+    for index in (0..64 * 64 * 64).step_by(SAMPLE) {
+        let (white_king, black_king, square) = (index / 4096, index / 64 % 64, index % 64);
+        if white_king == black_king || white_king == square || black_king == square {
+            continue;
+        }
+        if piece == 'P' && !(8..56).contains(&square) {
+            continue;
+        }
+        let mut board = [['1'; 8]; 8];
+        fen_square(&mut board, white_king, 'K');
+        fen_square(&mut board, black_king, 'k');
+        fen_square(&mut board, square, piece);
+        let rows: Vec<String> = board.iter().map(|row| row.iter().collect()).collect();
+        for side in ["w", "b"] {
+            let Ok(grid) = Grid::from_fen(&format!("{} {} - - 0 1", rows.join("/"), side)) else {
+                continue;
+            };
+            // The side that just moved can't be left in check.
+            let waiting = grid.side_to_move().opposite();
+            if !grid.is_square_attacked(grid.find_king_position(waiting), waiting) {
+                grids.push(grid);
+            }
+        }
+    }
+    grids
+}
+
+fn wdl(outcome: Outcome) -> Wdl {
+    match outcome {
+        Outcome::Win(_) => Wdl::Win,
+        Outcome::Draw => Wdl::Draw,
+        Outcome::Loss(_) => Wdl::Loss,
+    }
+}
+
+/// Result for the side to move after `mv`; captures and minor piece
+/// promotions leave a draw.
+fn wdl_after(grid: &Grid, mv: Move) -> Wdl {
+    let mut after = grid.clone();
+    after.make_move(mv);
+    endgame::probe(&after).map_or(Wdl::Draw, wdl)
+}
+
+fn check(ending: Ending) {
+    endgame::prepare(ending);
+    let tablebase = Tablebase::open(DIRECTORY).unwrap();
+    let piece = Piece {
+        type_of_piece: ending.piece(),
+        color: ChessColor::White,
+    }
+    .fen_char();
+    for (n, grid) in positions(piece).into_iter().enumerate() {
+        let fen = grid.to_fen();
+        let outcome = endgame::probe(&grid).unwrap();
+        let expected = wdl(outcome);
+        assert_eq!(tablebase.probe_wdl(&grid), Some(expected), "WDL of {}", fen);
+
+        if n % MOVES_SAMPLE == 0 && !grid.legal_moves().is_empty() {
+            let best = tablebase.best_moves(&grid).unwrap_or_else(|| panic!("no best moves in {}", fen));
+            for &mv in &best {
+                assert_eq!(-wdl_after(&grid, mv), expected, "{} in {}", mv.to_uci(), fen);
+            }
+            // Without pawns the quickest way to zero the count is the
+            // quickest mate.
+            if piece != 'P' {
+                let solved: HashSet<Move> = endgame::best_moves(&grid).unwrap().into_iter().collect();
+                assert_eq!(best.into_iter().collect::<HashSet<_>>(), solved, "best moves of {}", fen);
+            }
+        }
+
+        // Without a pawn the only way to zero the count is to mate, so DTZ
+        // is the distance to mate, give or take the ply the tables may round.
+        if piece == 'P' || outcome == Outcome::Loss(0) {
+            continue;
+        }
+        let dtz = tablebase.probe_dtz(&grid).unwrap();
+        match outcome {
+            Outcome::Win(plies) => assert!(dtz == plies as i32 || dtz == plies as i32 + 1, "DTZ {} of {}", dtz, fen),
+            Outcome::Loss(plies) => {
+                assert!(-dtz == plies as i32 || -dtz == plies as i32 + 1, "DTZ {} of {}", dtz, fen)
+            }
+            Outcome::Draw => assert_eq!(dtz, 0, "DTZ of {}", fen),
+        }
+    }
+}
+
+#[test]
+fn kqk_matches_solver() {
+    check(Ending::Kqk);
+}
+
+#[test]
+fn krk_matches_solver() {
+    check(Ending::Krk);
+}
+
+#[test]
+fn kpk_matches_solver() {
+    check(Ending::Kpk);
+}
+
+/// Black's side of a table is read by flipping the board.
+#[test]
+fn colours_are_mirrored() {
+    let tablebase = Tablebase::open(DIRECTORY).unwrap();
+    for (white, black) in [
+        ("8/8/8/4k3/8/8/8/K6Q w - - 0 1", "k6q/8/8/8/4K3/8/8/8 b - - 0 1"),
+        ("8/8/8/8/8/3k4/8/K2R4 b - - 0 1", "k2r4/8/3K4/8/8/8/8/8 w - - 0 1"),
+        ("8/8/8/8/8/2k5/P7/K7 w - - 0 1", "k7/p7/2K5/8/8/8/8/8 b - - 0 1"),
+    ] {
+        let (white, black) = (Grid::from_fen(white).unwrap(), Grid::from_fen(black).unwrap());
+        assert_eq!(tablebase.probe_wdl(&white), tablebase.probe_wdl(&black));
+        assert_eq!(tablebase.probe_dtz(&white), tablebase.probe_dtz(&black));
+    }
+}